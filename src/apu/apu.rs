@@ -1,5 +1,10 @@
-use crate::mmu::mmio::MMIO;
-use rodio::{buffer::SamplesBuffer, OutputStream, OutputStreamHandle, Sink};
+use crate::{
+    apu::audio_output::{AudioOutput, NullOutput},
+    mmu::mmio::MMIO,
+    save_state::{Snapshot, StateError, StateReader, StateWriter},
+};
+
+pub mod audio_output;
 
 // WAVE DUTY CYCLES
 const WAVE_DUTY_CYCLES: [[u8; 8]; 4] = [
@@ -52,7 +57,7 @@ impl Default for ChannelOne {
 impl ChannelOne {
     /// Frequency timer ticks every T-cycle.
     pub fn duty_cycle(&mut self) {
-        self.freq_timer -= 1;
+        self.freq_timer = self.freq_timer.wrapping_sub(1);
 
         if self.freq_timer == 0 {
             let freq = ((self.nr14 & 0b111) as u16) << 8 | self.nr13 as u16;
@@ -211,7 +216,7 @@ impl Default for ChannelTwo {
 impl ChannelTwo {
     /// Frequency timer ticks every T-cycle.
     pub fn duty_cycle(&mut self) {
-        self.freq_timer -= 1;
+        self.freq_timer = self.freq_timer.wrapping_sub(1);
 
         if self.freq_timer == 0 {
             let freq = ((self.nr24 & 0b111) as u16) << 8 | self.nr23 as u16;
@@ -334,7 +339,7 @@ impl Default for ChannelThree {
 impl ChannelThree {
     /// Frequency timer ticks every T-cycle.
     pub fn duty_cycle(&mut self) {
-        self.freq_timer -= 1;
+        self.freq_timer = self.freq_timer.wrapping_sub(1);
 
         if self.freq_timer == 0 {
             let freq = ((self.nr34 & 0b111) as u16) << 8 | self.nr33 as u16;
@@ -446,7 +451,7 @@ impl Default for ChannelFour {
 impl ChannelFour {
     /// Frequency timer ticks every T-cycle.
     pub fn duty_cycle(&mut self) {
-        self.freq_timer -= 1;
+        self.freq_timer = self.freq_timer.wrapping_sub(1);
 
         if self.freq_timer == 0 {
             let base_divisor = ((self.nr43 & 0b111) * 16).max(8) as u16;
@@ -584,10 +589,8 @@ pub struct APU {
     /// Buffer that holds the sound samples before being queued into the audio queue
     buffer: Vec<f32>,
 
-    /// Queue to append samples to, never stops playing
-    pub sink: Sink,
-    /// Rodio frontend streams to play sound
-    streams: (OutputStream, OutputStreamHandle),
+    /// Where the mixed samples end up, no audio device by default
+    pub output: Box<dyn AudioOutput>,
     /// Fast-forward the APU
     pub speed: bool,
     /// Frontend communication for enabling/disabling individual channels
@@ -598,9 +601,6 @@ pub struct APU {
 
 impl Default for APU {
     fn default() -> Self {
-        let streams = OutputStream::try_default().unwrap();
-        let sink = Sink::try_new(&streams.1).unwrap();
-
         Self {
             wave_ram: [0xFF; 0x10],
             internal_cycles: 0,
@@ -619,8 +619,7 @@ impl Default for APU {
 
             buffer: Vec::with_capacity(1024),
 
-            sink,
-            streams,
+            output: Box::new(NullOutput),
             speed: false,
             ch_enable: (true, true, true, true),
 
@@ -632,7 +631,7 @@ impl Default for APU {
 impl MMIO for APU {
     // Here, we mask on reads and not writes since write-only bits are very present
     // and should always read back as 1. Plus, it makes clearing easier.
-    fn read(&mut self, address: u16) -> u8 {
+    fn peek(&self, address: u16) -> u8 {
         match address {
             0xFF10 => self.ch1.nr10 | 0x80,
             0xFF11 => self.ch1.nr11 | 0x3F,
@@ -778,6 +777,11 @@ impl MMIO for APU {
 }
 
 impl APU {
+    /// Swaps in a new audio output and returns the previous one.
+    pub fn replace_output(&mut self, output: Box<dyn AudioOutput>) -> Box<dyn AudioOutput> {
+        std::mem::replace(&mut self.output, output)
+    }
+
     #[rustfmt::skip]
     pub fn tick(&mut self, div: u8) {
         self.ch1.duty_cycle();
@@ -796,7 +800,7 @@ impl APU {
         }
 
         // Magic number (cpu_freq / 44.1kHz or 48kHz) where cpu_freq ~ 4MHz
-        while self.internal_cycles < 87 * (4 * self.speed as u16 + 1) {
+        while self.internal_cycles >= 87 * (4 * self.speed as u16 + 1) {
             self.internal_cycles -= 87 * (4 * self.speed as u16 + 1);
 
             let ch1_sample = if self.is_ch1_enabled() && self.ch_enable.0 { self.ch1.sample() } else { 0.0 };
@@ -842,10 +846,9 @@ impl APU {
 
             self.buffer.extend([ls, rs]);
 
-            // len() of sink does not return amount of samples but amount of SamplesBuffer, hence 1 SamplesBuffer = 1024 sample
+            // Hand the samples to the output in batches of 1024
             if self.buffer.len() >= 1024 {
-                while self.sink.len() > 2 {}
-                self.sink.append(SamplesBuffer::new(2, 48000, self.buffer.clone()));
+                self.output.queue_samples(&self.buffer);
                 self.buffer.clear();
             }
         }
@@ -892,3 +895,137 @@ impl APU {
         self.nr52 & (1 << 3) != 0
     }
 }
+
+// -------- SAVE STATES --------
+
+impl Snapshot for ChannelOne {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&[self.volume, self.vol_timer, self.sweep_timer]);
+        writer.write_bytes(&[self.len_counter, self.duty_cycle]);
+        writer.write_u16(self.freq_timer);
+        writer.write_bytes(&[self.nr10, self.nr11, self.nr12, self.nr13, self.nr14]);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.volume = reader.read_u8()?;
+        self.vol_timer = reader.read_u8()?;
+        self.sweep_timer = reader.read_u8()?;
+        self.len_counter = reader.read_u8()?;
+        self.duty_cycle = reader.read_u8()?;
+        self.freq_timer = reader.read_u16()?;
+
+        let mut regs = [0u8; 5];
+        reader.read_into(&mut regs)?;
+        [self.nr10, self.nr11, self.nr12, self.nr13, self.nr14] = regs;
+
+        Ok(())
+    }
+}
+
+impl Snapshot for ChannelTwo {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&[self.volume, self.vol_timer]);
+        writer.write_bytes(&[self.len_counter, self.duty_cycle]);
+        writer.write_u16(self.freq_timer);
+        writer.write_bytes(&[self.nr21, self.nr22, self.nr23, self.nr24]);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.volume = reader.read_u8()?;
+        self.vol_timer = reader.read_u8()?;
+        self.len_counter = reader.read_u8()?;
+        self.duty_cycle = reader.read_u8()?;
+        self.freq_timer = reader.read_u16()?;
+
+        let mut regs = [0u8; 4];
+        reader.read_into(&mut regs)?;
+        [self.nr21, self.nr22, self.nr23, self.nr24] = regs;
+
+        Ok(())
+    }
+}
+
+impl Snapshot for ChannelThree {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.current_index);
+        writer.write_u16(self.len_counter);
+        writer.write_u16(self.freq_timer);
+        writer.write_bytes(&[self.nr30, self.nr31, self.nr32, self.nr33, self.nr34]);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.current_index = reader.read_u8()?;
+        self.len_counter = reader.read_u16()?;
+        self.freq_timer = reader.read_u16()?;
+
+        let mut regs = [0u8; 5];
+        reader.read_into(&mut regs)?;
+        [self.nr30, self.nr31, self.nr32, self.nr33, self.nr34] = regs;
+
+        Ok(())
+    }
+}
+
+impl Snapshot for ChannelFour {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&[self.volume, self.vol_timer, self.len_counter]);
+        writer.write_u16(self.lfsr);
+        writer.write_u16(self.freq_timer);
+        writer.write_bytes(&[self.nr41, self.nr42, self.nr43, self.nr44]);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.volume = reader.read_u8()?;
+        self.vol_timer = reader.read_u8()?;
+        self.len_counter = reader.read_u8()?;
+        self.lfsr = reader.read_u16()?;
+        self.freq_timer = reader.read_u16()?;
+
+        let mut regs = [0u8; 4];
+        reader.read_into(&mut regs)?;
+        [self.nr41, self.nr42, self.nr43, self.nr44] = regs;
+
+        Ok(())
+    }
+}
+
+/// Frontend settings (output, fast-forward, channel toggles) are not part of the state.
+impl Snapshot for APU {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.wave_ram);
+        writer.write_u16(self.internal_cycles);
+        writer.write_u8(self.div_apu);
+        writer.write_u8(self.div_bit);
+
+        self.ch1.save_state(writer);
+        self.ch2.save_state(writer);
+        self.ch3.save_state(writer);
+        self.ch4.save_state(writer);
+
+        writer.write_bytes(&[self.nr50, self.nr51, self.nr52]);
+        writer.write_f32(self.capacitor);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.read_into(&mut self.wave_ram)?;
+        self.internal_cycles = reader.read_u16()?;
+        self.div_apu = reader.read_u8()?;
+        self.div_bit = reader.read_u8()?;
+
+        self.ch1.load_state(reader)?;
+        self.ch2.load_state(reader)?;
+        self.ch3.load_state(reader)?;
+        self.ch4.load_state(reader)?;
+
+        self.nr50 = reader.read_u8()?;
+        self.nr51 = reader.read_u8()?;
+        self.nr52 = reader.read_u8()?;
+        self.capacitor = reader.read_f32()?;
+
+        // Samples of the old timeline shouldn't leak into the restored one
+        self.buffer.clear();
+        self.output.clear();
+
+        Ok(())
+    }
+}
@@ -0,0 +1,83 @@
+use rodio::{buffer::SamplesBuffer, OutputStream, OutputStreamHandle, Sink};
+
+/// Sample rate the APU mixes its output at.
+pub const SAMPLE_RATE: u32 = 48_000;
+
+/// Destination for the interleaved stereo samples the APU produces.
+///
+/// Keeps the APU itself free of any audio device so the emulator core can run
+/// headless (CI, tooling) while the frontend plugs in a real audio backend.
+pub trait AudioOutput {
+    /// Receives a batch of interleaved stereo samples (left, right, left, ...).
+    fn queue_samples(&mut self, samples: &[f32]);
+
+    /// Volume between 0.0 and 1.0, ignored by outputs that don't play sound.
+    fn set_volume(&mut self, _volume: f32) {}
+
+    /// Drops every sample that has been queued but not played yet.
+    fn clear(&mut self) {}
+}
+
+/// Discards all samples, used when no audio device is present.
+#[derive(Default)]
+pub struct NullOutput;
+
+impl AudioOutput for NullOutput {
+    fn queue_samples(&mut self, _samples: &[f32]) {}
+}
+
+/// Collects all samples in memory so they can be inspected or written out later.
+#[derive(Default)]
+pub struct BufferedOutput {
+    pub samples: Vec<f32>,
+}
+
+impl AudioOutput for BufferedOutput {
+    fn queue_samples(&mut self, samples: &[f32]) {
+        self.samples.extend_from_slice(samples);
+    }
+
+    fn clear(&mut self) {
+        self.samples.clear();
+    }
+}
+
+/// Plays the samples on the default audio device via rodio.
+pub struct RodioOutput {
+    /// Queue to append samples to, never stops playing
+    sink: Sink,
+    /// Rodio frontend streams to play sound, need to be kept alive
+    _streams: (OutputStream, OutputStreamHandle),
+}
+
+impl RodioOutput {
+    /// Returns `None` if no audio device could be opened.
+    pub fn new() -> Option<Self> {
+        let streams = OutputStream::try_default().ok()?;
+        let sink = Sink::try_new(&streams.1).ok()?;
+
+        Some(Self {
+            sink,
+            _streams: streams,
+        })
+    }
+}
+
+impl AudioOutput for RodioOutput {
+    fn queue_samples(&mut self, samples: &[f32]) {
+        // len() of sink does not return amount of samples but amount of SamplesBuffer.
+        // Blocking here is what keeps the emulator in sync with the audio device.
+        while self.sink.len() > 2 {}
+        self.sink
+            .append(SamplesBuffer::new(2, SAMPLE_RATE, samples.to_vec()));
+    }
+
+    fn set_volume(&mut self, volume: f32) {
+        self.sink.set_volume(volume);
+    }
+
+    fn clear(&mut self) {
+        self.sink.clear();
+        self.sink.play();
+    }
+}
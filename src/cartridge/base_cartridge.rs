@@ -1,18 +1,31 @@
+use crate::cartridge::camera_source::ImageSource;
+use crate::cartridge::mbc::huc1::HuC1;
+use crate::cartridge::mbc::huc3::HuC3;
 use crate::cartridge::mbc::mbc1::MBC1;
 use crate::cartridge::mbc::mbc2::MBC2;
 use crate::cartridge::mbc::mbc3::MBC3;
 use crate::cartridge::mbc::mbc5::MBC5;
+use crate::cartridge::mbc::mbc6::MBC6;
+use crate::cartridge::mbc::mbc7::MBC7;
+use crate::cartridge::mbc::mmm01::MMM01;
 use crate::cartridge::mbc::no_mbc::NoMBC;
+use crate::cartridge::mbc::pocket_camera::PocketCamera;
 use crate::mmu::mmio::MMIO;
+use crate::save_state::{Snapshot, StateError, StateReader, StateWriter};
 
 #[allow(clippy::upper_case_acronyms)]
-#[derive(PartialEq, Eq)]
 pub enum CartridgeType {
     NoMBC(NoMBC),
     MBC1(MBC1),
     MBC2(Box<MBC2>),
     MBC3(MBC3),
     MBC5(MBC5),
+    MBC6(MBC6),
+    MBC7(Box<MBC7>),
+    HuC1(HuC1),
+    HuC3(HuC3),
+    MMM01(MMM01),
+    PocketCamera(PocketCamera),
 }
 
 pub struct Cartridge {
@@ -31,43 +44,247 @@ impl Cartridge {
     // TODO: create per_cartridge! macro to avoid repetition
     /// Loads in a `.sav` file and puts its contents
     /// into cartridge RAM
+    ///
+    /// Files that are shorter than cartridge RAM only fill the start of it.
+    /// For MBC3 and HuC3, the RTC footer after the RAM is restored as well.
     pub fn load_sram(&mut self, save: &[u8]) {
         match &mut self.cartridge_type {
             CartridgeType::MBC1(mbc1) => {
-                for (i, bank) in save.chunks(0x2000).enumerate() {
-                    mbc1.external_ram[i] = bank.try_into().unwrap();
-                }
+                copy_banks(&mut mbc1.external_ram, save);
             }
             CartridgeType::MBC2(mbc2) => {
-                mbc2.built_in_ram = save.try_into().unwrap();
+                let len = save.len().min(mbc2.built_in_ram.len());
+                mbc2.built_in_ram[..len].copy_from_slice(&save[..len]);
             }
             CartridgeType::MBC3(mbc3) => {
-                for (i, bank) in save.chunks(0x2000).enumerate() {
-                    mbc3.external_ram[i] = bank.try_into().unwrap();
-                }
+                let ram_len = copy_banks(&mut mbc3.external_ram, save);
+                mbc3.load_rtc(&save[ram_len..]);
             }
             CartridgeType::MBC5(mbc5) => {
-                for (i, bank) in save.chunks(0x2000).enumerate() {
-                    mbc5.external_ram[i] = bank.try_into().unwrap();
-                }
+                copy_banks(&mut mbc5.external_ram, save);
             }
-            _ => {}
+            CartridgeType::MBC6(mbc6) => {
+                let ram_len = copy_banks(&mut mbc6.external_ram, save);
+                let flash = &save[ram_len..];
+                let len = flash.len().min(mbc6.flash.len());
+                mbc6.flash[..len].copy_from_slice(&flash[..len]);
+            }
+            CartridgeType::MBC7(mbc7) => mbc7.eeprom.load_bytes(save),
+            CartridgeType::HuC1(huc1) => {
+                copy_banks(&mut huc1.external_ram, save);
+            }
+            CartridgeType::HuC3(huc3) => {
+                let ram_len = copy_banks(&mut huc3.external_ram, save);
+                huc3.load_rtc(&save[ram_len..]);
+            }
+            CartridgeType::MMM01(mmm01) => {
+                copy_banks(&mut mmm01.external_ram, save);
+            }
+            CartridgeType::PocketCamera(camera) => {
+                copy_banks(&mut camera.external_ram, save);
+            }
+            CartridgeType::NoMBC(_) => {}
         }
     }
 
     /// Dumps all of SRAM into a Vec of bytes by joining
     /// the banks together.
     ///
+    /// For MBC3 with a clock, the 48 byte RTC footer is appended, HuC3 appends
+    /// its clock as well. MBC6 saves its flash after the RAM, MBC7 its EEPROM.
+    ///
     /// Returns `None` if no cartridge RAM is present.
     pub fn dump_sram(&self) -> Option<Vec<u8>> {
         match &self.cartridge_type {
             CartridgeType::MBC1(mbc1) => Some(mbc1.external_ram.concat()),
             CartridgeType::MBC2(mbc2) => Some(mbc2.built_in_ram.to_vec()),
-            CartridgeType::MBC3(mbc3) => Some(mbc3.external_ram.concat()),
+            CartridgeType::MBC3(mbc3) if mbc3.external_ram.is_empty() && !mbc3.has_rtc() => None,
+            CartridgeType::MBC3(mbc3) => {
+                let mut save = mbc3.external_ram.concat();
+                if let Some(footer) = mbc3.dump_rtc() {
+                    save.extend(footer);
+                }
+
+                Some(save)
+            }
             CartridgeType::MBC5(mbc5) => Some(mbc5.external_ram.concat()),
+            CartridgeType::MBC6(mbc6) => {
+                Some([mbc6.external_ram.concat(), mbc6.flash.clone()].concat())
+            }
+            CartridgeType::MBC7(mbc7) => Some(mbc7.eeprom.to_bytes()),
+            CartridgeType::HuC1(huc1) => Some(huc1.external_ram.concat()),
+            CartridgeType::HuC3(huc3) => {
+                Some([huc3.external_ram.concat(), huc3.dump_rtc().to_vec()].concat())
+            }
+            CartridgeType::MMM01(mmm01) => Some(mmm01.external_ram.concat()),
+            CartridgeType::PocketCamera(camera) => Some(camera.external_ram.concat()),
+            CartridgeType::NoMBC(_) => None,
+        }
+    }
+
+    /// Writes the ROM or RAM byte that is mapped at `address` (0x0000 - 0x7FFF, 0xA000 - 0xBFFF),
+    /// the counterpart to `peek` for debugging tools and cheats.
+    ///
+    /// Unlike `write` this never switches banks, ROM is patched instead.
+    /// Clocks, sensors and other registers mapped over RAM are left alone.
+    pub fn poke(&mut self, address: u16, value: u8) {
+        match &mut self.cartridge_type {
+            CartridgeType::NoMBC(nombc) => nombc.poke(address, value),
+            CartridgeType::MBC1(mbc1) => mbc1.poke(address, value),
+            CartridgeType::MBC2(mbc2) => mbc2.poke(address, value),
+            CartridgeType::MBC3(mbc3) => mbc3.poke(address, value),
+            CartridgeType::MBC5(mbc5) => mbc5.poke(address, value),
+            CartridgeType::MBC6(mbc6) => mbc6.poke(address, value),
+            CartridgeType::MBC7(mbc7) => mbc7.poke(address, value),
+            CartridgeType::HuC1(huc1) => huc1.poke(address, value),
+            CartridgeType::HuC3(huc3) => huc3.poke(address, value),
+            CartridgeType::MMM01(mmm01) => mmm01.poke(address, value),
+            CartridgeType::PocketCamera(camera) => camera.poke(address, value),
+        }
+    }
+
+    /// The whole ROM, bank `n` starts at `n * 0x4000` (`n * 0x2000` on MBC6).
+    pub fn rom(&self) -> &[u8] {
+        match &self.cartridge_type {
+            CartridgeType::NoMBC(nombc) => &nombc.rom,
+            CartridgeType::MBC1(mbc1) => &mbc1.rom,
+            CartridgeType::MBC2(mbc2) => &mbc2.rom,
+            CartridgeType::MBC3(mbc3) => &mbc3.rom,
+            CartridgeType::MBC5(mbc5) => &mbc5.rom,
+            CartridgeType::MBC6(mbc6) => &mbc6.rom,
+            CartridgeType::MBC7(mbc7) => &mbc7.rom,
+            CartridgeType::HuC1(huc1) => &huc1.rom,
+            CartridgeType::HuC3(huc3) => &huc3.rom,
+            CartridgeType::MMM01(mmm01) => &mmm01.rom,
+            CartridgeType::PocketCamera(camera) => &camera.rom,
+        }
+    }
+
+    /// The whole ROM for patching any bank, whether it is mapped or not.
+    pub fn rom_mut(&mut self) -> &mut [u8] {
+        match &mut self.cartridge_type {
+            CartridgeType::NoMBC(nombc) => &mut nombc.rom,
+            CartridgeType::MBC1(mbc1) => &mut mbc1.rom,
+            CartridgeType::MBC2(mbc2) => &mut mbc2.rom,
+            CartridgeType::MBC3(mbc3) => &mut mbc3.rom,
+            CartridgeType::MBC5(mbc5) => &mut mbc5.rom,
+            CartridgeType::MBC6(mbc6) => &mut mbc6.rom,
+            CartridgeType::MBC7(mbc7) => &mut mbc7.rom,
+            CartridgeType::HuC1(huc1) => &mut huc1.rom,
+            CartridgeType::HuC3(huc3) => &mut huc3.rom,
+            CartridgeType::MMM01(mmm01) => &mut mmm01.rom,
+            CartridgeType::PocketCamera(camera) => &mut camera.rom,
+        }
+    }
+
+    /// Cartridge RAM with all banks one after another, empty if there is none.
+    ///
+    /// MBC2 has 512 half bytes built in, MBC7 saves to an EEPROM that isn't mapped as RAM.
+    pub fn sram(&self) -> &[u8] {
+        match &self.cartridge_type {
+            CartridgeType::MBC1(mbc1) => mbc1.external_ram.as_flattened(),
+            CartridgeType::MBC2(mbc2) => &mbc2.built_in_ram,
+            CartridgeType::MBC3(mbc3) => mbc3.external_ram.as_flattened(),
+            CartridgeType::MBC5(mbc5) => mbc5.external_ram.as_flattened(),
+            CartridgeType::MBC6(mbc6) => mbc6.external_ram.as_flattened(),
+            CartridgeType::HuC1(huc1) => huc1.external_ram.as_flattened(),
+            CartridgeType::HuC3(huc3) => huc3.external_ram.as_flattened(),
+            CartridgeType::MMM01(mmm01) => mmm01.external_ram.as_flattened(),
+            CartridgeType::PocketCamera(camera) => camera.external_ram.as_flattened(),
+            CartridgeType::MBC7(_) | CartridgeType::NoMBC(_) => &[],
+        }
+    }
+
+    /// Like `sram`, for writing any bank whether it is mapped or not.
+    pub fn sram_mut(&mut self) -> &mut [u8] {
+        match &mut self.cartridge_type {
+            CartridgeType::MBC1(mbc1) => mbc1.external_ram.as_flattened_mut(),
+            CartridgeType::MBC2(mbc2) => &mut mbc2.built_in_ram,
+            CartridgeType::MBC3(mbc3) => mbc3.external_ram.as_flattened_mut(),
+            CartridgeType::MBC5(mbc5) => mbc5.external_ram.as_flattened_mut(),
+            CartridgeType::MBC6(mbc6) => mbc6.external_ram.as_flattened_mut(),
+            CartridgeType::HuC1(huc1) => huc1.external_ram.as_flattened_mut(),
+            CartridgeType::HuC3(huc3) => huc3.external_ram.as_flattened_mut(),
+            CartridgeType::MMM01(mmm01) => mmm01.external_ram.as_flattened_mut(),
+            CartridgeType::PocketCamera(camera) => camera.external_ram.as_flattened_mut(),
+            CartridgeType::MBC7(_) | CartridgeType::NoMBC(_) => &mut [],
+        }
+    }
+
+    /// Advances the parts of the cartridge that run on their own: the MBC3
+    /// and HuC3 clocks, the MBC5 rumble motor and the camera sensor.
+    pub fn tick(&mut self, cycles: u16) {
+        match &mut self.cartridge_type {
+            CartridgeType::MBC3(mbc3) => mbc3.tick(cycles),
+            CartridgeType::MBC5(mbc5) => mbc5.tick(cycles),
+            CartridgeType::HuC3(huc3) => huc3.tick(cycles),
+            CartridgeType::PocketCamera(camera) => camera.tick(cycles),
+            _ => {}
+        }
+    }
+
+    /// Sets where the Pocket Camera takes its pictures from.
+    pub fn set_camera_source(&mut self, source: Box<dyn ImageSource>) {
+        if let CartridgeType::PocketCamera(camera) = &mut self.cartridge_type {
+            camera.set_source(source);
+        }
+    }
+
+    pub fn is_camera(&self) -> bool {
+        matches!(self.cartridge_type, CartridgeType::PocketCamera(_))
+    }
+
+    /// Passes the console tilt in g on to MBC7 cartridges.
+    pub fn set_tilt(&mut self, x: f32, y: f32) {
+        if let CartridgeType::MBC7(mbc7) = &mut self.cartridge_type {
+            mbc7.set_tilt(x, y);
+        }
+    }
+
+    /// Rumble motor duty cycle since the last call, between 0.0 and 1.0.
+    ///
+    /// Returns `None` if the cartridge has no rumble motor.
+    pub fn take_rumble_duty(&mut self) -> Option<f32> {
+        match &mut self.cartridge_type {
+            CartridgeType::MBC5(mbc5) if mbc5.has_rumble() => Some(mbc5.take_rumble_duty()),
             _ => None,
         }
     }
+
+    /// ROM bank in 16 KiB units that is mapped at `address` (0x0000 - 0x7FFF).
+    ///
+    /// The bank is the one the game selected, it is not wrapped to the size of the ROM.
+    pub fn rom_bank(&self, address: u16) -> usize {
+        let upper = address >= 0x4000;
+
+        match &self.cartridge_type {
+            CartridgeType::MBC1(mbc1) => mbc1.rom_bank(upper),
+            CartridgeType::MMM01(mmm01) => mmm01.rom_bank(upper),
+            _ if !upper => 0,
+            CartridgeType::NoMBC(_) => 1,
+            CartridgeType::MBC2(mbc2) => mbc2.rom_bank(),
+            CartridgeType::MBC3(mbc3) => mbc3.rom_bank(),
+            CartridgeType::MBC5(mbc5) => mbc5.rom_bank(),
+            // MBC6 switches 8 KiB banks
+            CartridgeType::MBC6(mbc6) => mbc6.rom_bank(address).0 / 2,
+            CartridgeType::MBC7(mbc7) => mbc7.rom_bank(),
+            CartridgeType::HuC1(huc1) => huc1.rom_bank(),
+            CartridgeType::HuC3(huc3) => huc3.rom_bank(),
+            CartridgeType::PocketCamera(camera) => camera.rom_bank(),
+        }
+    }
+}
+
+/// Copies `save` bank by bank into `banks` and returns how many bytes were used.
+fn copy_banks<const N: usize>(banks: &mut [[u8; N]], save: &[u8]) -> usize {
+    let mut copied = 0;
+
+    for (bank, chunk) in banks.iter_mut().zip(save.chunks(N)) {
+        bank[..chunk.len()].copy_from_slice(chunk);
+        copied += chunk.len();
+    }
+
+    copied
 }
 
 impl Default for Cartridge {
@@ -81,13 +298,19 @@ impl Default for Cartridge {
 
 impl MMIO for Cartridge {
     #[inline(always)]
-    fn read(&mut self, address: u16) -> u8 {
-        match &mut self.cartridge_type {
-            CartridgeType::NoMBC(nombc) => nombc.read(address),
-            CartridgeType::MBC1(mbc1) => mbc1.read(address),
-            CartridgeType::MBC2(mbc2) => mbc2.read(address),
-            CartridgeType::MBC3(mbc3) => mbc3.read(address),
-            CartridgeType::MBC5(mbc5) => mbc5.read(address),
+    fn peek(&self, address: u16) -> u8 {
+        match &self.cartridge_type {
+            CartridgeType::NoMBC(nombc) => nombc.peek(address),
+            CartridgeType::MBC1(mbc1) => mbc1.peek(address),
+            CartridgeType::MBC2(mbc2) => mbc2.peek(address),
+            CartridgeType::MBC3(mbc3) => mbc3.peek(address),
+            CartridgeType::MBC5(mbc5) => mbc5.peek(address),
+            CartridgeType::MBC6(mbc6) => mbc6.peek(address),
+            CartridgeType::MBC7(mbc7) => mbc7.peek(address),
+            CartridgeType::HuC1(huc1) => huc1.peek(address),
+            CartridgeType::HuC3(huc3) => huc3.peek(address),
+            CartridgeType::MMM01(mmm01) => mmm01.peek(address),
+            CartridgeType::PocketCamera(camera) => camera.peek(address),
         }
     }
 
@@ -98,7 +321,79 @@ impl MMIO for Cartridge {
             CartridgeType::MBC2(mbc2) => mbc2.write(address, value),
             CartridgeType::MBC3(mbc3) => mbc3.write(address, value),
             CartridgeType::MBC5(mbc5) => mbc5.write(address, value),
+            CartridgeType::MBC6(mbc6) => mbc6.write(address, value),
+            CartridgeType::MBC7(mbc7) => mbc7.write(address, value),
+            CartridgeType::HuC1(huc1) => huc1.write(address, value),
+            CartridgeType::HuC3(huc3) => huc3.write(address, value),
+            CartridgeType::MMM01(mmm01) => mmm01.write(address, value),
+            CartridgeType::PocketCamera(camera) => camera.write(address, value),
             _ => {}
         }
     }
 }
+
+impl Snapshot for Cartridge {
+    /// ROM contents are not part of the state, only bank registers and RAM.
+    fn save_state(&self, writer: &mut StateWriter) {
+        match &self.cartridge_type {
+            CartridgeType::NoMBC(_) => writer.write_u8(0),
+            CartridgeType::MBC1(mbc1) => {
+                writer.write_u8(1);
+                mbc1.save_state(writer);
+            }
+            CartridgeType::MBC2(mbc2) => {
+                writer.write_u8(2);
+                mbc2.save_state(writer);
+            }
+            CartridgeType::MBC3(mbc3) => {
+                writer.write_u8(3);
+                mbc3.save_state(writer);
+            }
+            CartridgeType::MBC5(mbc5) => {
+                writer.write_u8(5);
+                mbc5.save_state(writer);
+            }
+            CartridgeType::MBC6(mbc6) => {
+                writer.write_u8(6);
+                mbc6.save_state(writer);
+            }
+            CartridgeType::MBC7(mbc7) => {
+                writer.write_u8(7);
+                mbc7.save_state(writer);
+            }
+            CartridgeType::HuC1(huc1) => {
+                writer.write_u8(8);
+                huc1.save_state(writer);
+            }
+            CartridgeType::HuC3(huc3) => {
+                writer.write_u8(9);
+                huc3.save_state(writer);
+            }
+            CartridgeType::MMM01(mmm01) => {
+                writer.write_u8(10);
+                mmm01.save_state(writer);
+            }
+            CartridgeType::PocketCamera(camera) => {
+                writer.write_u8(11);
+                camera.save_state(writer);
+            }
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        match (reader.read_u8()?, &mut self.cartridge_type) {
+            (0, CartridgeType::NoMBC(_)) => Ok(()),
+            (1, CartridgeType::MBC1(mbc1)) => mbc1.load_state(reader),
+            (2, CartridgeType::MBC2(mbc2)) => mbc2.load_state(reader),
+            (3, CartridgeType::MBC3(mbc3)) => mbc3.load_state(reader),
+            (5, CartridgeType::MBC5(mbc5)) => mbc5.load_state(reader),
+            (6, CartridgeType::MBC6(mbc6)) => mbc6.load_state(reader),
+            (7, CartridgeType::MBC7(mbc7)) => mbc7.load_state(reader),
+            (8, CartridgeType::HuC1(huc1)) => huc1.load_state(reader),
+            (9, CartridgeType::HuC3(huc3)) => huc3.load_state(reader),
+            (10, CartridgeType::MMM01(mmm01)) => mmm01.load_state(reader),
+            (11, CartridgeType::PocketCamera(camera)) => camera.load_state(reader),
+            _ => Err(StateError::InvalidValue("cartridge type")),
+        }
+    }
+}
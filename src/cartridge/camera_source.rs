@@ -0,0 +1,100 @@
+use std::path::{Path, PathBuf};
+
+use image::{imageops::FilterType, ImageResult};
+
+/// Size of the picture the Game Boy Camera takes.
+pub const SENSOR_WIDTH: usize = 128;
+pub const SENSOR_HEIGHT: usize = 112;
+
+/// Provides the light that falls onto the camera sensor.
+///
+/// Lets the Pocket Camera work without a real camera attached.
+pub trait ImageSource {
+    /// Grayscale picture of `SENSOR_WIDTH` x `SENSOR_HEIGHT` pixels
+    /// row by row, 0 is black and 255 white.
+    fn capture(&mut self) -> Vec<u8>;
+}
+
+/// Diagonal gradient with a bar moving across it, so the
+/// viewfinder visibly updates.
+#[derive(Default)]
+pub struct TestPattern {
+    frame: usize,
+}
+
+impl ImageSource for TestPattern {
+    fn capture(&mut self) -> Vec<u8> {
+        self.frame = (self.frame + 1) % SENSOR_WIDTH;
+
+        (0..SENSOR_HEIGHT)
+            .flat_map(|y| (0..SENSOR_WIDTH).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                if (x + SENSOR_WIDTH - self.frame) % SENSOR_WIDTH < 8 {
+                    0
+                } else {
+                    ((x + y) * 255 / (SENSOR_WIDTH + SENSOR_HEIGHT)) as u8
+                }
+            })
+            .collect()
+    }
+}
+
+/// The same picture, loaded from an image file, for every capture.
+pub struct StillImage {
+    pixels: Vec<u8>,
+}
+
+impl StillImage {
+    pub fn open(path: &Path) -> ImageResult<Self> {
+        Ok(Self {
+            pixels: load_picture(path)?,
+        })
+    }
+}
+
+impl ImageSource for StillImage {
+    fn capture(&mut self) -> Vec<u8> {
+        self.pixels.clone()
+    }
+}
+
+/// Cycles through a list of image files, one per capture.
+pub struct FrameSequence {
+    frames: Vec<Vec<u8>>,
+    index: usize,
+}
+
+impl FrameSequence {
+    pub fn open(paths: &[PathBuf]) -> ImageResult<Self> {
+        Ok(Self {
+            frames: paths
+                .iter()
+                .map(|p| load_picture(p))
+                .collect::<ImageResult<_>>()?,
+            index: 0,
+        })
+    }
+}
+
+impl ImageSource for FrameSequence {
+    fn capture(&mut self) -> Vec<u8> {
+        let Some(frame) = self.frames.get(self.index) else {
+            return vec![0; SENSOR_WIDTH * SENSOR_HEIGHT];
+        };
+
+        self.index = (self.index + 1) % self.frames.len();
+        frame.clone()
+    }
+}
+
+/// Scales and crops the image to the sensor size and converts it to grayscale.
+fn load_picture(path: &Path) -> ImageResult<Vec<u8>> {
+    Ok(image::open(path)?
+        .resize_to_fill(
+            SENSOR_WIDTH as u32,
+            SENSOR_HEIGHT as u32,
+            FilterType::Triangle,
+        )
+        .into_luma8()
+        .into_raw())
+}
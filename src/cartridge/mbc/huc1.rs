@@ -0,0 +1,154 @@
+use crate::{
+    mmu::mmio::MMIO,
+    save_state::{Snapshot, StateError, StateReader, StateWriter},
+};
+
+/// Hudson's MBC1 variant with an infrared port instead of a RAM enable.
+#[derive(PartialEq, Eq)]
+pub struct HuC1 {
+    pub rom: Vec<u8>,
+    pub external_ram: Vec<[u8; 0x2000]>,
+
+    /// 0x0E maps the IR port to 0xA000 - 0xBFFF, anything else RAM
+    ir_mode: bool,
+    ir_led: bool,
+
+    rom_bank_number: u8,
+    ram_bank_number: u8,
+}
+
+impl HuC1 {
+    pub fn new(rom: &[u8], ram_size: u8) -> Self {
+        Self {
+            rom: rom.to_vec(),
+            external_ram: vec![[0xFF; 0x2000]; (ram_size / 8) as usize],
+
+            ir_mode: false,
+            ir_led: false,
+
+            rom_bank_number: 0x01,
+            ram_bank_number: 0x00,
+        }
+    }
+
+    /// ROM bank mapped to 0x4000 - 0x7FFF.
+    pub fn rom_bank(&self) -> usize {
+        self.rom_bank_number as usize
+    }
+
+    /// Whether the game currently turns its IR LED on.
+    pub fn ir_led(&self) -> bool {
+        self.ir_led
+    }
+
+    /// Writes the ROM or RAM byte that is mapped at `address`, without touching the registers.
+    ///
+    /// Nothing is written while the IR port is mapped instead of RAM.
+    pub fn poke(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x7FFF => {
+                let index = self.rom_index(address);
+                self.rom[index] = value;
+            }
+            0xA000..=0xBFFF if !self.ir_mode && !self.external_ram.is_empty() => {
+                let bank = self.ram_bank_number as usize & (self.external_ram.len() - 1);
+                self.external_ram[bank][address as usize - 0xA000] = value;
+            }
+            _ => {}
+        }
+    }
+
+    fn rom_index(&self, address: u16) -> usize {
+        if address < 0x4000 {
+            return address as usize;
+        }
+
+        let address = (self.rom_bank_number as usize * 0x4000) + (address as usize - 0x4000);
+        address & (self.rom.len() - 1)
+    }
+}
+
+impl MMIO for HuC1 {
+    #[inline(always)]
+    fn peek(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x7FFF => self.rom[self.rom_index(address)],
+            0xA000..=0xBFFF => {
+                if self.ir_mode {
+                    // Bit 0 set means light is received, no other device is ever there
+                    0xC0
+                } else if !self.external_ram.is_empty() {
+                    let bank = self.ram_bank_number as usize & (self.external_ram.len() - 1);
+                    self.external_ram[bank][address as usize - 0xA000]
+                } else {
+                    0xFF
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[inline(always)]
+    fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ir_mode = value == 0x0E,
+            0x2000..=0x3FFF => self.rom_bank_number = (value & 0x3F).max(1),
+            0x4000..=0x5FFF => self.ram_bank_number = value & 0x3,
+            0x6000..=0x7FFF => {}
+            0xA000..=0xBFFF => {
+                if self.ir_mode {
+                    self.ir_led = value & 0x1 != 0;
+                } else if !self.external_ram.is_empty() {
+                    let bank = self.ram_bank_number as usize & (self.external_ram.len() - 1);
+                    self.external_ram[bank][address as usize - 0xA000] = value;
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Snapshot for HuC1 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.ir_mode);
+        writer.write_bool(self.ir_led);
+        writer.write_u8(self.rom_bank_number);
+        writer.write_u8(self.ram_bank_number);
+
+        writer.write_bytes(&self.external_ram.concat());
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.ir_mode = reader.read_bool()?;
+        self.ir_led = reader.read_bool()?;
+        self.rom_bank_number = reader.read_u8()?;
+        self.ram_bank_number = reader.read_u8()?;
+
+        for bank in &mut self.external_ram {
+            reader.read_into(bank)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HuC1;
+    use crate::mmu::mmio::MMIO;
+
+    #[test]
+    fn ir_mode_replaces_ram() {
+        let mut huc1 = HuC1::new(&[0; 0x8000], 8);
+        huc1.write(0xA000, 0x42);
+
+        huc1.write(0x0000, 0x0E);
+        assert_eq!(huc1.peek(0xA000), 0xC0);
+        huc1.write(0xA000, 0x01);
+        assert!(huc1.ir_led());
+
+        huc1.write(0x0000, 0x00);
+        assert_eq!(huc1.peek(0xA000), 0x42);
+        assert!(huc1.ir_led());
+    }
+}
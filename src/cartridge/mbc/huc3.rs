@@ -0,0 +1,394 @@
+use crate::{
+    cartridge::mbc::mbc3::{unix_time, CYCLES_PER_SECOND},
+    mmu::mmio::MMIO,
+    save_state::{Snapshot, StateError, StateReader, StateWriter},
+};
+
+const MINUTES_PER_DAY: u16 = 60 * 24;
+
+/// Hudson's mapper with a clock, alarm and IR port.
+///
+/// The clock is not mapped directly, games talk to it by writing commands
+/// in mode 0x0B and reading back the result in mode 0x0C.
+#[derive(PartialEq, Eq)]
+pub struct HuC3 {
+    pub rom: Vec<u8>,
+    pub external_ram: Vec<[u8; 0x2000]>,
+
+    /// What 0xA000 - 0xBFFF maps to, see `peek` and `write`
+    mode: u8,
+    rom_bank_number: u8,
+    ram_bank_number: u8,
+
+    rtc: HuC3Clock,
+}
+
+#[derive(Default, PartialEq, Eq)]
+struct HuC3Clock {
+    minutes: u16,
+    days: u16,
+    alarm_minutes: u16,
+    alarm_days: u16,
+    alarm_enabled: bool,
+
+    /// Seconds and T-cycles since the last full minute
+    seconds: u8,
+    sub_second: u32,
+
+    /// Nibble address of the next command
+    access_index: u8,
+    access_flags: u8,
+    /// Result of the last read command
+    read_value: u8,
+}
+
+impl HuC3Clock {
+    fn tick(&mut self, cycles: u16) {
+        self.sub_second += cycles as u32;
+        if self.sub_second >= CYCLES_PER_SECOND {
+            self.sub_second -= CYCLES_PER_SECOND;
+            self.advance(1);
+        }
+    }
+
+    fn advance(&mut self, seconds: u64) {
+        let seconds = self.seconds as u64 + seconds;
+        let minutes = self.minutes as u64 + seconds / 60;
+        let days = self.days as u64 + minutes / MINUTES_PER_DAY as u64;
+
+        self.seconds = (seconds % 60) as u8;
+        self.minutes = (minutes % MINUTES_PER_DAY as u64) as u16;
+        self.days = days as u16;
+    }
+
+    /// Executes a command: upper nibble is the command, lower nibble its argument.
+    fn command(&mut self, value: u8) {
+        let argument = value & 0xF;
+        let index = self.access_index as u16;
+
+        match value >> 4 {
+            // Read nibble and advance
+            0x1 => {
+                self.read_value = match index {
+                    0x00..=0x02 => (self.minutes >> (index * 4)) as u8 & 0xF,
+                    0x03..=0x06 => (self.days >> ((index - 3) * 4)) as u8 & 0xF,
+                    _ => 0x0,
+                };
+                self.access_index = self.access_index.wrapping_add(1);
+            }
+            // Write nibble, 0x3 advances afterwards
+            0x2 | 0x3 => {
+                let set_nibble = |register: &mut u16, nibble: u16| {
+                    *register &= !(0xF << (nibble * 4));
+                    *register |= (argument as u16) << (nibble * 4);
+                };
+
+                match index {
+                    0x00..=0x02 => set_nibble(&mut self.minutes, index),
+                    0x03..=0x06 => set_nibble(&mut self.days, index - 3),
+                    0x58..=0x5A => set_nibble(&mut self.alarm_minutes, index - 0x58),
+                    0x5B..=0x5E => set_nibble(&mut self.alarm_days, index - 0x5B),
+                    0x5F => self.alarm_enabled = argument & 1 != 0,
+                    _ => {}
+                }
+
+                if value >> 4 == 0x3 {
+                    self.access_index = self.access_index.wrapping_add(1);
+                }
+            }
+            0x4 => self.access_index = (self.access_index & 0xF0) | argument,
+            0x5 => self.access_index = (self.access_index & 0x0F) | (argument << 4),
+            0x6 => self.access_flags = argument,
+            _ => {}
+        }
+    }
+
+    fn response(&self) -> u8 {
+        // Flag 0x2 asks whether the clock is ready, which it always is
+        if self.access_flags == 0x2 {
+            0x1
+        } else {
+            self.read_value
+        }
+    }
+}
+
+impl HuC3 {
+    pub fn new(rom: &[u8], ram_size: u8) -> Self {
+        Self {
+            rom: rom.to_vec(),
+            external_ram: vec![[0xFF; 0x2000]; (ram_size / 8) as usize],
+
+            mode: 0x00,
+            rom_bank_number: 0x01,
+            ram_bank_number: 0x00,
+
+            rtc: HuC3Clock::default(),
+        }
+    }
+
+    /// ROM bank mapped to 0x4000 - 0x7FFF.
+    pub fn rom_bank(&self) -> usize {
+        self.rom_bank_number as usize
+    }
+
+    pub fn tick(&mut self, cycles: u16) {
+        self.rtc.tick(cycles);
+    }
+
+    /// Clock state as appended to `.sav` files by SameBoy: 64 bit UNIX timestamp,
+    /// minutes, days, alarm minutes and days (16 bit each) and the alarm enable byte.
+    pub fn dump_rtc(&self) -> [u8; 17] {
+        let mut footer = [0; 17];
+
+        footer[0..8].copy_from_slice(&unix_time().to_le_bytes());
+        footer[8..10].copy_from_slice(&self.rtc.minutes.to_le_bytes());
+        footer[10..12].copy_from_slice(&self.rtc.days.to_le_bytes());
+        footer[12..14].copy_from_slice(&self.rtc.alarm_minutes.to_le_bytes());
+        footer[14..16].copy_from_slice(&self.rtc.alarm_days.to_le_bytes());
+        footer[16] = self.rtc.alarm_enabled as u8;
+
+        footer
+    }
+
+    /// Restores the clock from a `.sav` footer (see `dump_rtc`)
+    /// and lets the time pass that went by since it was written.
+    pub fn load_rtc(&mut self, footer: &[u8]) {
+        let Ok(footer) = <[u8; 17]>::try_from(footer) else {
+            return;
+        };
+
+        let u16_at = |i: usize| u16::from_le_bytes([footer[i], footer[i + 1]]);
+        let timestamp = u64::from_le_bytes(footer[0..8].try_into().unwrap());
+
+        self.rtc.minutes = u16_at(8) % MINUTES_PER_DAY;
+        self.rtc.days = u16_at(10);
+        self.rtc.alarm_minutes = u16_at(12);
+        self.rtc.alarm_days = u16_at(14);
+        self.rtc.alarm_enabled = footer[16] & 1 != 0;
+
+        self.rtc.seconds = 0;
+        self.rtc.sub_second = 0;
+        self.rtc.advance(unix_time().saturating_sub(timestamp));
+    }
+
+    /// Writes the ROM or RAM byte that is mapped at `address`, without touching the registers.
+    ///
+    /// RAM can be poked in the read only mode 0x0 as well, nothing is written
+    /// while the clock or IR port are mapped.
+    pub fn poke(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x7FFF => {
+                let index = self.rom_index(address);
+                self.rom[index] = value;
+            }
+            0xA000..=0xBFFF if matches!(self.mode, 0x0 | 0xA) => {
+                if let Some(bank) = self.ram_bank() {
+                    self.external_ram[bank][address as usize - 0xA000] = value;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn rom_index(&self, address: u16) -> usize {
+        if address < 0x4000 {
+            return address as usize;
+        }
+
+        let address = (self.rom_bank_number as usize * 0x4000) + (address as usize - 0x4000);
+        address & (self.rom.len() - 1)
+    }
+
+    fn ram_bank(&self) -> Option<usize> {
+        let banks = self.external_ram.len();
+        if banks == 0 {
+            return None;
+        }
+
+        Some(self.ram_bank_number as usize & (banks - 1))
+    }
+}
+
+impl MMIO for HuC3 {
+    #[inline(always)]
+    fn peek(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x7FFF => self.rom[self.rom_index(address)],
+            0xA000..=0xBFFF => match self.mode {
+                0x0 | 0xA => self.ram_bank().map_or(0xFF, |bank| {
+                    self.external_ram[bank][address as usize - 0xA000]
+                }),
+                0xC => self.rtc.response(),
+                // Semaphore, the clock is always ready for the next command
+                0xD => 0x1,
+                // IR, no light received
+                0xE => 0xC0,
+                _ => 0xFF,
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    #[inline(always)]
+    fn write(&mut self, address: u16, value: u8) {
+        match address {
+            // 0x0 RAM read only, 0xA RAM read / write, 0xB command,
+            // 0xC response, 0xD semaphore, 0xE IR
+            0x0000..=0x1FFF => self.mode = value & 0xF,
+            0x2000..=0x3FFF => self.rom_bank_number = value & 0x7F,
+            0x4000..=0x5FFF => self.ram_bank_number = value & 0x3,
+            0x6000..=0x7FFF => {}
+            0xA000..=0xBFFF => match self.mode {
+                0xA => {
+                    if let Some(bank) = self.ram_bank() {
+                        self.external_ram[bank][address as usize - 0xA000] = value;
+                    }
+                }
+                0xB => self.rtc.command(value),
+                _ => {}
+            },
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Snapshot for HuC3 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.mode);
+        writer.write_u8(self.rom_bank_number);
+        writer.write_u8(self.ram_bank_number);
+
+        writer.write_u16(self.rtc.minutes);
+        writer.write_u16(self.rtc.days);
+        writer.write_u16(self.rtc.alarm_minutes);
+        writer.write_u16(self.rtc.alarm_days);
+        writer.write_bool(self.rtc.alarm_enabled);
+        writer.write_u8(self.rtc.seconds);
+        writer.write_u32(self.rtc.sub_second);
+        writer.write_u8(self.rtc.access_index);
+        writer.write_u8(self.rtc.access_flags);
+        writer.write_u8(self.rtc.read_value);
+
+        writer.write_bytes(&self.external_ram.concat());
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.mode = reader.read_u8()?;
+        self.rom_bank_number = reader.read_u8()?;
+        self.ram_bank_number = reader.read_u8()?;
+
+        self.rtc.minutes = reader.read_u16()?;
+        self.rtc.days = reader.read_u16()?;
+        self.rtc.alarm_minutes = reader.read_u16()?;
+        self.rtc.alarm_days = reader.read_u16()?;
+        self.rtc.alarm_enabled = reader.read_bool()?;
+        self.rtc.seconds = reader.read_u8()?;
+        self.rtc.sub_second = reader.read_u32()?;
+        self.rtc.access_index = reader.read_u8()?;
+        self.rtc.access_flags = reader.read_u8()?;
+        self.rtc.read_value = reader.read_u8()?;
+
+        for bank in &mut self.external_ram {
+            reader.read_into(bank)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HuC3, MINUTES_PER_DAY};
+    use crate::{cartridge::mbc::mbc3::CYCLES_PER_SECOND, mmu::mmio::MMIO};
+
+    fn command(huc3: &mut HuC3, value: u8) {
+        huc3.write(0x0000, 0x0B);
+        huc3.write(0xA000, value);
+    }
+
+    fn response(huc3: &mut HuC3) -> u8 {
+        huc3.write(0x0000, 0x0C);
+        huc3.peek(0xA000)
+    }
+
+    fn set_index(huc3: &mut HuC3, index: u8) {
+        command(huc3, 0x40 | (index & 0xF));
+        command(huc3, 0x50 | (index >> 4));
+    }
+
+    /// Writes minutes and days nibble by nibble, the way games set the clock.
+    fn set_time(huc3: &mut HuC3, minutes: u16, days: u16) {
+        set_index(huc3, 0x00);
+        for nibble in 0..3 {
+            command(huc3, 0x30 | (minutes >> (nibble * 4)) as u8 & 0xF);
+        }
+        for nibble in 0..4 {
+            command(huc3, 0x30 | (days >> (nibble * 4)) as u8 & 0xF);
+        }
+    }
+
+    fn time(huc3: &mut HuC3) -> (u16, u16) {
+        set_index(huc3, 0x00);
+        let mut nibbles = [0u16; 7];
+        for nibble in &mut nibbles {
+            command(huc3, 0x10);
+            *nibble = response(huc3) as u16;
+        }
+
+        let minutes = nibbles[0] | nibbles[1] << 4 | nibbles[2] << 8;
+        let days = nibbles[3] | nibbles[4] << 4 | nibbles[5] << 8 | nibbles[6] << 12;
+        (minutes, days)
+    }
+
+    fn tick_seconds(huc3: &mut HuC3, seconds: u32) {
+        for _ in 0..seconds * (CYCLES_PER_SECOND / 0x8000) {
+            huc3.tick(0x8000);
+        }
+    }
+
+    #[test]
+    fn clock_is_set_and_read_by_commands() {
+        let mut huc3 = HuC3::new(&[0; 0x8000], 32);
+
+        set_time(&mut huc3, 0x123, 0x0456);
+        assert_eq!(time(&mut huc3), (0x123, 0x0456));
+
+        tick_seconds(&mut huc3, 60);
+        assert_eq!(time(&mut huc3), (0x124, 0x0456));
+
+        // Write without advancing
+        set_index(&mut huc3, 0x00);
+        command(&mut huc3, 0x20);
+        command(&mut huc3, 0x27);
+        assert_eq!(time(&mut huc3), (0x127, 0x0456));
+
+        // Ready flag
+        command(&mut huc3, 0x62);
+        assert_eq!(response(&mut huc3), 0x1);
+    }
+
+    #[test]
+    fn clock_carries_into_days() {
+        let mut huc3 = HuC3::new(&[0; 0x8000], 32);
+
+        set_time(&mut huc3, MINUTES_PER_DAY - 1, 9);
+        tick_seconds(&mut huc3, 60);
+
+        assert_eq!(time(&mut huc3), (0, 10));
+    }
+
+    #[test]
+    fn clock_survives_sram_footer() {
+        let mut huc3 = HuC3::new(&[0; 0x8000], 32);
+        set_time(&mut huc3, 600, 42);
+        set_index(&mut huc3, 0x5F);
+        command(&mut huc3, 0x21);
+
+        let mut loaded = HuC3::new(&[0; 0x8000], 32);
+        loaded.load_rtc(&huc3.dump_rtc());
+
+        assert_eq!(time(&mut loaded), (600, 42));
+        assert!(loaded.rtc.alarm_enabled);
+    }
+}
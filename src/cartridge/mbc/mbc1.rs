@@ -1,4 +1,7 @@
-use crate::mmu::mmio::MMIO;
+use crate::{
+    mmu::mmio::MMIO,
+    save_state::{Snapshot, StateError, StateReader, StateWriter},
+};
 
 #[derive(PartialEq, Eq)]
 pub struct MBC1 {
@@ -7,6 +10,8 @@ pub struct MBC1 {
 
     rom_size: usize,
     ram_size: u8,
+    /// MBC1M: bank bit 4 is not connected, the upper bits select one of four 256 KiB games
+    multicart: bool,
 
     ram_enable: bool,
     rom_bank_number: u8,
@@ -22,6 +27,7 @@ impl MBC1 {
 
             rom_size,
             ram_size,
+            multicart: is_multicart(rom),
 
             ram_enable: false,
             rom_bank_number: 0x01,
@@ -29,43 +35,95 @@ impl MBC1 {
             banking_mode: 0x00,
         }
     }
-}
 
-impl MMIO for MBC1 {
-    #[inline(always)]
-    fn read(&mut self, address: u16) -> u8 {
-        match address {
-            0x0000..=0x3FFF => {
-                if self.banking_mode == 0 || self.rom_size < 1024 {
-                    self.rom[address as usize]
-                } else {
-                    let rom_bank = self.ram_or_upper_rom << 5;
-                    let address = (rom_bank as u32 * 0x4000) + address as u32;
-
-                    self.rom[address as usize & (self.rom.len() - 1)]
-                }
+    /// ROM bank for 0x0000 - 0x3FFF (`upper == false`) or 0x4000 - 0x7FFF.
+    pub fn rom_bank(&self, upper: bool) -> usize {
+        let rom_bank = if !upper {
+            if self.banking_mode == 0 || self.rom_size < 1024 {
+                0
+            } else {
+                self.ram_or_upper_rom << self.upper_bank_shift()
             }
-            0x4000..=0x7FFF => {
-                let rom_bank = if self.rom_size < 1024 {
-                    self.rom_bank_number
-                } else {
-                    (self.ram_or_upper_rom << 5) | self.rom_bank_number
-                };
+        } else if self.rom_size < 1024 {
+            self.rom_bank_number
+        } else if self.multicart {
+            (self.ram_or_upper_rom << 4) | (self.rom_bank_number & 0x0F)
+        } else {
+            (self.ram_or_upper_rom << 5) | self.rom_bank_number
+        };
+
+        rom_bank as usize
+    }
 
-                let address = (rom_bank as u32 * 0x4000) + (address as u32 - 0x4000);
-                self.rom[address as usize & (self.rom.len() - 1)]
+    /// Writes the ROM or RAM byte that is mapped at `address`, without touching the registers.
+    ///
+    /// Nothing is written while RAM is disabled, just like `peek` doesn't see it.
+    pub fn poke(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x7FFF => {
+                let index = self.rom_index(address);
+                self.rom[index] = value;
             }
             0xA000..=0xBFFF => {
-                if self.ram_enable && self.ram_size > 0 {
-                    if self.banking_mode == 0 || self.ram_size <= 8 {
-                        self.external_ram[0][address as usize - 0xA000]
-                    } else {
-                        self.external_ram[self.ram_or_upper_rom as usize][address as usize - 0xA000]
-                    }
-                } else {
-                    0xFF
+                if let Some((bank, i)) = self.ram_index(address) {
+                    self.external_ram[bank][i] = value;
                 }
             }
+            _ => {}
+        }
+    }
+
+    fn rom_index(&self, address: u16) -> usize {
+        let bank = self.rom_bank(address >= 0x4000);
+        let address = bank * 0x4000 + (address as usize & 0x3FFF);
+
+        address & (self.rom.len() - 1)
+    }
+
+    fn ram_index(&self, address: u16) -> Option<(usize, usize)> {
+        if !self.ram_enable || self.ram_size == 0 {
+            return None;
+        }
+
+        let bank = if self.banking_mode == 0 || self.ram_size <= 8 {
+            0
+        } else {
+            self.ram_or_upper_rom as usize
+        };
+
+        Some((bank, address as usize - 0xA000))
+    }
+
+    /// The upper two bank bits start at bit 5, or bit 4 on multicarts.
+    fn upper_bank_shift(&self) -> u8 {
+        if self.multicart {
+            4
+        } else {
+            5
+        }
+    }
+}
+
+/// MBC1M carts are 1 MiB collections of four 256 KiB games, each with its own header.
+/// They are detected by the Nintendo logo that starts every game's header.
+fn is_multicart(rom: &[u8]) -> bool {
+    const LOGO: std::ops::Range<usize> = 0x0104..0x0134;
+
+    rom.len() == 0x10_0000
+        && (1..4).any(|game| {
+            let offset = game * 0x4_0000;
+            rom[offset + LOGO.start..offset + LOGO.end] == rom[LOGO]
+        })
+}
+
+impl MMIO for MBC1 {
+    #[inline(always)]
+    fn peek(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x7FFF => self.rom[self.rom_index(address)],
+            0xA000..=0xBFFF => self
+                .ram_index(address)
+                .map_or(0xFF, |(bank, i)| self.external_ram[bank][i]),
             _ => unreachable!(),
         }
     }
@@ -94,16 +152,128 @@ impl MMIO for MBC1 {
                 self.banking_mode = value & 0x1;
             }
             0xA000..=0xBFFF => {
-                if self.ram_enable && self.ram_size > 0 {
-                    if self.banking_mode == 0 {
-                        self.external_ram[0][address as usize - 0xA000] = value;
-                    } else {
-                        self.external_ram[self.ram_or_upper_rom as usize]
-                            [address as usize - 0xA000] = value;
-                    }
+                if let Some((bank, i)) = self.ram_index(address) {
+                    self.external_ram[bank][i] = value;
                 }
             }
             _ => unreachable!(),
         }
     }
 }
+
+impl Snapshot for MBC1 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.ram_enable);
+        writer.write_u8(self.rom_bank_number);
+        writer.write_u8(self.ram_or_upper_rom);
+        writer.write_u8(self.banking_mode);
+
+        writer.write_bytes(&self.external_ram.concat());
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.ram_enable = reader.read_bool()?;
+        self.rom_bank_number = reader.read_u8()?;
+        self.ram_or_upper_rom = reader.read_u8()?;
+        self.banking_mode = reader.read_u8()?;
+
+        for bank in &mut self.external_ram {
+            reader.read_into(bank)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MBC1;
+    use crate::mmu::mmio::MMIO;
+
+    const LOGO: [u8; 0x30] = [0xCE; 0x30];
+
+    /// 1 MiB ROM with its bank number at 0x0200 of every bank.
+    ///
+    /// With `games`, the logo is repeated at the start of every 256 KiB.
+    fn rom(games: bool) -> Vec<u8> {
+        let mut rom = vec![0; 0x10_0000];
+        for bank in 0..64 {
+            rom[bank * 0x4000 + 0x200] = bank as u8;
+        }
+
+        let headers = if games { 4 } else { 1 };
+        for game in 0..headers {
+            rom[game * 0x4_0000 + 0x104..][..LOGO.len()].copy_from_slice(&LOGO);
+        }
+
+        rom
+    }
+
+    /// Banks mapped to 0x0000 - 0x3FFF and 0x4000 - 0x7FFF.
+    fn banks(mbc1: &MBC1) -> (u8, u8) {
+        (mbc1.peek(0x0200), mbc1.peek(0x4200))
+    }
+
+    #[test]
+    fn detects_multicart() {
+        assert!(MBC1::new(&rom(true), 1024, 0).multicart);
+        assert!(!MBC1::new(&rom(false), 1024, 0).multicart);
+
+        // Only 1 MiB images are multicarts
+        assert!(!MBC1::new(&rom(true)[..0x8_0000], 512, 0).multicart);
+    }
+
+    #[test]
+    fn multicart_bank_wiring() {
+        let mut mbc1 = MBC1::new(&rom(true), 1024, 0);
+
+        // Bit 4 of the bank number isn't connected, the upper bits start at bit 4
+        mbc1.write(0x2000, 0x12);
+        mbc1.write(0x4000, 0x01);
+        assert_eq!(banks(&mbc1), (0x00, 0x12));
+
+        mbc1.write(0x6000, 0x01);
+        assert_eq!(banks(&mbc1), (0x10, 0x12));
+
+        mbc1.write(0x4000, 0x03);
+        assert_eq!(banks(&mbc1), (0x30, 0x32));
+    }
+
+    #[test]
+    fn regular_bank_wiring() {
+        let mut mbc1 = MBC1::new(&rom(false), 1024, 0);
+
+        mbc1.write(0x2000, 0x12);
+        mbc1.write(0x4000, 0x01);
+        assert_eq!(banks(&mbc1), (0x00, 0x32));
+
+        mbc1.write(0x6000, 0x01);
+        assert_eq!(banks(&mbc1), (0x20, 0x32));
+    }
+
+    #[test]
+    fn ram_banking_needs_more_than_8_kib() {
+        // 1 MiB ROM with 8 KiB RAM: the bank bits select ROM, RAM stays at bank 0
+        let mut mbc1 = MBC1::new(&rom(false), 1024, 8);
+        mbc1.write(0x0000, 0x0A);
+        mbc1.write(0x4000, 0x02);
+        mbc1.write(0x6000, 0x01);
+
+        mbc1.write(0xA000, 0x42);
+        assert_eq!(mbc1.peek(0xA000), 0x42);
+        assert_eq!(mbc1.external_ram[0][0], 0x42);
+
+        // 32 KiB RAM is banked in mode 1
+        let mut mbc1 = MBC1::new(&rom(false)[..0x8_0000], 512, 32);
+        mbc1.write(0x0000, 0x0A);
+        mbc1.write(0x4000, 0x02);
+        mbc1.write(0x6000, 0x01);
+
+        mbc1.write(0xA000, 0x42);
+        assert_eq!(mbc1.external_ram[2][0], 0x42);
+        assert_eq!(mbc1.external_ram[0][0], 0xFF);
+
+        mbc1.write(0x6000, 0x00);
+        assert_eq!(mbc1.peek(0xA000), 0xFF);
+    }
+}
@@ -1,4 +1,7 @@
-use crate::mmu::mmio::MMIO;
+use crate::{
+    mmu::mmio::MMIO,
+    save_state::{Snapshot, StateError, StateReader, StateWriter},
+};
 
 #[derive(PartialEq, Eq)]
 pub struct MBC2 {
@@ -19,17 +22,43 @@ impl MBC2 {
             ram_enable: false,
         }
     }
+
+    /// ROM bank mapped to 0x4000 - 0x7FFF.
+    pub fn rom_bank(&self) -> usize {
+        self.rom_bank as usize
+    }
+
+    /// Writes the ROM byte or RAM half byte that is mapped at `address`, without touching the registers.
+    ///
+    /// Nothing is written while RAM is disabled, just like `peek` doesn't see it.
+    pub fn poke(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x7FFF => {
+                let index = self.rom_index(address);
+                self.rom[index] = value;
+            }
+            0xA000..=0xBFFF if self.ram_enable => {
+                self.built_in_ram[address as usize & 0x1FF] = value & 0xF;
+            }
+            _ => {}
+        }
+    }
+
+    fn rom_index(&self, address: u16) -> usize {
+        if address < 0x4000 {
+            return address as usize;
+        }
+
+        let address = (self.rom_bank as usize * 0x4000) + (address as usize - 0x4000);
+        address & (self.rom.len() - 1)
+    }
 }
 
 impl MMIO for MBC2 {
     #[inline(always)]
-    fn read(&mut self, address: u16) -> u8 {
+    fn peek(&self, address: u16) -> u8 {
         match address {
-            0x0000..=0x3FFF => self.rom[address as usize],
-            0x4000..=0x7FFF => {
-                let address = (self.rom_bank as u32 * 0x4000) + (address as u32 - 0x4000);
-                self.rom[address as usize & (self.rom.len() - 1)]
-            }
+            0x0000..=0x7FFF => self.rom[self.rom_index(address)],
             // upper 4 bits ignored but mooneye tests expects them to be open bus (1)
             0xA000..=0xA1FF => {
                 if self.ram_enable {
@@ -76,3 +105,19 @@ impl MMIO for MBC2 {
         }
     }
 }
+
+impl Snapshot for MBC2 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.rom_bank);
+        writer.write_bool(self.ram_enable);
+
+        writer.write_bytes(&self.built_in_ram);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.rom_bank = reader.read_u8()?;
+        self.ram_enable = reader.read_bool()?;
+
+        reader.read_into(&mut self.built_in_ram)
+    }
+}
@@ -1,11 +1,28 @@
-use crate::mmu::mmio::MMIO;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{
+    mmu::mmio::MMIO,
+    save_state::{Snapshot, StateError, StateReader, StateWriter},
+};
+
+/// T-cycles per second, the RTC runs off its own 32.768 kHz crystal
+/// so this does not change in double speed mode.
+pub const CYCLES_PER_SECOND: u32 = 4_194_304;
+
+/// Seconds since the UNIX epoch, used to let the clock catch up
+/// on the time that passed while the emulator was closed.
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |t| t.as_secs())
+}
 
 #[derive(PartialEq, Eq)]
 pub struct MBC3 {
     pub rom: Vec<u8>,
     pub external_ram: Vec<[u8; 0x2000]>,
 
-    rtc: RealTimeClock,
+    rtc: Option<RealTimeClock>,
 
     ram_timer_enable: bool,
     rom_bank_number: u8,
@@ -13,48 +30,135 @@ pub struct MBC3 {
     latch_data: u8,
 }
 
-#[derive(Default, PartialEq, Eq)]
-struct RealTimeClock {
+/// Counter registers as they are mapped at 0x08 - 0x0C.
+#[derive(Default, Clone, Copy, PartialEq, Eq)]
+struct RtcRegisters {
     seconds: u8,
     minutes: u8,
     hours: u8,
 
+    /// Lower 8 bits of the day counter
     dl: u8,
+    /// Bit 0: day counter bit 8, Bit 6: halt, Bit 7: day counter carry
     dh: u8,
 }
 
+impl RtcRegisters {
+    fn days(&self) -> u64 {
+        ((self.dh as u64 & 1) << 8) | self.dl as u64
+    }
+
+    fn halted(&self) -> bool {
+        self.dh & 0x40 != 0
+    }
+
+    /// Lets `seconds` pass, overflowing into the next higher counter.
+    ///
+    /// The carry bit is set once the 9 bit day counter overflows
+    /// and stays set until the game clears it.
+    fn advance(&mut self, seconds: u64) {
+        let seconds = self.seconds as u64 + seconds;
+        let minutes = self.minutes as u64 + seconds / 60;
+        let hours = self.hours as u64 + minutes / 60;
+        let days = self.days() + hours / 24;
+
+        self.seconds = (seconds % 60) as u8;
+        self.minutes = (minutes % 60) as u8;
+        self.hours = (hours % 24) as u8;
+        self.dl = days as u8;
+        self.dh = (self.dh & 0xFE) | ((days >> 8) & 1) as u8;
+
+        if days > 0x1FF {
+            self.dh |= 0x80;
+        }
+    }
+
+    fn to_bytes(self) -> [u8; 5] {
+        [self.seconds, self.minutes, self.hours, self.dl, self.dh]
+    }
+
+    fn from_bytes(bytes: [u8; 5]) -> Self {
+        Self {
+            seconds: bytes[0] & 0x3F,
+            minutes: bytes[1] & 0x3F,
+            hours: bytes[2] & 0x1F,
+            dl: bytes[3],
+            dh: bytes[4] & 0xC1,
+        }
+    }
+}
+
+/// The clock keeps counting in `live`, games only ever
+/// read the copy that was `latched` last.
+#[derive(Default, PartialEq, Eq)]
+struct RealTimeClock {
+    live: RtcRegisters,
+    latched: RtcRegisters,
+
+    /// T-cycles since the last full second
+    sub_second: u32,
+}
+
+impl RealTimeClock {
+    fn tick(&mut self, cycles: u16) {
+        if self.live.halted() {
+            return;
+        }
+
+        self.sub_second += cycles as u32;
+        if self.sub_second >= CYCLES_PER_SECOND {
+            self.sub_second -= CYCLES_PER_SECOND;
+            self.live.advance(1);
+        }
+    }
+
+    fn latch(&mut self) {
+        self.latched = self.live;
+    }
+}
+
 impl MMIO for RealTimeClock {
-    fn read(&mut self, address: u16) -> u8 {
+    fn peek(&self, address: u16) -> u8 {
         // no masking on read as we mask on write already
         match address {
-            0x08 => self.seconds,
-            0x09 => self.minutes,
-            0x0A => self.hours,
-            0x0B => self.dl,
-            0x0C => self.dh,
+            0x08 => self.latched.seconds,
+            0x09 => self.latched.minutes,
+            0x0A => self.latched.hours,
+            0x0B => self.latched.dl,
+            0x0C => self.latched.dh,
             _ => unreachable!(),
         }
     }
 
+    /// Writes go to the running clock, the latched copy is
+    /// updated as well so that games can read back what they wrote.
     fn write(&mut self, address: u16, value: u8) {
         match address {
-            0x08 => self.seconds = value & 0x3B,
-            0x09 => self.minutes = value & 0x3B,
-            0x0A => self.hours = value & 0x17,
-            0x0B => self.dl = value,
-            0x0C => self.dh = value,
+            0x08 => {
+                self.live.seconds = value & 0x3F;
+                self.sub_second = 0;
+            }
+            0x09 => self.live.minutes = value & 0x3F,
+            0x0A => self.live.hours = value & 0x1F,
+            0x0B => self.live.dl = value,
+            0x0C => self.live.dh = value & 0xC1,
             _ => unreachable!(),
         }
+
+        self.latch();
     }
 }
 
 impl MBC3 {
-    pub fn new(rom: &[u8]) -> Self {
+    pub fn new(rom: &[u8], ram_size: u8) -> Self {
+        // Only MBC3+TIMER+BATTERY and MBC3+TIMER+RAM+BATTERY have the clock
+        let rtc = matches!(rom.get(0x0147), Some(0x0F | 0x10)).then(RealTimeClock::default);
+
         MBC3 {
             rom: rom.to_vec(),
-            external_ram: vec![[0xFF; 0x2000]; 4],
+            external_ram: vec![[0xFF; 0x2000]; (ram_size / 8) as usize],
 
-            rtc: RealTimeClock::default(),
+            rtc,
 
             ram_timer_enable: false,
             rom_bank_number: 0x01,
@@ -62,29 +166,121 @@ impl MBC3 {
             latch_data: 0x00,
         }
     }
+
+    /// ROM bank mapped to 0x4000 - 0x7FFF.
+    pub fn rom_bank(&self) -> usize {
+        self.rom_bank_number as usize
+    }
+
+    /// Advances the clock by `cycles` T-cycles of real (not double speed) time.
+    pub fn tick(&mut self, cycles: u16) {
+        if let Some(rtc) = &mut self.rtc {
+            rtc.tick(cycles);
+        }
+    }
+
+    pub fn has_rtc(&self) -> bool {
+        self.rtc.is_some()
+    }
+
+    /// Clock state in the 48 byte footer format most emulators append to `.sav` files:
+    /// live and latched registers as 32 bit values followed by a 64 bit UNIX timestamp.
+    pub fn dump_rtc(&self) -> Option<[u8; 48]> {
+        let rtc = self.rtc.as_ref()?;
+        let mut footer = [0; 48];
+
+        let registers = rtc
+            .live
+            .to_bytes()
+            .into_iter()
+            .chain(rtc.latched.to_bytes());
+        for (i, value) in registers.enumerate() {
+            footer[i * 4] = value;
+        }
+
+        footer[40..].copy_from_slice(&unix_time().to_le_bytes());
+
+        Some(footer)
+    }
+
+    /// Restores the clock from a `.sav` footer (see `dump_rtc`) and lets
+    /// the time pass that went by in the real world since it was written.
+    ///
+    /// The older 44 byte variant with a 32 bit timestamp is accepted as well.
+    pub fn load_rtc(&mut self, footer: &[u8]) {
+        let Some(rtc) = &mut self.rtc else {
+            return;
+        };
+
+        let timestamp = match footer.len() {
+            44 => u32::from_le_bytes(footer[40..44].try_into().unwrap()) as u64,
+            48 => u64::from_le_bytes(footer[40..48].try_into().unwrap()),
+            _ => return,
+        };
+
+        let register = |i: usize| footer[i * 4];
+        rtc.live = RtcRegisters::from_bytes([0, 1, 2, 3, 4].map(register));
+        rtc.latched = RtcRegisters::from_bytes([5, 6, 7, 8, 9].map(register));
+        rtc.sub_second = 0;
+
+        if !rtc.live.halted() {
+            rtc.live.advance(unix_time().saturating_sub(timestamp));
+        }
+    }
+
+    /// Writes the ROM or RAM byte that is mapped at `address`, without touching the registers.
+    ///
+    /// Nothing is written while RAM is disabled or the clock is mapped instead.
+    pub fn poke(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x7FFF => {
+                let index = self.rom_index(address);
+                self.rom[index] = value;
+            }
+            0xA000..=0xBFFF if self.ram_timer_enable && self.ram_bank_rtc <= 0x03 => {
+                if let Some(bank) = self.ram_bank() {
+                    self.external_ram[bank][(address - 0xA000) as usize] = value;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn rom_index(&self, address: u16) -> usize {
+        if address < 0x4000 {
+            return address as usize;
+        }
+
+        (self.rom_bank_number as usize) * 0x4000 + (address as usize - 0x4000)
+    }
+
+    /// RAM is mirrored if the cartridge has fewer than four banks.
+    fn ram_bank(&self) -> Option<usize> {
+        let banks = self.external_ram.len();
+        if banks == 0 {
+            return None;
+        }
+
+        Some(self.ram_bank_rtc as usize & (banks - 1))
+    }
 }
 
 impl MMIO for MBC3 {
     #[inline(always)]
-    fn read(&mut self, address: u16) -> u8 {
+    fn peek(&self, address: u16) -> u8 {
         match address {
-            0x0000..=0x3FFF => self.rom[address as usize],
-            0x4000..=0x7FFF => {
-                let address = (self.rom_bank_number as u32) * 0x4000 + (address as u32 - 0x4000);
-                self.rom[address as usize]
-            }
+            0x0000..=0x7FFF => self.rom[self.rom_index(address)],
             0xA000..=0xBFFF => {
-                if self.ram_timer_enable {
-                    if self.ram_bank_rtc <= 0x03 {
-                        self.external_ram[(self.ram_bank_rtc & 0x03) as usize]
-                            [(address - 0xA000) as usize]
-                    } else if self.ram_bank_rtc >= 0x08 && self.ram_bank_rtc <= 0x0C {
-                        self.rtc.read(self.ram_bank_rtc as u16)
-                    } else {
-                        0xFF
-                    }
-                } else {
-                    0xFF
+                if !self.ram_timer_enable {
+                    return 0xFF;
+                }
+
+                match (self.ram_bank_rtc, &self.rtc) {
+                    (0x00..=0x03, _) => self.ram_bank().map_or(0xFF, |bank| {
+                        self.external_ram[bank][(address - 0xA000) as usize]
+                    }),
+                    (0x08..=0x0C, Some(rtc)) => rtc.peek(self.ram_bank_rtc as u16),
+                    _ => 0xFF,
                 }
             }
             _ => unreachable!(),
@@ -101,21 +297,215 @@ impl MMIO for MBC3 {
                 self.ram_bank_rtc = value;
             }
             0x6000..=0x7FFF => {
-                // TODO: Latch Clock Data
+                // Writing 0x00 and then 0x01 copies the running clock into the latched registers
+                if self.latch_data == 0x00 && value == 0x01 {
+                    if let Some(rtc) = &mut self.rtc {
+                        rtc.latch();
+                    }
+                }
+
+                self.latch_data = value;
             }
             0xA000..=0xBFFF => {
-                if self.ram_timer_enable {
-                    if self.ram_bank_rtc <= 0x03 {
-                        self.external_ram[(self.ram_bank_rtc & 0x03) as usize]
-                            [(address - 0xA000) as usize] = value;
-                    } else {
-                        if self.ram_bank_rtc >= 0x08 && self.ram_bank_rtc <= 0x0C {
-                            self.rtc.write(self.ram_bank_rtc as u16, value);
+                if !self.ram_timer_enable {
+                    return;
+                }
+
+                match (self.ram_bank_rtc, &mut self.rtc) {
+                    (0x00..=0x03, _) => {
+                        if let Some(bank) = self.ram_bank() {
+                            self.external_ram[bank][(address - 0xA000) as usize] = value;
                         }
                     }
+                    (0x08..=0x0C, Some(rtc)) => rtc.write(self.ram_bank_rtc as u16, value),
+                    _ => {}
                 }
             }
             _ => {}
         }
     }
 }
+
+impl Snapshot for MBC3 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        if let Some(rtc) = &self.rtc {
+            writer.write_bytes(&rtc.live.to_bytes());
+            writer.write_bytes(&rtc.latched.to_bytes());
+            writer.write_u32(rtc.sub_second);
+        }
+
+        writer.write_bool(self.ram_timer_enable);
+        writer.write_u8(self.rom_bank_number);
+        writer.write_u8(self.ram_bank_rtc);
+        writer.write_u8(self.latch_data);
+
+        writer.write_bytes(&self.external_ram.concat());
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        if let Some(rtc) = &mut self.rtc {
+            rtc.live = RtcRegisters::from_bytes(reader.read_bytes(5)?.try_into().unwrap());
+            rtc.latched = RtcRegisters::from_bytes(reader.read_bytes(5)?.try_into().unwrap());
+            rtc.sub_second = reader.read_u32()?;
+        }
+
+        self.ram_timer_enable = reader.read_bool()?;
+        self.rom_bank_number = reader.read_u8()?;
+        self.ram_bank_rtc = reader.read_u8()?;
+        self.latch_data = reader.read_u8()?;
+
+        for bank in &mut self.external_ram {
+            reader.read_into(bank)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{unix_time, CYCLES_PER_SECOND, MBC3};
+    use crate::{
+        cartridge::base_cartridge::{Cartridge, CartridgeType},
+        mmu::mmio::MMIO,
+    };
+
+    /// MBC3+TIMER+RAM+BATTERY with 32 KiB of RAM, clock registers enabled.
+    fn mbc3() -> MBC3 {
+        let mut rom = vec![0; 0x8000];
+        rom[0x147] = 0x10;
+
+        let mut mbc3 = MBC3::new(&rom, 32);
+        mbc3.write(0x0000, 0x0A);
+        mbc3
+    }
+
+    fn set_register(mbc3: &mut MBC3, register: u8, value: u8) {
+        mbc3.write(0x4000, register);
+        mbc3.write(0xA000, value);
+    }
+
+    fn register(mbc3: &mut MBC3, register: u8) -> u8 {
+        mbc3.write(0x4000, register);
+        mbc3.peek(0xA000)
+    }
+
+    fn latch(mbc3: &mut MBC3) {
+        mbc3.write(0x6000, 0x00);
+        mbc3.write(0x6000, 0x01);
+    }
+
+    fn tick_seconds(mbc3: &mut MBC3, seconds: u32) {
+        for _ in 0..seconds * (CYCLES_PER_SECOND / 0x8000) {
+            mbc3.tick(0x8000);
+        }
+    }
+
+    #[test]
+    fn latches_on_0_then_1() {
+        let mut mbc3 = mbc3();
+        mbc3.write(0x6000, 0x00);
+
+        tick_seconds(&mut mbc3, 1);
+        assert_eq!(register(&mut mbc3, 0x08), 0);
+
+        mbc3.write(0x6000, 0x01);
+        assert_eq!(register(&mut mbc3, 0x08), 1);
+
+        // 1 -> 1 is no latch
+        tick_seconds(&mut mbc3, 1);
+        mbc3.write(0x6000, 0x01);
+        assert_eq!(register(&mut mbc3, 0x08), 1);
+
+        latch(&mut mbc3);
+        assert_eq!(register(&mut mbc3, 0x08), 2);
+    }
+
+    #[test]
+    fn halt_freezes_clock() {
+        let mut mbc3 = mbc3();
+        set_register(&mut mbc3, 0x08, 30);
+        set_register(&mut mbc3, 0x0C, 0x40);
+
+        tick_seconds(&mut mbc3, 3);
+        latch(&mut mbc3);
+        assert_eq!(register(&mut mbc3, 0x08), 30);
+
+        set_register(&mut mbc3, 0x0C, 0x00);
+        tick_seconds(&mut mbc3, 3);
+        latch(&mut mbc3);
+        assert_eq!(register(&mut mbc3, 0x08), 33);
+    }
+
+    #[test]
+    fn day_counter_carries_into_dh() {
+        let mut mbc3 = mbc3();
+        set_register(&mut mbc3, 0x08, 59);
+        set_register(&mut mbc3, 0x09, 59);
+        set_register(&mut mbc3, 0x0A, 23);
+        set_register(&mut mbc3, 0x0B, 0xFF);
+        set_register(&mut mbc3, 0x0C, 0x01);
+
+        tick_seconds(&mut mbc3, 1);
+        latch(&mut mbc3);
+
+        let registers = [0x08, 0x09, 0x0A, 0x0B, 0x0C].map(|r| register(&mut mbc3, r));
+        assert_eq!(registers, [0, 0, 0, 0x00, 0x80]);
+
+        // The carry stays until the game clears it
+        tick_seconds(&mut mbc3, 1);
+        latch(&mut mbc3);
+        assert_eq!(register(&mut mbc3, 0x0C), 0x80);
+    }
+
+    /// A save with 32 KiB of RAM and an RTC footer of `len` bytes for `timestamp`.
+    fn save(len: usize, live: [u8; 5], latched: [u8; 5], timestamp: u64) -> Vec<u8> {
+        let mut save = vec![0x55; 0x8000];
+        let mut footer = vec![0; len];
+
+        for (i, value) in live.into_iter().chain(latched).enumerate() {
+            footer[i * 4] = value;
+        }
+        footer[40..].copy_from_slice(&timestamp.to_le_bytes()[..len - 40]);
+
+        save.extend(footer);
+        save
+    }
+
+    #[test]
+    fn loads_48_byte_footer() {
+        let mut cartridge = Cartridge::new(CartridgeType::MBC3(mbc3()), "");
+        // Halted, so no time passes while loading
+        let live = [1, 2, 3, 4, 0x41];
+        let latched = [5, 6, 7, 8, 0x00];
+        cartridge.load_sram(&save(48, live, latched, unix_time()));
+
+        let dump = cartridge.dump_sram().unwrap();
+        assert_eq!(dump.len(), 0x8000 + 48);
+        assert!(dump[..0x8000].iter().all(|&b| b == 0x55));
+        assert_eq!(
+            dump[0x8000..0x8000 + 40],
+            save(48, live, latched, 0)[0x8000..0x8000 + 40]
+        );
+
+        let CartridgeType::MBC3(mbc3) = &mut cartridge.cartridge_type else {
+            unreachable!()
+        };
+        assert_eq!(register(mbc3, 0x0A), 7);
+        latch(mbc3);
+        assert_eq!([0x08, 0x0C].map(|r| register(mbc3, r)), [1, 0x41]);
+    }
+
+    #[test]
+    fn loads_44_byte_footer_and_catches_up() {
+        let mut cartridge = Cartridge::new(CartridgeType::MBC3(mbc3()), "");
+        cartridge.load_sram(&save(44, [0; 5], [0; 5], unix_time() - 120));
+
+        let CartridgeType::MBC3(mbc3) = &mut cartridge.cartridge_type else {
+            unreachable!()
+        };
+        latch(mbc3);
+        assert_eq!(register(mbc3, 0x09), 2);
+        assert!(register(mbc3, 0x08) <= 1);
+    }
+}
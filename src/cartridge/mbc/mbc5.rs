@@ -1,4 +1,7 @@
-use crate::mmu::mmio::MMIO;
+use crate::{
+    mmu::mmio::MMIO,
+    save_state::{Snapshot, StateError, StateReader, StateWriter},
+};
 
 #[derive(PartialEq, Eq)]
 pub struct MBC5 {
@@ -12,6 +15,13 @@ pub struct MBC5 {
     rom_bank_number: u8,
     rom_bank_bit9: u8,
     ram_bank_number: u8,
+
+    /// MBC5+RUMBLE: bit 3 of the RAM bank register drives the motor
+    rumble: bool,
+    motor_on: bool,
+    /// T-cycles the motor was on / passed since the last `take_rumble_duty`
+    motor_cycles: u32,
+    elapsed_cycles: u32,
 }
 
 impl MBC5 {
@@ -27,30 +37,97 @@ impl MBC5 {
             rom_bank_number: 0x01,
             rom_bank_bit9: 0x00,
             ram_bank_number: 0x00,
+
+            rumble: matches!(rom.get(0x0147), Some(0x1C..=0x1E)),
+            motor_on: false,
+            motor_cycles: 0,
+            elapsed_cycles: 0,
         }
     }
-}
 
-impl MMIO for MBC5 {
-    #[inline(always)]
-    fn read(&mut self, address: u16) -> u8 {
-        match address {
-            0x0000..=0x3FFF => self.rom[address as usize],
-            0x4000..=0x7FFF => {
-                let rom_bank: u16 =
-                    ((self.rom_bank_bit9 as u16) << 8) | (self.rom_bank_number as u16);
+    /// ROM bank mapped to 0x4000 - 0x7FFF.
+    pub fn rom_bank(&self) -> usize {
+        ((self.rom_bank_bit9 as usize) << 8) | self.rom_bank_number as usize
+    }
 
-                let address = (rom_bank as u32 * 0x4000) + (address as u32 - 0x4000);
-                self.rom[address as usize & (self.rom.len() - 1)]
+    /// Writes the ROM or RAM byte that is mapped at `address`, without touching the registers.
+    ///
+    /// Nothing is written while RAM is disabled, just like `peek` doesn't see it.
+    pub fn poke(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x7FFF => {
+                let index = self.rom_index(address);
+                self.rom[index] = value;
             }
             0xA000..=0xBFFF => {
-                if self.ram_enable {
-                    let l = self.external_ram.len() - 1;
-                    self.external_ram[self.ram_bank_number as usize & l][address as usize - 0xA000]
-                } else {
-                    0xFF
+                if let Some((bank, i)) = self.ram_index(address) {
+                    self.external_ram[bank][i] = value;
                 }
             }
+            _ => {}
+        }
+    }
+
+    fn rom_index(&self, address: u16) -> usize {
+        if address < 0x4000 {
+            return address as usize;
+        }
+
+        let address = self.rom_bank() * 0x4000 + (address as usize - 0x4000);
+        address & (self.rom.len() - 1)
+    }
+
+    fn ram_index(&self, address: u16) -> Option<(usize, usize)> {
+        let banks = self.external_ram.len();
+        if !self.ram_enable || banks == 0 {
+            return None;
+        }
+
+        Some((
+            self.ram_bank_number as usize & (banks - 1),
+            address as usize - 0xA000,
+        ))
+    }
+
+    pub fn has_rumble(&self) -> bool {
+        self.rumble
+    }
+
+    pub fn tick(&mut self, cycles: u16) {
+        if self.rumble {
+            self.elapsed_cycles += cycles as u32;
+            if self.motor_on {
+                self.motor_cycles += cycles as u32;
+            }
+        }
+    }
+
+    /// Fraction of time the motor was on since the last call.
+    ///
+    /// Games toggle the motor quickly to vary its strength,
+    /// so this duty cycle is what the player feels.
+    pub fn take_rumble_duty(&mut self) -> f32 {
+        let duty = if self.elapsed_cycles == 0 {
+            self.motor_on as u8 as f32
+        } else {
+            self.motor_cycles as f32 / self.elapsed_cycles as f32
+        };
+
+        self.motor_cycles = 0;
+        self.elapsed_cycles = 0;
+
+        duty
+    }
+}
+
+impl MMIO for MBC5 {
+    #[inline(always)]
+    fn peek(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x7FFF => self.rom[self.rom_index(address)],
+            0xA000..=0xBFFF => self
+                .ram_index(address)
+                .map_or(0xFF, |(bank, i)| self.external_ram[bank][i]),
             _ => unreachable!(),
         }
     }
@@ -62,15 +139,46 @@ impl MMIO for MBC5 {
             0x0000..=0x1FFF => self.ram_enable = (value & 0xF) == 0xA,
             0x2000..=0x2FFF => self.rom_bank_number = value,
             0x3000..=0x3FFF => self.rom_bank_bit9 = value & 0x1,
-            0x4000..=0x5FFF => self.ram_bank_number = value & 0x0F,
+            0x4000..=0x5FFF => {
+                if self.rumble {
+                    self.motor_on = value & 0x08 != 0;
+                    self.ram_bank_number = value & 0x07;
+                } else {
+                    self.ram_bank_number = value & 0x0F;
+                }
+            }
             0xA000..=0xBFFF => {
-                if self.ram_enable {
-                    let l = self.external_ram.len() - 1;
-                    self.external_ram[self.ram_bank_number as usize & l]
-                        [address as usize - 0xA000] = value;
+                if let Some((bank, i)) = self.ram_index(address) {
+                    self.external_ram[bank][i] = value;
                 }
             }
             _ => {}
         }
     }
 }
+
+impl Snapshot for MBC5 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.ram_enable);
+        writer.write_u8(self.rom_bank_number);
+        writer.write_u8(self.rom_bank_bit9);
+        writer.write_u8(self.ram_bank_number);
+        writer.write_bool(self.motor_on);
+
+        writer.write_bytes(&self.external_ram.concat());
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.ram_enable = reader.read_bool()?;
+        self.rom_bank_number = reader.read_u8()?;
+        self.rom_bank_bit9 = reader.read_u8()?;
+        self.ram_bank_number = reader.read_u8()?;
+        self.motor_on = reader.read_bool()?;
+
+        for bank in &mut self.external_ram {
+            reader.read_into(bank)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,352 @@
+use crate::{
+    mmu::mmio::MMIO,
+    save_state::{Snapshot, StateError, StateReader, StateWriter},
+};
+
+const FLASH_SIZE: usize = 0x10_0000;
+const FLASH_SECTOR_SIZE: usize = 0x2_0000;
+
+/// Mapper of Net de Get: Minigame @ 100. ROM, flash and RAM are banked in
+/// halves, 0x4000 - 0x5FFF / 0xA000 - 0xAFFF (A) and 0x6000 - 0x7FFF / 0xB000 - 0xBFFF (B).
+#[derive(PartialEq, Eq)]
+pub struct MBC6 {
+    pub rom: Vec<u8>,
+    pub external_ram: Vec<[u8; 0x1000]>,
+    /// 1 MiB of flash the game downloads minigames into
+    pub flash: Vec<u8>,
+
+    ram_enable: bool,
+    ram_bank_a: u8,
+    ram_bank_b: u8,
+
+    /// 8 KiB bank numbers and whether they select flash instead of ROM
+    rom_bank_a: u8,
+    rom_bank_b: u8,
+    flash_select_a: bool,
+    flash_select_b: bool,
+
+    flash_enable: bool,
+    flash_write_enable: bool,
+    flash_state: FlashState,
+}
+
+/// Progress of the JEDEC style command sequence of the flash chip.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum FlashState {
+    Read,
+    /// 0xAA was written to 0x5555
+    Unlock1,
+    /// 0x55 was written to 0x2AAA, the next write is the command
+    Unlock2,
+    /// Returns manufacturer and device ID instead of data
+    Id,
+    /// The next write programs a byte
+    Program,
+    /// 0x80 started an erase, which needs to be unlocked again
+    Erase,
+    EraseUnlock1,
+    EraseUnlock2,
+}
+
+impl MBC6 {
+    pub fn new(rom: &[u8], ram_size: u8) -> Self {
+        Self {
+            rom: rom.to_vec(),
+            external_ram: vec![[0xFF; 0x1000]; (ram_size / 4) as usize],
+            flash: vec![0xFF; FLASH_SIZE],
+
+            ram_enable: false,
+            ram_bank_a: 0x00,
+            ram_bank_b: 0x00,
+
+            rom_bank_a: 0x00,
+            rom_bank_b: 0x00,
+            flash_select_a: false,
+            flash_select_b: false,
+
+            flash_enable: false,
+            flash_write_enable: false,
+            flash_state: FlashState::Read,
+        }
+    }
+
+    /// Bank number and whether it is flash for 0x4000 - 0x7FFF.
+    pub fn rom_bank(&self, address: u16) -> (usize, bool) {
+        if address < 0x6000 {
+            (self.rom_bank_a as usize, self.flash_select_a)
+        } else {
+            (self.rom_bank_b as usize, self.flash_select_b)
+        }
+    }
+
+    /// Writes the ROM, flash or RAM byte that is mapped at `address`, without touching
+    /// the registers or going through the command sequence of the flash chip.
+    ///
+    /// Nothing is written while RAM or flash is disabled, just like `peek` doesn't see it.
+    pub fn poke(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x3FFF => self.rom[address as usize] = value,
+            0x4000..=0x7FFF => {
+                let (bank, flash) = self.rom_bank(address);
+                let address = bank * 0x2000 + (address as usize & 0x1FFF);
+
+                if !flash {
+                    let len = self.rom.len();
+                    self.rom[address & (len - 1)] = value;
+                } else if self.flash_enable {
+                    self.flash[address & (FLASH_SIZE - 1)] = value;
+                }
+            }
+            0xA000..=0xBFFF => {
+                if let Some((bank, i)) = self.ram_index(address) {
+                    self.external_ram[bank][i] = value;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn ram_index(&self, address: u16) -> Option<(usize, usize)> {
+        let banks = self.external_ram.len();
+        if !self.ram_enable || banks == 0 {
+            return None;
+        }
+
+        let bank = if address < 0xB000 { self.ram_bank_a } else { self.ram_bank_b };
+
+        Some((bank as usize & (banks - 1), address as usize & 0xFFF))
+    }
+
+    fn read_flash(&self, flash_address: usize) -> u8 {
+        match self.flash_state {
+            // Macronix MX29F008
+            FlashState::Id => match flash_address & 0x1 {
+                0 => 0xC2,
+                _ => 0x81,
+            },
+            _ => self.flash[flash_address],
+        }
+    }
+
+    fn write_flash(&mut self, flash_address: usize, value: u8) {
+        if !self.flash_write_enable {
+            return;
+        }
+
+        if value == 0xF0 {
+            self.flash_state = FlashState::Read;
+            return;
+        }
+
+        let command_address = flash_address & 0x7FFF;
+        self.flash_state = match (self.flash_state, command_address, value) {
+            (FlashState::Read | FlashState::Id, 0x5555, 0xAA) => FlashState::Unlock1,
+            (FlashState::Unlock1, 0x2AAA, 0x55) => FlashState::Unlock2,
+            (FlashState::Unlock2, 0x5555, 0x90) => FlashState::Id,
+            (FlashState::Unlock2, 0x5555, 0xA0) => FlashState::Program,
+            (FlashState::Unlock2, 0x5555, 0x80) => FlashState::Erase,
+            (FlashState::Erase, 0x5555, 0xAA) => FlashState::EraseUnlock1,
+            (FlashState::EraseUnlock1, 0x2AAA, 0x55) => FlashState::EraseUnlock2,
+            (FlashState::EraseUnlock2, 0x5555, 0x10) => {
+                self.flash.fill(0xFF);
+                FlashState::Read
+            }
+            (FlashState::EraseUnlock2, _, 0x30) => {
+                let sector = flash_address & !(FLASH_SECTOR_SIZE - 1);
+                self.flash[sector..sector + FLASH_SECTOR_SIZE].fill(0xFF);
+                FlashState::Read
+            }
+            // Programming can only clear bits, erasing sets them again
+            (FlashState::Program, _, _) => {
+                self.flash[flash_address] &= value;
+                FlashState::Read
+            }
+            (FlashState::Id, _, _) => FlashState::Id,
+            _ => FlashState::Read,
+        };
+    }
+}
+
+impl MMIO for MBC6 {
+    #[inline(always)]
+    fn peek(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x3FFF => self.rom[address as usize],
+            0x4000..=0x7FFF => {
+                let (bank, flash) = self.rom_bank(address);
+                let address = bank * 0x2000 + (address as usize & 0x1FFF);
+
+                if flash {
+                    if self.flash_enable {
+                        self.read_flash(address & (FLASH_SIZE - 1))
+                    } else {
+                        0xFF
+                    }
+                } else {
+                    self.rom[address & (self.rom.len() - 1)]
+                }
+            }
+            0xA000..=0xBFFF => self
+                .ram_index(address)
+                .map_or(0xFF, |(bank, i)| self.external_ram[bank][i]),
+            _ => unreachable!(),
+        }
+    }
+
+    #[inline(always)]
+    fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x03FF => self.ram_enable = value == 0x0A,
+            0x0400..=0x07FF => self.ram_bank_a = value & 0x7,
+            0x0800..=0x0BFF => self.ram_bank_b = value & 0x7,
+            0x0C00..=0x0FFF => self.flash_enable = value & 0x1 != 0,
+            0x1000 => self.flash_write_enable = value & 0x1 != 0,
+            0x1001..=0x1FFF => {}
+            0x2000..=0x27FF => self.rom_bank_a = value & 0x7F,
+            0x2800..=0x2FFF => self.flash_select_a = value == 0x08,
+            0x3000..=0x37FF => self.rom_bank_b = value & 0x7F,
+            0x3800..=0x3FFF => self.flash_select_b = value == 0x08,
+            0x4000..=0x7FFF => {
+                let (bank, flash) = self.rom_bank(address);
+                if flash && self.flash_enable {
+                    let address = bank * 0x2000 + (address as usize & 0x1FFF);
+                    self.write_flash(address & (FLASH_SIZE - 1), value);
+                }
+            }
+            0xA000..=0xBFFF => {
+                if let Some((bank, i)) = self.ram_index(address) {
+                    self.external_ram[bank][i] = value;
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Snapshot for MBC6 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.ram_enable);
+        writer.write_u8(self.ram_bank_a);
+        writer.write_u8(self.ram_bank_b);
+
+        writer.write_u8(self.rom_bank_a);
+        writer.write_u8(self.rom_bank_b);
+        writer.write_bool(self.flash_select_a);
+        writer.write_bool(self.flash_select_b);
+
+        writer.write_bool(self.flash_enable);
+        writer.write_bool(self.flash_write_enable);
+        writer.write_u8(self.flash_state as u8);
+
+        writer.write_bytes(&self.external_ram.concat());
+        writer.write_bytes(&self.flash);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.ram_enable = reader.read_bool()?;
+        self.ram_bank_a = reader.read_u8()?;
+        self.ram_bank_b = reader.read_u8()?;
+
+        self.rom_bank_a = reader.read_u8()?;
+        self.rom_bank_b = reader.read_u8()?;
+        self.flash_select_a = reader.read_bool()?;
+        self.flash_select_b = reader.read_bool()?;
+
+        self.flash_enable = reader.read_bool()?;
+        self.flash_write_enable = reader.read_bool()?;
+        self.flash_state = match reader.read_u8()? {
+            0 => FlashState::Read,
+            1 => FlashState::Unlock1,
+            2 => FlashState::Unlock2,
+            3 => FlashState::Id,
+            4 => FlashState::Program,
+            5 => FlashState::Erase,
+            6 => FlashState::EraseUnlock1,
+            7 => FlashState::EraseUnlock2,
+            _ => return Err(StateError::InvalidValue("flash state")),
+        };
+
+        for bank in &mut self.external_ram {
+            reader.read_into(bank)?;
+        }
+        reader.read_into(&mut self.flash)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MBC6;
+    use crate::mmu::mmio::MMIO;
+
+    /// Flash enabled and writable, bank A maps flash 0x4000 - 0x5FFF and
+    /// bank B flash 0x2000 - 0x3FFF, so 0x5555 and 0x6AAA reach the command addresses.
+    fn mbc6() -> MBC6 {
+        let mut mbc6 = MBC6::new(&[0; 0x8000], 32);
+        mbc6.write(0x0C00, 0x01);
+        mbc6.write(0x1000, 0x01);
+        mbc6.write(0x2000, 0x02);
+        mbc6.write(0x2800, 0x08);
+        mbc6.write(0x3000, 0x01);
+        mbc6.write(0x3800, 0x08);
+        mbc6
+    }
+
+    fn unlock(mbc6: &mut MBC6) {
+        mbc6.write(0x5555, 0xAA);
+        mbc6.write(0x6AAA, 0x55);
+    }
+
+    fn program(mbc6: &mut MBC6, address: u16, value: u8) {
+        unlock(mbc6);
+        mbc6.write(0x5555, 0xA0);
+        mbc6.write(address, value);
+    }
+
+    #[test]
+    fn flash_reports_id() {
+        let mut mbc6 = mbc6();
+
+        unlock(&mut mbc6);
+        mbc6.write(0x5555, 0x90);
+        assert_eq!((mbc6.peek(0x4000), mbc6.peek(0x4001)), (0xC2, 0x81));
+
+        mbc6.write(0x4000, 0xF0);
+        assert_eq!(mbc6.peek(0x4000), 0xFF);
+    }
+
+    #[test]
+    fn flash_programs_and_erases_sectors() {
+        let mut mbc6 = mbc6();
+
+        program(&mut mbc6, 0x4010, 0x5A);
+        assert_eq!(mbc6.peek(0x4010), 0x5A);
+        assert_eq!(mbc6.flash[0x4010], 0x5A);
+
+        // Programming only clears bits
+        program(&mut mbc6, 0x4010, 0xA5);
+        assert_eq!(mbc6.peek(0x4010), 0x00);
+
+        // Writes without the unlock sequence are ignored
+        mbc6.write(0x4011, 0x00);
+        assert_eq!(mbc6.peek(0x4011), 0xFF);
+
+        mbc6.flash[0x2_0000] = 0x00;
+        unlock(&mut mbc6);
+        mbc6.write(0x5555, 0x80);
+        unlock(&mut mbc6);
+        mbc6.write(0x4010, 0x30);
+        assert_eq!(mbc6.peek(0x4010), 0xFF);
+        assert_eq!(mbc6.flash[0x2_0000], 0x00);
+    }
+
+    #[test]
+    fn flash_is_read_only_without_write_enable() {
+        let mut mbc6 = mbc6();
+        mbc6.write(0x1000, 0x00);
+
+        program(&mut mbc6, 0x4010, 0x00);
+        assert_eq!(mbc6.peek(0x4010), 0xFF);
+    }
+}
@@ -0,0 +1,548 @@
+use crate::{
+    mmu::mmio::MMIO,
+    save_state::{Snapshot, StateError, StateReader, StateWriter},
+};
+
+/// Accelerometer reading when the Game Boy lies flat.
+const ACCEL_CENTER: u16 = 0x81D0;
+/// Change of the reading per 1 g of tilt.
+const ACCEL_GRAVITY: f32 = 112.0;
+
+/// Mapper with a two axis accelerometer and a 93LC56 serial EEPROM (256 bytes),
+/// used by Kirby Tilt 'n' Tumble and Command Master.
+#[derive(PartialEq, Eq)]
+pub struct MBC7 {
+    pub rom: Vec<u8>,
+    pub eeprom: Eeprom,
+
+    ram_enable_1: bool,
+    ram_enable_2: bool,
+    rom_bank_number: u8,
+
+    /// Offset from `ACCEL_CENTER` the console is tilted by, set by the frontend
+    tilt: (i16, i16),
+    accel_x: u16,
+    accel_y: u16,
+    /// Set by writing 0x55 to 0xA00x, a following 0xAA to 0xA01x samples the sensor
+    accel_erased: bool,
+}
+
+impl MBC7 {
+    pub fn new(rom: &[u8]) -> Self {
+        Self {
+            rom: rom.to_vec(),
+            eeprom: Eeprom::default(),
+
+            ram_enable_1: false,
+            ram_enable_2: false,
+            rom_bank_number: 0x01,
+
+            tilt: (0, 0),
+            accel_x: ACCEL_CENTER,
+            accel_y: ACCEL_CENTER,
+            accel_erased: false,
+        }
+    }
+
+    /// ROM bank mapped to 0x4000 - 0x7FFF.
+    pub fn rom_bank(&self) -> usize {
+        self.rom_bank_number as usize
+    }
+
+    /// Tilt towards the right (`x`) and towards the player (`y`) in g, usually -1.0 to 1.0.
+    pub fn set_tilt(&mut self, x: f32, y: f32) {
+        self.tilt = ((-x * ACCEL_GRAVITY) as i16, (y * ACCEL_GRAVITY) as i16);
+    }
+
+    /// Patches the ROM byte that is mapped at `address`, without touching the registers.
+    ///
+    /// Accelerometer and EEPROM are only reachable through their serial protocol, so
+    /// 0xA000 - 0xBFFF can't be poked. The EEPROM contents are public in `eeprom`.
+    pub fn poke(&mut self, address: u16, value: u8) {
+        if address < 0x8000 {
+            let index = self.rom_index(address);
+            self.rom[index] = value;
+        }
+    }
+
+    fn rom_index(&self, address: u16) -> usize {
+        if address < 0x4000 {
+            return address as usize;
+        }
+
+        let address = (self.rom_bank_number as usize * 0x4000) + (address as usize - 0x4000);
+        address & (self.rom.len() - 1)
+    }
+
+    fn read_register(&self, address: u16) -> u8 {
+        match (address >> 4) & 0xF {
+            0x2 => self.accel_x as u8,
+            0x3 => (self.accel_x >> 8) as u8,
+            0x4 => self.accel_y as u8,
+            0x5 => (self.accel_y >> 8) as u8,
+            0x6 => 0x00,
+            0x8 => self.eeprom.read(),
+            _ => 0xFF,
+        }
+    }
+
+    fn write_register(&mut self, address: u16, value: u8) {
+        match (address >> 4) & 0xF {
+            0x0 if value == 0x55 => {
+                self.accel_erased = true;
+                self.accel_x = 0x8000;
+                self.accel_y = 0x8000;
+            }
+            0x1 if value == 0xAA && self.accel_erased => {
+                self.accel_erased = false;
+                self.accel_x = ACCEL_CENTER.wrapping_add_signed(self.tilt.0);
+                self.accel_y = ACCEL_CENTER.wrapping_add_signed(self.tilt.1);
+            }
+            0x8 => self.eeprom.write(value),
+            _ => {}
+        }
+    }
+}
+
+impl MMIO for MBC7 {
+    #[inline(always)]
+    fn peek(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x7FFF => self.rom[self.rom_index(address)],
+            0xA000..=0xAFFF if self.ram_enable_1 && self.ram_enable_2 => {
+                self.read_register(address)
+            }
+            0xA000..=0xBFFF => 0xFF,
+            _ => unreachable!(),
+        }
+    }
+
+    #[inline(always)]
+    fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enable_1 = value == 0x0A,
+            0x2000..=0x3FFF => self.rom_bank_number = value & 0x7F,
+            0x4000..=0x5FFF => self.ram_enable_2 = value == 0x40,
+            0x6000..=0x7FFF => {}
+            0xA000..=0xAFFF if self.ram_enable_1 && self.ram_enable_2 => {
+                self.write_register(address, value);
+            }
+            0xA000..=0xBFFF => {}
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum EepromState {
+    /// Waiting for the start bit
+    Idle,
+    /// Collecting the 2 bit opcode and 8 bit address
+    Command { bits: u16, count: u8 },
+    /// Shifting out a dummy 0 bit followed by the 16 bit word
+    Read { word: u16, count: u8 },
+    /// Collecting 16 data bits, `address == None` writes all words
+    Write {
+        address: Option<u8>,
+        bits: u16,
+        count: u8,
+    },
+}
+
+/// 93LC56 in 16 bit mode, driven bit by bit through 0xA080:
+/// Bit 7 chip select, bit 6 clock, bit 1 data in, bit 0 data out.
+#[derive(PartialEq, Eq)]
+pub struct Eeprom {
+    pub words: [u16; 128],
+
+    cs: bool,
+    clk: bool,
+    di: bool,
+    do_: bool,
+
+    write_enable: bool,
+    state: EepromState,
+}
+
+impl Default for Eeprom {
+    fn default() -> Self {
+        Self {
+            words: [0xFFFF; 128],
+
+            cs: false,
+            clk: false,
+            di: false,
+            do_: true,
+
+            write_enable: false,
+            state: EepromState::Idle,
+        }
+    }
+}
+
+impl Eeprom {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.words.iter().flat_map(|w| w.to_le_bytes()).collect()
+    }
+
+    pub fn load_bytes(&mut self, bytes: &[u8]) {
+        for (word, chunk) in self.words.iter_mut().zip(bytes.chunks_exact(2)) {
+            *word = u16::from_le_bytes([chunk[0], chunk[1]]);
+        }
+    }
+
+    fn read(&self) -> u8 {
+        ((self.cs as u8) << 7) | ((self.clk as u8) << 6) | ((self.di as u8) << 1) | self.do_ as u8
+    }
+
+    fn write(&mut self, value: u8) {
+        let cs = value & 0x80 != 0;
+        let clk = value & 0x40 != 0;
+        let rising_edge = clk && !self.clk;
+
+        self.di = value & 0x02 != 0;
+        self.clk = clk;
+
+        if !cs {
+            // Deselecting aborts any command, DO then signals ready
+            self.cs = false;
+            self.state = EepromState::Idle;
+            self.do_ = true;
+            return;
+        }
+
+        self.cs = true;
+        if rising_edge {
+            self.clock_in(self.di);
+        }
+    }
+
+    fn clock_in(&mut self, bit: bool) {
+        self.state = match self.state {
+            EepromState::Idle if bit => EepromState::Command { bits: 0, count: 0 },
+            EepromState::Idle => EepromState::Idle,
+            EepromState::Command { bits, count } => {
+                let bits = (bits << 1) | bit as u16;
+                if count + 1 < 10 {
+                    EepromState::Command {
+                        bits,
+                        count: count + 1,
+                    }
+                } else {
+                    self.execute(bits)
+                }
+            }
+            EepromState::Read { word, count } => {
+                self.do_ = word & (0x8000 >> count) != 0;
+                if count + 1 < 16 {
+                    EepromState::Read {
+                        word,
+                        count: count + 1,
+                    }
+                } else {
+                    EepromState::Idle
+                }
+            }
+            EepromState::Write {
+                address,
+                bits,
+                count,
+            } => {
+                let bits = (bits << 1) | bit as u16;
+                if count + 1 < 16 {
+                    EepromState::Write {
+                        address,
+                        bits,
+                        count: count + 1,
+                    }
+                } else {
+                    if self.write_enable {
+                        match address {
+                            Some(address) => self.words[address as usize] = bits,
+                            None => self.words.fill(bits),
+                        }
+                    }
+
+                    self.do_ = true;
+                    EepromState::Idle
+                }
+            }
+        };
+    }
+
+    /// `command` holds the opcode in bits 8 - 9 and the address in bits 0 - 7.
+    fn execute(&mut self, command: u16) -> EepromState {
+        let address = (command & 0x7F) as u8;
+
+        match (command >> 8) & 0x3 {
+            // READ, a dummy 0 bit precedes the data
+            0b10 => {
+                self.do_ = false;
+                EepromState::Read {
+                    word: self.words[address as usize],
+                    count: 0,
+                }
+            }
+            // WRITE
+            0b01 => EepromState::Write {
+                address: Some(address),
+                bits: 0,
+                count: 0,
+            },
+            // ERASE
+            0b11 => {
+                if self.write_enable {
+                    self.words[address as usize] = 0xFFFF;
+                }
+                EepromState::Idle
+            }
+            // Extended commands, selected by the upper two address bits
+            _ => match (command >> 6) & 0x3 {
+                // EWDS
+                0b00 => {
+                    self.write_enable = false;
+                    EepromState::Idle
+                }
+                // WRAL
+                0b01 => EepromState::Write {
+                    address: None,
+                    bits: 0,
+                    count: 0,
+                },
+                // ERAL
+                0b10 => {
+                    if self.write_enable {
+                        self.words.fill(0xFFFF);
+                    }
+                    EepromState::Idle
+                }
+                // EWEN
+                _ => {
+                    self.write_enable = true;
+                    EepromState::Idle
+                }
+            },
+        }
+    }
+}
+
+impl Snapshot for MBC7 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.ram_enable_1);
+        writer.write_bool(self.ram_enable_2);
+        writer.write_u8(self.rom_bank_number);
+
+        writer.write_u16(self.accel_x);
+        writer.write_u16(self.accel_y);
+        writer.write_bool(self.accel_erased);
+
+        let eeprom = &self.eeprom;
+        writer.write_bytes(&eeprom.to_bytes());
+        writer.write_bool(eeprom.cs);
+        writer.write_bool(eeprom.clk);
+        writer.write_bool(eeprom.di);
+        writer.write_bool(eeprom.do_);
+        writer.write_bool(eeprom.write_enable);
+
+        match eeprom.state {
+            EepromState::Idle => writer.write_u8(0),
+            EepromState::Command { bits, count } => {
+                writer.write_u8(1);
+                writer.write_u16(bits);
+                writer.write_u8(count);
+            }
+            EepromState::Read { word, count } => {
+                writer.write_u8(2);
+                writer.write_u16(word);
+                writer.write_u8(count);
+            }
+            EepromState::Write {
+                address,
+                bits,
+                count,
+            } => {
+                writer.write_u8(3);
+                writer.write_u16(address.map_or(0xFFFF, |a| a as u16));
+                writer.write_u16(bits);
+                writer.write_u8(count);
+            }
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.ram_enable_1 = reader.read_bool()?;
+        self.ram_enable_2 = reader.read_bool()?;
+        self.rom_bank_number = reader.read_u8()?;
+
+        self.accel_x = reader.read_u16()?;
+        self.accel_y = reader.read_u16()?;
+        self.accel_erased = reader.read_bool()?;
+
+        let eeprom = &mut self.eeprom;
+        eeprom.load_bytes(reader.read_bytes(256)?);
+        eeprom.cs = reader.read_bool()?;
+        eeprom.clk = reader.read_bool()?;
+        eeprom.di = reader.read_bool()?;
+        eeprom.do_ = reader.read_bool()?;
+        eeprom.write_enable = reader.read_bool()?;
+
+        eeprom.state = match reader.read_u8()? {
+            0 => EepromState::Idle,
+            1 => EepromState::Command {
+                bits: reader.read_u16()?,
+                count: reader.read_u8()?,
+            },
+            2 => EepromState::Read {
+                word: reader.read_u16()?,
+                count: reader.read_u8()?,
+            },
+            3 => EepromState::Write {
+                address: match reader.read_u16()? {
+                    0xFFFF => None,
+                    a => Some(a as u8),
+                },
+                bits: reader.read_u16()?,
+                count: reader.read_u8()?,
+            },
+            _ => return Err(StateError::InvalidValue("EEPROM state")),
+        };
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ACCEL_CENTER, MBC7};
+    use crate::mmu::mmio::MMIO;
+
+    /// MBC7 with both RAM enables set, so the registers are mapped.
+    fn mbc7() -> MBC7 {
+        let mut mbc7 = MBC7::new(&[0; 0x8000]);
+        mbc7.write(0x0000, 0x0A);
+        mbc7.write(0x4000, 0x40);
+        mbc7
+    }
+
+    /// Clocks `bit` into the EEPROM with chip select held and returns DO after the rising edge.
+    fn clock(mbc7: &mut MBC7, bit: bool) -> bool {
+        let di = (bit as u8) << 1;
+        mbc7.write(0xA080, 0x80 | di);
+        mbc7.write(0xA080, 0xC0 | di);
+
+        mbc7.peek(0xA080) & 0x1 != 0
+    }
+
+    fn clock_bits(mbc7: &mut MBC7, bits: u16, count: u8) {
+        for i in (0..count).rev() {
+            clock(mbc7, bits & (1 << i) != 0);
+        }
+    }
+
+    /// Start bit, 2 bit opcode and 8 bit address.
+    fn command(mbc7: &mut MBC7, opcode: u16, address: u8) {
+        clock(mbc7, true);
+        clock_bits(mbc7, (opcode << 8) | address as u16, 10);
+    }
+
+    fn deselect(mbc7: &mut MBC7) {
+        mbc7.write(0xA080, 0x00);
+    }
+
+    fn write_word(mbc7: &mut MBC7, address: u8, word: u16) {
+        command(mbc7, 0b01, address);
+        clock_bits(mbc7, word, 16);
+        deselect(mbc7);
+    }
+
+    fn read_word(mbc7: &mut MBC7, address: u8) -> u16 {
+        command(mbc7, 0b10, address);
+        // The dummy 0 bit is shifted out by the last address bit
+        assert_eq!(mbc7.peek(0xA080) & 0x1, 0);
+
+        let word = (0..16).fold(0, |word, _| (word << 1) | clock(mbc7, false) as u16);
+        deselect(mbc7);
+        word
+    }
+
+    fn extended(mbc7: &mut MBC7, address: u8) {
+        command(mbc7, 0b00, address);
+        deselect(mbc7);
+    }
+
+    const EWEN: u8 = 0xC0;
+    const EWDS: u8 = 0x00;
+    const WRAL: u8 = 0x40;
+    const ERAL: u8 = 0x80;
+
+    #[test]
+    fn eeprom_writes_only_when_enabled() {
+        let mut mbc7 = mbc7();
+
+        write_word(&mut mbc7, 0x05, 0x1234);
+        assert_eq!(read_word(&mut mbc7, 0x05), 0xFFFF);
+
+        extended(&mut mbc7, EWEN);
+        write_word(&mut mbc7, 0x05, 0x1234);
+        write_word(&mut mbc7, 0x7F, 0xBEEF);
+        assert_eq!(read_word(&mut mbc7, 0x05), 0x1234);
+        assert_eq!(read_word(&mut mbc7, 0x7F), 0xBEEF);
+        assert_eq!(mbc7.eeprom.to_bytes()[0x0A..0x0C], [0x34, 0x12]);
+
+        extended(&mut mbc7, EWDS);
+        write_word(&mut mbc7, 0x05, 0x5678);
+        assert_eq!(read_word(&mut mbc7, 0x05), 0x1234);
+    }
+
+    #[test]
+    fn eeprom_erases_and_writes_all() {
+        let mut mbc7 = mbc7();
+        extended(&mut mbc7, EWEN);
+
+        command(&mut mbc7, 0b00, WRAL);
+        clock_bits(&mut mbc7, 0xA5A5, 16);
+        deselect(&mut mbc7);
+        assert!(mbc7.eeprom.words.iter().all(|&w| w == 0xA5A5));
+
+        command(&mut mbc7, 0b11, 0x03);
+        deselect(&mut mbc7);
+        assert_eq!(read_word(&mut mbc7, 0x03), 0xFFFF);
+        assert_eq!(read_word(&mut mbc7, 0x04), 0xA5A5);
+
+        extended(&mut mbc7, ERAL);
+        assert!(mbc7.eeprom.words.iter().all(|&w| w == 0xFFFF));
+    }
+
+    #[test]
+    fn deselect_aborts_command() {
+        let mut mbc7 = mbc7();
+        extended(&mut mbc7, EWEN);
+
+        command(&mut mbc7, 0b01, 0x10);
+        clock_bits(&mut mbc7, 0x00FF, 8);
+        deselect(&mut mbc7);
+
+        assert_eq!(mbc7.peek(0xA080) & 0x1, 1);
+        assert_eq!(read_word(&mut mbc7, 0x10), 0xFFFF);
+    }
+
+    #[test]
+    fn accelerometer_samples_tilt_after_erase() {
+        let mut mbc7 = mbc7();
+        mbc7.set_tilt(0.5, -0.5);
+
+        let x = |mbc7: &MBC7| u16::from_le_bytes([mbc7.peek(0xA020), mbc7.peek(0xA030)]);
+        let y = |mbc7: &MBC7| u16::from_le_bytes([mbc7.peek(0xA040), mbc7.peek(0xA050)]);
+        assert_eq!(x(&mbc7), ACCEL_CENTER);
+
+        // 0xAA without a preceding 0x55 doesn't sample
+        mbc7.write(0xA010, 0xAA);
+        assert_eq!(x(&mbc7), ACCEL_CENTER);
+
+        mbc7.write(0xA000, 0x55);
+        assert_eq!((x(&mbc7), y(&mbc7)), (0x8000, 0x8000));
+
+        mbc7.write(0xA010, 0xAA);
+        assert_eq!((x(&mbc7), y(&mbc7)), (ACCEL_CENTER - 56, ACCEL_CENTER - 56));
+    }
+}
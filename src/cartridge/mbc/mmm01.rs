@@ -0,0 +1,296 @@
+use crate::{
+    mmu::mmio::MMIO,
+    save_state::{Snapshot, StateError, StateReader, StateWriter},
+};
+
+/// Multicart mapper. Until the menu maps a game, the last 32 KiB of the ROM
+/// are visible and every register is writable. Afterwards the game sees an
+/// MBC1 like mapper confined to its region of ROM and RAM.
+#[derive(PartialEq, Eq)]
+pub struct MMM01 {
+    pub rom: Vec<u8>,
+    pub external_ram: Vec<[u8; 0x2000]>,
+
+    mapped: bool,
+    ram_enable: bool,
+
+    rom_bank_low: u8,
+    rom_bank_mid: u8,
+    rom_bank_high: u8,
+    /// ROM bank low bits the game can't change after mapping
+    rom_bank_mask: u8,
+
+    ram_bank_low: u8,
+    ram_bank_high: u8,
+    /// RAM bank low bits the game can't change after mapping
+    ram_bank_mask: u8,
+
+    mbc1_mode: bool,
+    mbc1_mode_locked: bool,
+    /// Swaps ROM bank high and RAM bank low, for games bigger than 512 KiB
+    multiplex: bool,
+}
+
+impl MMM01 {
+    pub fn new(rom: &[u8], ram_size: u8) -> Self {
+        Self {
+            rom: rom.to_vec(),
+            external_ram: vec![[0xFF; 0x2000]; (ram_size / 8) as usize],
+
+            mapped: false,
+            ram_enable: false,
+
+            rom_bank_low: 0x01,
+            rom_bank_mid: 0x00,
+            rom_bank_high: 0x00,
+            rom_bank_mask: 0x00,
+
+            ram_bank_low: 0x00,
+            ram_bank_high: 0x00,
+            ram_bank_mask: 0x00,
+
+            mbc1_mode: false,
+            mbc1_mode_locked: false,
+            multiplex: false,
+        }
+    }
+
+    /// ROM bank for 0x0000 - 0x3FFF (`upper == false`) or 0x4000 - 0x7FFF.
+    pub fn rom_bank(&self, upper: bool) -> usize {
+        if !self.mapped {
+            return 0x1FE | upper as usize;
+        }
+
+        let low = if !upper {
+            self.rom_bank_low & self.rom_bank_mask
+        } else if self.rom_bank_low & !self.rom_bank_mask & 0x1F == 0 {
+            // Like MBC1, bank 0 of a game can't be mapped into 0x4000 - 0x7FFF
+            self.rom_bank_low | 1
+        } else {
+            self.rom_bank_low
+        };
+        let high = if self.multiplex { self.ram_bank_low } else { self.rom_bank_high };
+
+        ((high as usize) << 7) | ((self.rom_bank_mid as usize) << 5) | low as usize
+    }
+
+    /// Writes the ROM or RAM byte that is mapped at `address`, without touching the registers.
+    ///
+    /// Nothing is written while RAM is disabled, just like `peek` doesn't see it.
+    pub fn poke(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x7FFF => {
+                let index = self.rom_index(address);
+                self.rom[index] = value;
+            }
+            0xA000..=0xBFFF => {
+                if let Some((bank, i)) = self.ram_index(address) {
+                    self.external_ram[bank][i] = value;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn rom_index(&self, address: u16) -> usize {
+        let bank = self.rom_bank(address >= 0x4000);
+        let address = bank * 0x4000 + (address as usize & 0x3FFF);
+
+        address & (self.rom.len() - 1)
+    }
+
+    fn ram_index(&self, address: u16) -> Option<(usize, usize)> {
+        let banks = self.external_ram.len();
+        if !self.ram_enable || banks == 0 {
+            return None;
+        }
+
+        Some((self.ram_bank() & (banks - 1), address as usize - 0xA000))
+    }
+
+    fn ram_bank(&self) -> usize {
+        let low = if self.multiplex { self.rom_bank_high } else { self.ram_bank_low };
+        let low = if self.mbc1_mode { low } else { low & self.ram_bank_mask };
+
+        ((self.ram_bank_high as usize) << 2) | low as usize
+    }
+}
+
+impl MMIO for MMM01 {
+    #[inline(always)]
+    fn peek(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x7FFF => self.rom[self.rom_index(address)],
+            0xA000..=0xBFFF => self
+                .ram_index(address)
+                .map_or(0xFF, |(bank, i)| self.external_ram[bank][i]),
+            _ => unreachable!(),
+        }
+    }
+
+    #[inline(always)]
+    fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => {
+                self.ram_enable = (value & 0xF) == 0xA;
+
+                if !self.mapped {
+                    self.ram_bank_mask = (value >> 4) & 0x3;
+                    self.mapped = value & 0x40 != 0;
+                }
+            }
+            0x2000..=0x3FFF => {
+                if !self.mapped {
+                    self.rom_bank_low = value & 0x1F;
+                    self.rom_bank_mid = (value >> 5) & 0x3;
+                } else {
+                    let writable = !self.rom_bank_mask & 0x1F;
+                    self.rom_bank_low =
+                        (self.rom_bank_low & self.rom_bank_mask) | (value & writable);
+                }
+            }
+            0x4000..=0x5FFF => {
+                if !self.mapped {
+                    self.ram_bank_low = value & 0x3;
+                    self.ram_bank_high = (value >> 2) & 0x3;
+                    self.rom_bank_high = (value >> 4) & 0x3;
+                    self.mbc1_mode_locked = value & 0x40 != 0;
+                } else {
+                    let writable = !self.ram_bank_mask & 0x3;
+                    self.ram_bank_low =
+                        (self.ram_bank_low & self.ram_bank_mask) | (value & writable);
+                }
+            }
+            0x6000..=0x7FFF => {
+                if !self.mapped || !self.mbc1_mode_locked {
+                    self.mbc1_mode = value & 0x1 != 0;
+                }
+
+                if !self.mapped {
+                    // Bits 2 - 5 mask ROM bank bits 1 - 4
+                    self.rom_bank_mask = (value >> 1) & 0x1E;
+                    self.multiplex = value & 0x40 != 0;
+                }
+            }
+            0xA000..=0xBFFF => {
+                if let Some((bank, i)) = self.ram_index(address) {
+                    self.external_ram[bank][i] = value;
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Snapshot for MMM01 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.mapped);
+        writer.write_bool(self.ram_enable);
+
+        writer.write_bytes(&[
+            self.rom_bank_low,
+            self.rom_bank_mid,
+            self.rom_bank_high,
+            self.rom_bank_mask,
+            self.ram_bank_low,
+            self.ram_bank_high,
+            self.ram_bank_mask,
+        ]);
+
+        writer.write_bool(self.mbc1_mode);
+        writer.write_bool(self.mbc1_mode_locked);
+        writer.write_bool(self.multiplex);
+
+        writer.write_bytes(&self.external_ram.concat());
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.mapped = reader.read_bool()?;
+        self.ram_enable = reader.read_bool()?;
+
+        self.rom_bank_low = reader.read_u8()?;
+        self.rom_bank_mid = reader.read_u8()?;
+        self.rom_bank_high = reader.read_u8()?;
+        self.rom_bank_mask = reader.read_u8()?;
+        self.ram_bank_low = reader.read_u8()?;
+        self.ram_bank_high = reader.read_u8()?;
+        self.ram_bank_mask = reader.read_u8()?;
+
+        self.mbc1_mode = reader.read_bool()?;
+        self.mbc1_mode_locked = reader.read_bool()?;
+        self.multiplex = reader.read_bool()?;
+
+        for bank in &mut self.external_ram {
+            reader.read_into(bank)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MMM01;
+    use crate::mmu::mmio::MMIO;
+
+    /// 512 KiB ROM, every bank starts with its number.
+    fn mmm01() -> MMM01 {
+        let mut rom = vec![0; 32 * 0x4000];
+        for (bank, chunk) in rom.chunks_mut(0x4000).enumerate() {
+            chunk[0] = bank as u8;
+        }
+
+        MMM01::new(&rom, 32)
+    }
+
+    #[test]
+    fn menu_maps_last_32_kib() {
+        let mut mmm01 = mmm01();
+        assert_eq!((mmm01.peek(0x0000), mmm01.peek(0x4000)), (30, 31));
+
+        // Registers change, but stay hidden until the game is mapped
+        mmm01.write(0x2000, 0x08);
+        assert_eq!((mmm01.peek(0x0000), mmm01.peek(0x4000)), (30, 31));
+    }
+
+    #[test]
+    fn game_is_locked_to_its_region() {
+        let mut mmm01 = mmm01();
+
+        // Game of 8 banks starting at bank 8: ROM bank bits 3 - 4 are fixed
+        mmm01.write(0x2000, 0x08);
+        mmm01.write(0x6000, 0x30);
+        mmm01.write(0x0000, 0x40);
+        assert_eq!((mmm01.peek(0x0000), mmm01.peek(0x4000)), (8, 9));
+
+        mmm01.write(0x2000, 0x1F);
+        assert_eq!(mmm01.peek(0x4000), 15);
+
+        // Bank 0 of the game maps bank 1 of the game instead
+        mmm01.write(0x2000, 0x00);
+        assert_eq!(mmm01.peek(0x4000), 9);
+
+        // Neither mapping again nor changing the mask does anything
+        mmm01.write(0x0000, 0x00);
+        mmm01.write(0x6000, 0x00);
+        mmm01.write(0x2000, 0x13);
+        assert_eq!((mmm01.peek(0x0000), mmm01.peek(0x4000)), (8, 11));
+    }
+
+    #[test]
+    fn ram_needs_enable_after_mapping() {
+        let mut mmm01 = mmm01();
+        mmm01.write(0x0000, 0x40);
+
+        mmm01.write(0xA000, 0x42);
+        assert_eq!(mmm01.peek(0xA000), 0xFF);
+
+        // RAM banks are only switched in MBC1 mode 1
+        mmm01.write(0x0000, 0x0A);
+        mmm01.write(0x6000, 0x01);
+        mmm01.write(0x4000, 0x02);
+        mmm01.write(0xA000, 0x42);
+        assert_eq!(mmm01.peek(0xA000), 0x42);
+        assert_eq!(mmm01.external_ram[2][0], 0x42);
+    }
+}
@@ -1,5 +1,11 @@
+pub mod huc1;
+pub mod huc3;
 pub mod mbc1;
 pub mod mbc2;
 pub mod mbc3;
 pub mod mbc5;
+pub mod mbc6;
+pub mod mbc7;
+pub mod mmm01;
 pub mod no_mbc;
+pub mod pocket_camera;
@@ -9,11 +9,18 @@ impl NoMBC {
     pub fn new(rom: &[u8]) -> Self {
         Self { rom: rom.to_vec() }
     }
+
+    /// Patches the ROM byte at `address`, there is no RAM to write.
+    pub fn poke(&mut self, address: u16, value: u8) {
+        if address < 0x8000 {
+            self.rom[address as usize] = value;
+        }
+    }
 }
 
 impl MMIO for NoMBC {
     #[inline(always)]
-    fn read(&mut self, address: u16) -> u8 {
+    fn peek(&self, address: u16) -> u8 {
         if address < 0x8000 {
             self.rom[address as usize]
         } else {
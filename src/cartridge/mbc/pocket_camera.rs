@@ -0,0 +1,419 @@
+use crate::{
+    cartridge::camera_source::{ImageSource, TestPattern, SENSOR_HEIGHT, SENSOR_WIDTH},
+    mmu::mmio::MMIO,
+    save_state::{Snapshot, StateError, StateReader, StateWriter},
+};
+
+/// Camera registers 0xA000 - 0xA035, mirrored every 0x80 bytes.
+const REGISTER_COUNT: usize = 0x36;
+/// The picture is written to RAM bank 0 from 0xA100 on as 16 x 14 tiles.
+const IMAGE_OFFSET: usize = 0x100;
+const IMAGE_SIZE: usize = 16 * 14 * 16;
+/// Start of the 4 x 4 matrix of three thresholds each used for dithering
+const DITHER_MATRIX: usize = 0x06;
+
+/// Edge enhancement ratio selected by bits 4 - 6 of register 4.
+const EDGE_RATIOS: [f32; 8] = [0.5, 0.75, 1.0, 1.25, 2.0, 3.0, 4.0, 5.0];
+
+/// Game Boy Camera mapper (MAC-GBD) with its M64282FP image sensor.
+pub struct PocketCamera {
+    pub rom: Vec<u8>,
+    pub external_ram: Vec<[u8; 0x2000]>,
+
+    ram_write_enable: bool,
+    rom_bank_number: u8,
+    /// Bit 4 maps the camera registers instead of RAM
+    ram_bank_number: u8,
+
+    registers: [u8; REGISTER_COUNT],
+    /// T-cycles left until the running capture is done
+    capture_cycles: u32,
+
+    source: Box<dyn ImageSource>,
+}
+
+impl PocketCamera {
+    pub fn new(rom: &[u8]) -> Self {
+        Self {
+            rom: rom.to_vec(),
+            external_ram: vec![[0x00; 0x2000]; 16],
+
+            ram_write_enable: false,
+            rom_bank_number: 0x01,
+            ram_bank_number: 0x00,
+
+            registers: [0; REGISTER_COUNT],
+            capture_cycles: 0,
+
+            source: Box::<TestPattern>::default(),
+        }
+    }
+
+    /// ROM bank mapped to 0x4000 - 0x7FFF.
+    pub fn rom_bank(&self) -> usize {
+        self.rom_bank_number as usize
+    }
+
+    /// Replaces where captured pictures come from, a test pattern by default.
+    pub fn set_source(&mut self, source: Box<dyn ImageSource>) {
+        self.source = source;
+    }
+
+    /// Writes the ROM or RAM byte that is mapped at `address`, without touching the registers.
+    ///
+    /// Nothing is written while the camera registers are mapped or a capture is running.
+    pub fn poke(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x7FFF => {
+                let index = self.rom_index(address);
+                self.rom[index] = value;
+            }
+            0xA000..=0xBFFF if !self.registers_mapped() && self.capture_cycles == 0 => {
+                self.external_ram[self.ram_bank_number as usize][address as usize - 0xA000] = value;
+            }
+            _ => {}
+        }
+    }
+
+    fn rom_index(&self, address: u16) -> usize {
+        if address < 0x4000 {
+            return address as usize;
+        }
+
+        let address = (self.rom_bank_number as usize * 0x4000) + (address as usize - 0x4000);
+        address & (self.rom.len() - 1)
+    }
+
+    pub fn tick(&mut self, cycles: u16) {
+        if self.capture_cycles == 0 {
+            return;
+        }
+
+        self.capture_cycles = self.capture_cycles.saturating_sub(cycles as u32);
+        if self.capture_cycles == 0 {
+            self.finish_capture();
+        }
+    }
+
+    fn registers_mapped(&self) -> bool {
+        self.ram_bank_number & 0x10 != 0
+    }
+
+    /// Capture length in T-cycles, depends on the exposure time in registers 2 and 3.
+    fn capture_length(&self) -> u32 {
+        let exposure = u16::from_be_bytes([self.registers[2], self.registers[3]]) as u32;
+        let n_bit = self.registers[1] & 0x80 != 0;
+
+        4 * (32446 + if n_bit { 0 } else { 512 } + 16 * exposure)
+    }
+
+    /// Takes a picture from the image source, writes it into RAM and clears the busy bit.
+    fn finish_capture(&mut self) {
+        let mut picture = self.source.capture();
+        picture.resize(SENSOR_WIDTH * SENSOR_HEIGHT, 0);
+
+        let tiles = self.process(&picture);
+        self.external_ram[0][IMAGE_OFFSET..IMAGE_OFFSET + IMAGE_SIZE].copy_from_slice(&tiles);
+        self.registers[0] &= !0x1;
+    }
+
+    /// Runs the picture through what the sensor and mapper do to it:
+    /// gain and exposure, inversion, edge enhancement and finally dithering
+    /// down to 2 bpp tiles with the matrix from registers 0x06 - 0x35.
+    ///
+    /// The analog stages are approximated, the camera ROM adjusts
+    /// exposure until the picture looks right anyway.
+    fn process(&self, picture: &[u8]) -> Vec<u8> {
+        let gain_db = 14.0 + 1.5 * (self.registers[1] & 0x1F) as f32;
+        let gain = 10f32.powf((gain_db - 26.0) / 20.0);
+        let exposure =
+            u16::from_be_bytes([self.registers[2], self.registers[3]]) as f32 / 0x1000 as f32;
+        let invert = self.registers[4] & 0x08 != 0;
+
+        let sensor = picture
+            .iter()
+            .map(|&p| {
+                let light = p as f32 * gain * exposure;
+                if invert {
+                    255.0 - light
+                } else {
+                    light
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let pixel = |x: isize, y: isize| {
+            let x = x.clamp(0, SENSOR_WIDTH as isize - 1) as usize;
+            let y = y.clamp(0, SENSOR_HEIGHT as isize - 1) as usize;
+            sensor[y * SENSOR_WIDTH + x]
+        };
+
+        // 0: none, 1: horizontal, 2: vertical, 3: both
+        let edge_mode = (self.registers[1] >> 5) & 0x3;
+        let edge_ratio = EDGE_RATIOS[((self.registers[4] >> 4) & 0x7) as usize];
+
+        let mut tiles = vec![0; IMAGE_SIZE];
+
+        for y in 0..SENSOR_HEIGHT {
+            for x in 0..SENSOR_WIDTH {
+                let (xi, yi) = (x as isize, y as isize);
+                let center = pixel(xi, yi);
+                let mut value = center;
+
+                if edge_mode & 0x1 != 0 {
+                    value += edge_ratio * (2.0 * center - pixel(xi - 1, yi) - pixel(xi + 1, yi));
+                }
+                if edge_mode & 0x2 != 0 {
+                    value += edge_ratio * (2.0 * center - pixel(xi, yi - 1) - pixel(xi, yi + 1));
+                }
+
+                let thresholds = DITHER_MATRIX + ((y & 3) * 4 + (x & 3)) * 3;
+                let value = value.clamp(0.0, 255.0) as u8;
+                let color = match value {
+                    v if v < self.registers[thresholds] => 3,
+                    v if v < self.registers[thresholds + 1] => 2,
+                    v if v < self.registers[thresholds + 2] => 1,
+                    _ => 0,
+                };
+
+                // 16 tiles per row, 16 bytes per tile, 2 per line of pixels
+                let byte = ((y / 8) * 16 + x / 8) * 16 + (y % 8) * 2;
+                let bit = 7 - (x % 8);
+                tiles[byte] |= (color & 1) << bit;
+                tiles[byte + 1] |= (color >> 1) << bit;
+            }
+        }
+
+        tiles
+    }
+}
+
+impl MMIO for PocketCamera {
+    #[inline(always)]
+    fn peek(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x7FFF => self.rom[self.rom_index(address)],
+            0xA000..=0xBFFF => {
+                if self.registers_mapped() {
+                    // Only the control register can be read back
+                    if address & 0x7F == 0 {
+                        self.registers[0]
+                    } else {
+                        0x00
+                    }
+                } else if self.capture_cycles > 0 {
+                    // RAM is busy while the sensor is being read out
+                    0x00
+                } else {
+                    self.external_ram[self.ram_bank_number as usize][address as usize - 0xA000]
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[inline(always)]
+    fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_write_enable = (value & 0xF) == 0xA,
+            0x2000..=0x3FFF => self.rom_bank_number = value & 0x3F,
+            0x4000..=0x5FFF => self.ram_bank_number = value & 0x1F,
+            0x6000..=0x7FFF => {}
+            0xA000..=0xBFFF => {
+                if self.registers_mapped() {
+                    let register = (address & 0x7F) as usize;
+                    match register {
+                        0 => {
+                            let start = value & 0x1 != 0 && self.registers[0] & 0x1 == 0;
+                            self.registers[0] = value & 0x7;
+
+                            if start {
+                                self.capture_cycles = self.capture_length();
+                            } else if value & 0x1 == 0 {
+                                self.capture_cycles = 0;
+                            }
+                        }
+                        1..=0x35 => self.registers[register] = value,
+                        _ => {}
+                    }
+                } else if self.ram_write_enable && self.capture_cycles == 0 {
+                    self.external_ram[self.ram_bank_number as usize][address as usize - 0xA000] =
+                        value;
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Snapshot for PocketCamera {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.ram_write_enable);
+        writer.write_u8(self.rom_bank_number);
+        writer.write_u8(self.ram_bank_number);
+
+        writer.write_bytes(&self.registers);
+        writer.write_u32(self.capture_cycles);
+
+        writer.write_bytes(&self.external_ram.concat());
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.ram_write_enable = reader.read_bool()?;
+        self.rom_bank_number = reader.read_u8()?;
+        self.ram_bank_number = reader.read_u8()?;
+
+        reader.read_into(&mut self.registers)?;
+        self.capture_cycles = reader.read_u32()?;
+
+        for bank in &mut self.external_ram {
+            reader.read_into(bank)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PocketCamera, DITHER_MATRIX, IMAGE_OFFSET, IMAGE_SIZE};
+    use crate::{
+        cartridge::camera_source::{ImageSource, SENSOR_HEIGHT, SENSOR_WIDTH},
+        mmu::mmio::MMIO,
+    };
+
+    /// Picture built by `pixel(x, y)`.
+    struct Pattern(fn(usize, usize) -> u8);
+
+    impl ImageSource for Pattern {
+        fn capture(&mut self) -> Vec<u8> {
+            (0..SENSOR_HEIGHT)
+                .flat_map(|y| (0..SENSOR_WIDTH).map(move |x| (y, x)))
+                .map(|(y, x)| (self.0)(x, y))
+                .collect()
+        }
+    }
+
+    /// Camera with registers mapped, gain and exposure of 1 so the sensor sees the
+    /// picture unchanged, and thresholds of 64, 128 and 192 everywhere in the matrix.
+    fn camera(pixel: fn(usize, usize) -> u8) -> PocketCamera {
+        let mut camera = PocketCamera::new(&[0; 0x8000]);
+        camera.set_source(Box::new(Pattern(pixel)));
+
+        camera.write(0x0000, 0x0A);
+        camera.write(0x4000, 0x10);
+        // 14 dB + 8 * 1.5 dB = 26 dB
+        camera.write(0xA001, 0x08);
+        camera.write(0xA002, 0x10);
+        camera.write(0xA003, 0x00);
+        for i in 0..16 {
+            let thresholds = 0xA000 + (DITHER_MATRIX + i * 3) as u16;
+            camera.write(thresholds, 64);
+            camera.write(thresholds + 1, 128);
+            camera.write(thresholds + 2, 192);
+        }
+
+        camera
+    }
+
+    /// Starts a capture and runs it to the end, returns the picture from RAM bank 0.
+    fn capture(camera: &mut PocketCamera) -> Vec<u8> {
+        camera.write(0xA000, 0x01);
+        for _ in 0..camera.capture_length().div_ceil(0x1000) {
+            camera.tick(0x1000);
+        }
+
+        camera.write(0x4000, 0x00);
+        (0..IMAGE_SIZE)
+            .map(|i| camera.peek(0xA000 + (IMAGE_OFFSET + i) as u16))
+            .collect()
+    }
+
+    /// Low and high byte of a line of 8 pixels of `colors`.
+    fn line(colors: [u8; 8]) -> [u8; 2] {
+        colors.iter().fold([0, 0], |[low, high], &color| {
+            [(low << 1) | (color & 1), (high << 1) | (color >> 1)]
+        })
+    }
+
+    #[test]
+    fn capture_is_busy_for_its_length() {
+        let mut camera = camera(|_, _| 100);
+        let length = camera.capture_length();
+        assert_eq!(length, 4 * (32446 + 512 + 16 * 0x1000));
+
+        camera.write(0xA000, 0x01);
+        assert_eq!(camera.peek(0xA000) & 0x1, 1);
+
+        let mut elapsed = 0;
+        while elapsed + 0x1000 < length {
+            camera.tick(0x1000);
+            elapsed += 0x1000;
+        }
+        assert_eq!(camera.peek(0xA000) & 0x1, 1);
+
+        // RAM reads 0 and ignores writes while the sensor is read out
+        camera.write(0x4000, 0x00);
+        camera.write(0xA100, 0x42);
+        assert_eq!(camera.peek(0xA100), 0x00);
+
+        camera.tick((length - elapsed) as u16);
+        assert_eq!(camera.peek(0xA100), 0x00);
+        assert_eq!(camera.peek(0xA101), 0xFF);
+
+        camera.write(0x4000, 0x10);
+        assert_eq!(camera.peek(0xA000) & 0x1, 0);
+    }
+
+    #[test]
+    fn constant_picture_fills_every_tile() {
+        let mut camera = camera(|_, _| 100);
+        let image = capture(&mut camera);
+        assert!(image.chunks(2).all(|l| l == line([2; 8])));
+
+        // Inverted, 255 - 100 is between the second and third threshold
+        let mut camera = self::camera(|_, _| 100);
+        camera.write(0xA004, 0x08);
+        let image = capture(&mut camera);
+        assert!(image.chunks(2).all(|l| l == line([1; 8])));
+    }
+
+    #[test]
+    fn dither_matrix_repeats_every_4_pixels() {
+        let mut camera = camera(|_, _| 100);
+        // Top left position of the matrix always gives black, everything else white
+        for i in 0..16 {
+            let value = if i == 0 { 255 } else { 0 };
+            for threshold in 0..3 {
+                camera.write(0xA000 + (DITHER_MATRIX + i * 3 + threshold) as u16, value);
+            }
+        }
+
+        let image = capture(&mut camera);
+        let black_white = line([3, 0, 0, 0, 3, 0, 0, 0]);
+
+        // Lines 0 and 4 of the first tile and of the second tile in the row
+        assert_eq!(image[0..2], black_white);
+        assert_eq!(image[2..4], line([0; 8]));
+        assert_eq!(image[8..10], black_white);
+        assert_eq!(image[16..18], black_white);
+        // First line of the second row of tiles
+        assert_eq!(image[16 * 16..16 * 16 + 2], black_white);
+    }
+
+    #[test]
+    fn edge_enhancement_sharpens_lines() {
+        let column = |x: usize, _| if x == 4 { 150 } else { 100 };
+
+        let mut camera = camera(column);
+        let image = capture(&mut camera);
+        assert_eq!(image[0..2], line([2, 2, 2, 2, 1, 2, 2, 2]));
+
+        // Horizontal enhancement with a ratio of 1
+        let mut camera = self::camera(column);
+        camera.write(0xA001, 0x28);
+        camera.write(0xA004, 0x20);
+        let image = capture(&mut camera);
+        assert_eq!(image[0..2], line([2, 2, 2, 3, 0, 3, 2, 2]));
+    }
+}
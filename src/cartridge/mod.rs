@@ -1,2 +1,4 @@
 pub mod base_cartridge;
+pub mod camera_source;
 pub mod mbc;
+pub mod rom_error;
@@ -0,0 +1,52 @@
+use std::fmt::Display;
+
+/// Reasons a ROM can't be loaded, found while parsing its header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RomError {
+    /// File ends before the cartridge header does (0x0150 bytes)
+    TruncatedHeader(usize),
+    /// File is smaller than the ROM size code in the header says
+    TruncatedRom {
+        expected: usize,
+        actual: usize,
+    },
+    UnsupportedCartridgeType(u8),
+    InvalidRomSize(u8),
+    InvalidRamSize(u8),
+    HeaderChecksumMismatch {
+        expected: u8,
+        actual: u8,
+    },
+    /// Boot ROMs are 256 (DMG, MGB) or 2304 (CGB) bytes
+    InvalidBootRomSize(usize),
+}
+
+impl Display for RomError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RomError::TruncatedHeader(len) => write!(
+                f,
+                "File is too small to be a Game Boy ROM ({len} bytes, the header alone needs 336)"
+            ),
+            RomError::TruncatedRom { expected, actual } => write!(
+                f,
+                "ROM is truncated: the header declares {expected} bytes but the file only has {actual}"
+            ),
+            RomError::UnsupportedCartridgeType(t) => {
+                write!(f, "Cartridge type {t:#04X} is not supported")
+            }
+            RomError::InvalidRomSize(code) => write!(f, "Invalid ROM size code {code:#04X}"),
+            RomError::InvalidRamSize(code) => write!(f, "Invalid RAM size code {code:#04X}"),
+            RomError::HeaderChecksumMismatch { expected, actual } => write!(
+                f,
+                "Header checksum mismatch: header says {expected:#04X} but it is {actual:#04X}"
+            ),
+            RomError::InvalidBootRomSize(len) => write!(
+                f,
+                "Boot ROM has {len} bytes, expected 256 (DMG, MGB) or 2304 (CGB)"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RomError {}
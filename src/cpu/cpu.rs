@@ -1,6 +1,7 @@
 use crate::{
     cpu::registers::{Flag, Registers, Regs},
     mmu::{bus::Bus, mmio::MMIO},
+    save_state::{Snapshot, StateError, StateReader, StateWriter},
 };
 
 pub mod debug;
@@ -72,7 +73,7 @@ impl CPU {
 
         let opcode = self.fetch_operand(bus);
         if self.halt_bug {
-            self.registers.PC -= 1;
+            self.registers.PC = self.registers.PC.wrapping_sub(1);
             self.halt_bug = false;
         }
 
@@ -371,7 +372,7 @@ impl CPU {
                     let address = bus.read_16(self.registers.PC);
                     bus.write_16(address, self.registers.SP);
 
-                    self.registers.PC += 2;
+                    self.registers.PC = self.registers.PC.wrapping_add(2);
                     5
                 }
                 0x09 | 0x19 | 0x29 | 0x39 => {
@@ -523,7 +524,7 @@ impl CPU {
                     let condition = (opcode >> 3) & 0b11;
                     let value = bus.read_16(self.registers.PC);
 
-                    self.registers.PC += 2;
+                    self.registers.PC = self.registers.PC.wrapping_add(2);
 
                     match condition {
                         0 | 1 => self.jp_flag(Flag::Zero, value, condition != 0, bus),
@@ -541,7 +542,7 @@ impl CPU {
                     let condition = (opcode >> 3) & 0b11;
                     let value = bus.read_16(self.registers.PC);
 
-                    self.registers.PC += 2;
+                    self.registers.PC = self.registers.PC.wrapping_add(2);
 
                     match condition {
                         0 | 1 => self.call_flag(bus, Flag::Zero, value, condition != 0),
@@ -580,7 +581,7 @@ impl CPU {
                 0xCD => {
                     let address = bus.read_16(self.registers.PC);
 
-                    self.registers.PC += 2;
+                    self.registers.PC = self.registers.PC.wrapping_add(2);
                     self.call(bus, address);
 
                     6
@@ -626,7 +627,7 @@ impl CPU {
                     let address = bus.read_16(self.registers.PC);
                     bus.write(address, self.registers.A);
 
-                    self.registers.PC += 2;
+                    self.registers.PC = self.registers.PC.wrapping_add(2);
 
                     4
                 }
@@ -634,7 +635,7 @@ impl CPU {
                     let address = bus.read_16(self.registers.PC);
                     self.registers.A = bus.read(address);
 
-                    self.registers.PC += 2;
+                    self.registers.PC = self.registers.PC.wrapping_add(2);
 
                     4
                 }
@@ -694,7 +695,7 @@ impl CPU {
     /// Read next operand at PC and increase PC after.
     fn fetch_operand(&mut self, bus: &mut Bus) -> u8 {
         let operand = bus.read(self.registers.PC);
-        self.registers.PC += 1;
+        self.registers.PC = self.registers.PC.wrapping_add(1);
 
         operand
     }
@@ -873,7 +874,7 @@ impl CPU {
             ),
         );
 
-        self.registers.SP += signed_operand as u16;
+        self.registers.SP = self.registers.SP.wrapping_add(signed_operand as u16);
 
         self.registers.set_flag(Flag::Zero, false);
         self.registers.set_flag(Flag::Substraction, false);
@@ -906,28 +907,28 @@ impl CPU {
     }
 
     fn inc8(&mut self, value: u8) -> u8 {
-        let value = value + 1;
+        let value = value.wrapping_add(1);
 
         self.registers.set_flag(Flag::Zero, value == 0);
         self.registers.set_flag(Flag::Substraction, false);
 
         self.registers.set_flag(
             Flag::HalfCarry,
-            self.hc(value - 1, 1, 0xF, u8::wrapping_add),
+            self.hc(value.wrapping_sub(1), 1, 0xF, u8::wrapping_add),
         );
 
         value
     }
 
     fn dec8(&mut self, value: u8) -> u8 {
-        let value = value - 1;
+        let value = value.wrapping_sub(1);
 
         self.registers.set_flag(Flag::Zero, value == 0);
         self.registers.set_flag(Flag::Substraction, true);
 
         self.registers.set_flag(
             Flag::HalfCarry,
-            self.hc(value + 1, 1, 0xF, u8::wrapping_sub),
+            self.hc(value.wrapping_add(1), 1, 0xF, u8::wrapping_sub),
         );
 
         value
@@ -935,20 +936,32 @@ impl CPU {
 
     fn inc16(&mut self, reg16: Regs) {
         match reg16 {
-            Regs::BC => self.registers.set_bc(self.registers.get_bc() + 1),
-            Regs::DE => self.registers.set_de(self.registers.get_de() + 1),
-            Regs::HL => self.registers.set_hl(self.registers.get_hl() + 1),
-            Regs::SP => self.registers.SP += 1,
+            Regs::BC => self
+                .registers
+                .set_bc(self.registers.get_bc().wrapping_add(1)),
+            Regs::DE => self
+                .registers
+                .set_de(self.registers.get_de().wrapping_add(1)),
+            Regs::HL => self
+                .registers
+                .set_hl(self.registers.get_hl().wrapping_add(1)),
+            Regs::SP => self.registers.SP = self.registers.SP.wrapping_add(1),
             _ => {}
         }
     }
 
     fn dec16(&mut self, reg16: Regs) {
         match reg16 {
-            Regs::BC => self.registers.set_bc(self.registers.get_bc() - 1),
-            Regs::DE => self.registers.set_de(self.registers.get_de() - 1),
-            Regs::HL => self.registers.set_hl(self.registers.get_hl() - 1),
-            Regs::SP => self.registers.SP -= 1,
+            Regs::BC => self
+                .registers
+                .set_bc(self.registers.get_bc().wrapping_sub(1)),
+            Regs::DE => self
+                .registers
+                .set_de(self.registers.get_de().wrapping_sub(1)),
+            Regs::HL => self
+                .registers
+                .set_hl(self.registers.get_hl().wrapping_sub(1)),
+            Regs::SP => self.registers.SP = self.registers.SP.wrapping_sub(1),
             _ => {}
         }
     }
@@ -1081,7 +1094,11 @@ impl CPU {
 
         self.registers.set_flag(
             Flag::HalfCarry,
-            ((self.registers.A & 0xF) - (value & 0xF) - carry_bit) & 0x10 == 0x10,
+            (self.registers.A & 0xF)
+                .wrapping_sub(value & 0xF)
+                .wrapping_sub(carry_bit)
+                & 0x10
+                == 0x10,
         );
 
         let (intermediate_result, c1) = self.registers.A.overflowing_sub(value);
@@ -1243,18 +1260,18 @@ impl CPU {
     fn daa(&mut self) {
         if self.registers.get_flag(Flag::Substraction) {
             if self.registers.get_flag(Flag::Carry) {
-                self.registers.A -= 0x60;
+                self.registers.A = self.registers.A.wrapping_sub(0x60);
             }
             if self.registers.get_flag(Flag::HalfCarry) {
-                self.registers.A -= 0x6;
+                self.registers.A = self.registers.A.wrapping_sub(0x6);
             }
         } else {
             if self.registers.get_flag(Flag::Carry) || self.registers.A > 0x99 {
-                self.registers.A += 0x60;
+                self.registers.A = self.registers.A.wrapping_add(0x60);
                 self.registers.set_flag(Flag::Carry, true);
             }
             if self.registers.get_flag(Flag::HalfCarry) || (self.registers.A & 0x0F) > 0x09 {
-                self.registers.A += 0x6;
+                self.registers.A = self.registers.A.wrapping_add(0x6);
             }
         }
 
@@ -1283,22 +1300,22 @@ impl CPU {
         match reg16 {
             Regs::BC => {
                 self.registers.C = bus.read(self.registers.SP);
-                self.registers.SP += 1;
+                self.registers.SP = self.registers.SP.wrapping_add(1);
                 self.registers.B = bus.read(self.registers.SP);
             }
             Regs::DE => {
                 self.registers.E = bus.read(self.registers.SP);
-                self.registers.SP += 1;
+                self.registers.SP = self.registers.SP.wrapping_add(1);
                 self.registers.D = bus.read(self.registers.SP);
             }
             Regs::HL => {
                 self.registers.L = bus.read(self.registers.SP);
-                self.registers.SP += 1;
+                self.registers.SP = self.registers.SP.wrapping_add(1);
                 self.registers.H = bus.read(self.registers.SP);
             }
             Regs::AF => {
                 self.registers.F = bus.read(self.registers.SP);
-                self.registers.SP += 1;
+                self.registers.SP = self.registers.SP.wrapping_add(1);
                 self.registers.A = bus.read(self.registers.SP);
 
                 // clear out lower nibble since it should always be zero
@@ -1307,7 +1324,7 @@ impl CPU {
             _ => {}
         }
 
-        self.registers.SP += 1;
+        self.registers.SP = self.registers.SP.wrapping_add(1);
     }
 
     fn push(&mut self, reg16: Regs, bus: &mut Bus) {
@@ -1315,31 +1332,31 @@ impl CPU {
 
         match reg16 {
             Regs::BC => {
-                self.registers.SP -= 1;
+                self.registers.SP = self.registers.SP.wrapping_sub(1);
                 bus.write(self.registers.SP, self.registers.B);
 
-                self.registers.SP -= 1;
+                self.registers.SP = self.registers.SP.wrapping_sub(1);
                 bus.write(self.registers.SP, self.registers.C);
             }
             Regs::DE => {
-                self.registers.SP -= 1;
+                self.registers.SP = self.registers.SP.wrapping_sub(1);
                 bus.write(self.registers.SP, self.registers.D);
 
-                self.registers.SP -= 1;
+                self.registers.SP = self.registers.SP.wrapping_sub(1);
                 bus.write(self.registers.SP, self.registers.E);
             }
             Regs::HL => {
-                self.registers.SP -= 1;
+                self.registers.SP = self.registers.SP.wrapping_sub(1);
                 bus.write(self.registers.SP, self.registers.H);
 
-                self.registers.SP -= 1;
+                self.registers.SP = self.registers.SP.wrapping_sub(1);
                 bus.write(self.registers.SP, self.registers.L);
             }
             Regs::AF => {
-                self.registers.SP -= 1;
+                self.registers.SP = self.registers.SP.wrapping_sub(1);
                 bus.write(self.registers.SP, self.registers.A);
 
-                self.registers.SP -= 1;
+                self.registers.SP = self.registers.SP.wrapping_sub(1);
                 bus.write(self.registers.SP, self.registers.F);
             }
             _ => {}
@@ -1349,9 +1366,9 @@ impl CPU {
     fn call(&mut self, bus: &mut Bus, value: u16) {
         bus.tick(1);
 
-        self.registers.SP -= 1;
+        self.registers.SP = self.registers.SP.wrapping_sub(1);
         bus.write(self.registers.SP, ((self.registers.PC) >> 8) as u8);
-        self.registers.SP -= 1;
+        self.registers.SP = self.registers.SP.wrapping_sub(1);
         bus.write(self.registers.SP, self.registers.PC as u8);
 
         self.registers.PC = value;
@@ -1376,10 +1393,10 @@ impl CPU {
 
     fn ret(&mut self, bus: &mut Bus) {
         let lower_byte = bus.read(self.registers.SP);
-        let higher_byte = bus.read(self.registers.SP + 1);
+        let higher_byte = bus.read(self.registers.SP.wrapping_add(1));
 
         self.registers.PC = (higher_byte as u16) << 8 | lower_byte as u16;
-        self.registers.SP += 2;
+        self.registers.SP = self.registers.SP.wrapping_add(2);
 
         bus.tick(1);
     }
@@ -1433,3 +1450,31 @@ impl CPU {
             == pos + T::try_from(1).unwrap_or_else(|_| unreachable!())
     }
 }
+
+impl Snapshot for CPU {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.registers.save_state(writer);
+
+        writer.write_bool(self.ime);
+        writer.write_bool(self.halt);
+        writer.write_bool(self.stopped);
+        writer.write_bool(self.ei);
+        writer.write_bool(self.ime_req);
+        writer.write_bool(self.halt_bug);
+        writer.write_bool(self.cgb);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.registers.load_state(reader)?;
+
+        self.ime = reader.read_bool()?;
+        self.halt = reader.read_bool()?;
+        self.stopped = reader.read_bool()?;
+        self.ei = reader.read_bool()?;
+        self.ime_req = reader.read_bool()?;
+        self.halt_bug = reader.read_bool()?;
+        self.cgb = reader.read_bool()?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,367 @@
+use std::{fmt::Display, str::FromStr};
+
+use crate::{
+    cpu::{
+        debug::{instruction::Instruction, watchpoint::WatchHit},
+        registers::Registers,
+        CPU,
+    },
+    mmu::bus::Bus,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Register {
+    A,
+    F,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    AF,
+    BC,
+    DE,
+    HL,
+    SP,
+}
+
+impl Register {
+    fn value(self, registers: &Registers) -> u16 {
+        match self {
+            Register::A => registers.A as u16,
+            Register::F => registers.F as u16,
+            Register::B => registers.B as u16,
+            Register::C => registers.C as u16,
+            Register::D => registers.D as u16,
+            Register::E => registers.E as u16,
+            Register::H => registers.H as u16,
+            Register::L => registers.L as u16,
+            Register::AF => registers.get_af(),
+            Register::BC => registers.get_bc(),
+            Register::DE => registers.get_de(),
+            Register::HL => registers.get_hl(),
+            Register::SP => registers.SP,
+        }
+    }
+}
+
+impl FromStr for Register {
+    type Err = ConditionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "A" => Ok(Register::A),
+            "F" => Ok(Register::F),
+            "B" => Ok(Register::B),
+            "C" => Ok(Register::C),
+            "D" => Ok(Register::D),
+            "E" => Ok(Register::E),
+            "H" => Ok(Register::H),
+            "L" => Ok(Register::L),
+            "AF" => Ok(Register::AF),
+            "BC" => Ok(Register::BC),
+            "DE" => Ok(Register::DE),
+            "HL" => Ok(Register::HL),
+            "SP" => Ok(Register::SP),
+            _ => Err(ConditionError::UnknownRegister(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+impl Comparison {
+    /// Longer operators first, so that `<=` is not taken for `<`.
+    const OPERATORS: [(&'static str, Comparison); 7] = [
+        ("==", Comparison::Equal),
+        ("!=", Comparison::NotEqual),
+        ("<=", Comparison::LessEqual),
+        (">=", Comparison::GreaterEqual),
+        ("<", Comparison::Less),
+        (">", Comparison::Greater),
+        ("=", Comparison::Equal),
+    ];
+
+    /// Finds the first operator in `s`, returns its position and length.
+    pub(crate) fn find(s: &str) -> Option<(usize, usize, Comparison)> {
+        Self::OPERATORS.iter().find_map(|&(operator, comparison)| {
+            s.find(operator)
+                .map(|position| (position, operator.len(), comparison))
+        })
+    }
+
+    pub(crate) fn symbol(self) -> &'static str {
+        Self::OPERATORS
+            .iter()
+            .find(|&&(_, comparison)| comparison == self)
+            .map(|(operator, _)| *operator)
+            .unwrap()
+    }
+
+    pub(crate) fn holds(self, left: u16, right: u16) -> bool {
+        match self {
+            Comparison::Equal => left == right,
+            Comparison::NotEqual => left != right,
+            Comparison::Less => left < right,
+            Comparison::LessEqual => left <= right,
+            Comparison::Greater => left > right,
+            Comparison::GreaterEqual => left >= right,
+        }
+    }
+}
+
+/// Compares a register with a value, e.g. `A == $3F`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Condition {
+    pub register: Register,
+    pub comparison: Comparison,
+    pub value: u16,
+}
+
+impl Condition {
+    pub fn holds(&self, registers: &Registers) -> bool {
+        self.comparison
+            .holds(self.register.value(registers), self.value)
+    }
+}
+
+/// Parses `<register> <operator> <value>`, the value is hexadecimal
+/// with an optional `$` or `0x` prefix.
+impl FromStr for Condition {
+    type Err = ConditionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (position, length, comparison) =
+            Comparison::find(s).ok_or(ConditionError::MissingOperator)?;
+
+        let register = s[..position].trim().parse()?;
+        let value = s[position + length..].trim();
+
+        Ok(Self {
+            register,
+            comparison,
+            value: parse_hex(value)
+                .ok_or_else(|| ConditionError::InvalidValue(value.to_string()))?,
+        })
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let digits = if self.register as u8 >= Register::AF as u8 { 4 } else { 2 };
+        write!(
+            f,
+            "{:?} {} ${:0digits$X}",
+            self.register,
+            self.comparison.symbol(),
+            self.value
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConditionError {
+    MissingOperator,
+    UnknownRegister(String),
+    InvalidValue(String),
+}
+
+impl Display for ConditionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConditionError::MissingOperator => {
+                write!(f, "Expected one of ==, !=, <, <=, >, >=")
+            }
+            ConditionError::UnknownRegister(register) => {
+                write!(f, "Unknown register \"{register}\"")
+            }
+            ConditionError::InvalidValue(value) => {
+                write!(f, "\"{value}\" is not a hexadecimal number")
+            }
+        }
+    }
+}
+
+/// Parses a hexadecimal number with an optional `$` or `0x` prefix.
+pub fn parse_hex(s: &str) -> Option<u16> {
+    let s = s.trim();
+    let digits = s
+        .strip_prefix('$')
+        .or_else(|| s.strip_prefix("0x"))
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+
+    u16::from_str_radix(digits, 16).ok()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Breakpoint {
+    pub address: u16,
+    pub condition: Option<Condition>,
+    pub enabled: bool,
+}
+
+/// Where emulation stops next, besides breakpoints.
+#[derive(Clone, Copy, PartialEq)]
+enum Target {
+    None,
+    /// The return address of a call, reached with the stack of the caller
+    Return {
+        address: u16,
+        sp: u16,
+    },
+    /// The stack shrinks below the frame of the current function
+    StackBelow(u16),
+    Address(u16),
+}
+
+/// Why the debugger stopped emulation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Break {
+    Breakpoint(u16),
+    StepOver(u16),
+    StepOut(u16),
+    RunTo(u16),
+    /// The instruction at `pc` accessed a watched address
+    Watchpoint {
+        pc: u16,
+        hit: WatchHit,
+    },
+}
+
+impl Display for Break {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Break::Breakpoint(pc) => write!(f, "Breakpoint hit at ${pc:04X}"),
+            Break::StepOver(pc) => write!(f, "Stepped over the call, now at ${pc:04X}"),
+            Break::StepOut(pc) => write!(f, "Returned to ${pc:04X}"),
+            Break::RunTo(pc) => write!(f, "Reached ${pc:04X}"),
+            Break::Watchpoint { pc, hit } => write!(f, "{hit} at PC ${pc:04X}"),
+        }
+    }
+}
+
+/// Decides before every instruction whether emulation should pause.
+///
+/// Single steps are executed by the caller directly, the debugger only
+/// handles everything that runs until some point is reached.
+pub struct Debugger {
+    pub breakpoints: Vec<Breakpoint>,
+    target: Target,
+    /// Set when resuming, so the breakpoint at the current PC does not hit again right away
+    resuming: bool,
+    /// Why emulation paused, until it is resumed
+    last_break: Option<Break>,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self {
+            breakpoints: Vec::new(),
+            target: Target::None,
+            resuming: false,
+            last_break: None,
+        }
+    }
+
+    /// Runs until a breakpoint is hit.
+    pub fn resume(&mut self) {
+        self.target = Target::None;
+        self.resuming = true;
+        self.last_break = None;
+    }
+
+    /// Runs until `instruction`, which must be a call, returned.
+    pub fn step_over(&mut self, instruction: &Instruction, sp: u16) {
+        self.resume();
+        self.target = Target::Return {
+            address: instruction.next_address(),
+            sp,
+        };
+    }
+
+    /// Runs until the current function returned, i.e. the stack
+    /// shrinks below the `sp` it has now.
+    pub fn step_out(&mut self, sp: u16) {
+        self.resume();
+        self.target = Target::StackBelow(sp);
+    }
+
+    pub fn run_to(&mut self, address: u16) {
+        self.resume();
+        self.target = Target::Address(address);
+    }
+
+    pub fn has_breakpoint(&self, address: u16) -> bool {
+        self.breakpoints.iter().any(|b| b.address == address)
+    }
+
+    /// Adds an unconditional breakpoint at `address` or removes the ones there.
+    pub fn toggle_breakpoint(&mut self, address: u16) {
+        if self.has_breakpoint(address) {
+            self.breakpoints.retain(|b| b.address != address);
+        } else {
+            self.breakpoints.push(Breakpoint {
+                address,
+                condition: None,
+                enabled: true,
+            });
+        }
+    }
+
+    /// Called before every instruction, returns why emulation has to pause.
+    ///
+    /// A halted CPU executes nothing, so it is only checked once it woke up.
+    pub fn check(&mut self, cpu: &CPU) -> Option<Break> {
+        if cpu.halt || std::mem::take(&mut self.resuming) {
+            return None;
+        }
+
+        let registers = &cpu.registers;
+        let pc = registers.PC;
+
+        let reached = match self.target {
+            Target::None => None,
+            Target::Return { address, sp } => {
+                (pc == address && registers.SP >= sp).then_some(Break::StepOver(pc))
+            }
+            Target::StackBelow(sp) => (registers.SP > sp).then_some(Break::StepOut(pc)),
+            Target::Address(address) => (pc == address).then_some(Break::RunTo(pc)),
+        };
+
+        let hit = self.breakpoints.iter().any(|b| {
+            b.enabled && b.address == pc && b.condition.is_none_or(|c| c.holds(registers))
+        });
+
+        let result = reached.or(hit.then_some(Break::Breakpoint(pc)));
+        if result.is_some() {
+            self.target = Target::None;
+            self.last_break = result;
+        }
+
+        result
+    }
+
+    /// Called after every instruction with the `pc` it started at,
+    /// returns the first watchpoint it hit.
+    pub fn check_watchpoints(&mut self, bus: &mut Bus, pc: u16) -> Option<Break> {
+        let hit = bus.watchpoints.take_hit()?;
+        self.target = Target::None;
+        self.last_break = Some(Break::Watchpoint { pc, hit });
+
+        self.last_break
+    }
+
+    pub fn last_break(&self) -> Option<Break> {
+        self.last_break
+    }
+}
@@ -0,0 +1,444 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Display,
+};
+
+use crate::cpu::debug::{instruction::Instruction, symbols::Symbols};
+
+const BANK_SIZE: usize = 0x4000;
+
+/// Addresses the CPU starts running code at by itself.
+const ENTRY_POINTS: [(u16, &str); 14] = [
+    (0x0000, "RST_00"),
+    (0x0008, "RST_08"),
+    (0x0010, "RST_10"),
+    (0x0018, "RST_18"),
+    (0x0020, "RST_20"),
+    (0x0028, "RST_28"),
+    (0x0030, "RST_30"),
+    (0x0038, "RST_38"),
+    (0x0040, "VBlankInterrupt"),
+    (0x0048, "LCDInterrupt"),
+    (0x0050, "TimerInterrupt"),
+    (0x0058, "SerialInterrupt"),
+    (0x0060, "JoypadInterrupt"),
+    (0x0100, "EntryPoint"),
+];
+
+/// Opcodes that lock up the CPU, running into one means it isn't code.
+const ILLEGAL_OPCODES: [u8; 11] = [
+    0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB, 0xEC, 0xED, 0xF4, 0xFC, 0xFD,
+];
+
+/// An instruction reached by following the code.
+struct Code {
+    instruction: Instruction,
+    /// Bank and address it jumps to or calls, if known
+    target: Option<(u16, u16)>,
+    /// Execution never continues with the next instruction, e.g. after `ret`
+    ends_flow: bool,
+}
+
+/// Code that still has to be followed.
+#[derive(Clone, Copy)]
+struct Path {
+    bank: u16,
+    address: u16,
+    /// Bank mapped to 0x4000-0x7FFF, if known
+    switchable: Option<u16>,
+}
+
+/// Static disassembly of a whole ROM, in the syntax of rgbds.
+///
+/// Code is found by following jumps and calls from every entry point, all
+/// other bytes are kept as data so the listing assembles to the same ROM.
+/// Jumps from bank 0 into the switchable bank are only followed if the bank
+/// is known, i.e. it was selected with `ld a, n` and `ld [$2000], a` before.
+pub struct Disassembly<'a> {
+    rom: &'a [u8],
+    /// Instructions by their offset in the ROM
+    code: BTreeMap<usize, Code>,
+    /// Offsets of all bytes that belong to an instruction
+    covered: Vec<bool>,
+    /// Given symbols and the labels of all jump and call targets
+    labels: Symbols,
+    /// Symbols outside of ROM, used in place of memory operands
+    constants: BTreeMap<u16, String>,
+}
+
+impl<'a> Disassembly<'a> {
+    /// Follows the code of `rom`, labels from `symbols` are used instead of generated ones.
+    pub fn new(rom: &'a [u8], symbols: &Symbols) -> Self {
+        let mut names = HashSet::new();
+        let constants = symbols
+            .iter()
+            // Local labels can't be defined as constants
+            .filter(|&(_, address, label)| address >= 0x8000 && !label.contains('.'))
+            .filter(|&(_, _, label)| names.insert(label))
+            .fold(BTreeMap::new(), |mut constants, (_, address, label)| {
+                constants.entry(address).or_insert(label.to_string());
+                constants
+            });
+
+        let mut disassembly = Self {
+            rom,
+            code: BTreeMap::new(),
+            covered: vec![false; rom.len()],
+            labels: symbols.clone(),
+            constants,
+        };
+
+        let mut pending = Vec::new();
+        for (address, label) in ENTRY_POINTS {
+            disassembly.labels.insert(0, address, label.to_string());
+            pending.push(Path {
+                bank: 0,
+                address,
+                switchable: None,
+            });
+        }
+
+        while let Some(path) = pending.pop() {
+            disassembly.follow(path, &mut pending);
+        }
+
+        disassembly
+    }
+
+    /// The given symbols together with the generated labels, to be written as `.sym` file.
+    pub fn symbols(&self) -> &Symbols {
+        &self.labels
+    }
+
+    /// Decodes instructions from the start of `path` until the code jumps away
+    /// or runs into code that was already followed.
+    fn follow(&mut self, path: Path, pending: &mut Vec<Path>) {
+        let Path {
+            bank,
+            mut address,
+            mut switchable,
+        } = path;
+
+        let rom = self.rom;
+        let bank_end = if bank == 0 { 0x4000 } else { 0x8000 };
+        // Value of A, as long as it is a known constant
+        let mut a = None;
+
+        while let Some(offset) = self.offset(bank, address) {
+            if self.covered[offset] || ILLEGAL_OPCODES.contains(&rom[offset]) {
+                break;
+            }
+
+            let instruction = Instruction::decode(address, |at| {
+                let offset = offset + at.wrapping_sub(address) as usize;
+                rom.get(offset).copied().unwrap_or(0)
+            });
+
+            let end = offset + instruction.bytes.len();
+            if address as usize + instruction.bytes.len() > bank_end
+                || end > rom.len()
+                || self.covered[offset..end].contains(&true)
+            {
+                break;
+            }
+            self.covered[offset..end].fill(true);
+
+            let bytes = &instruction.bytes;
+            let next = instruction.next_address();
+            let operand = || u16::from_le_bytes([bytes[1], bytes[2]]);
+            let relative = || next.wrapping_add_signed(bytes[1] as i8 as i16);
+
+            let (target, call, ends_flow) = match bytes[0] {
+                0xC3 => (Some(operand()), false, true),
+                0xC2 | 0xCA | 0xD2 | 0xDA => (Some(operand()), false, false),
+                0x18 => (Some(relative()), false, true),
+                0x20 | 0x28 | 0x30 | 0x38 => (Some(relative()), false, false),
+                0xCD | 0xC4 | 0xCC | 0xD4 | 0xDC => (Some(operand()), true, false),
+                op if op & 0xC7 == 0xC7 => (Some((op & 0x38) as u16), true, false),
+                0xC9 | 0xD9 | 0xE9 => (None, false, true),
+                _ => (None, false, false),
+            };
+
+            if bytes[0] == 0xEA && (0x2000..=0x3FFF).contains(&operand()) {
+                switchable = a.map(|a| self.rom_bank(a));
+            }
+
+            a = match bytes[0] {
+                0x3E => Some(bytes[1]),
+                // XOR A
+                0xAF => Some(0),
+                // NOP and stores of A
+                0x00 | 0x02 | 0x12 | 0x22 | 0x32 | 0x77 | 0xE0 | 0xEA => a,
+                _ => None,
+            };
+
+            let target = target.and_then(|target| self.resolve(bank, switchable, target));
+            if let Some((target_bank, target_address)) = target {
+                let kind = if call { "Call" } else { "Jump" };
+                self.labels.insert(
+                    target_bank,
+                    target_address,
+                    format!("{kind}_{target_bank:03X}_{target_address:04X}"),
+                );
+
+                pending.push(Path {
+                    bank: target_bank,
+                    address: target_address,
+                    switchable,
+                });
+            }
+
+            self.code.insert(
+                offset,
+                Code {
+                    instruction,
+                    target,
+                    ends_flow,
+                },
+            );
+
+            if ends_flow {
+                break;
+            }
+            address = next;
+        }
+    }
+
+    fn bank_count(&self) -> u16 {
+        self.rom.len().div_ceil(BANK_SIZE).max(2) as u16
+    }
+
+    /// Bank mapped to 0x4000-0x7FFF after `value` is written to the MBC.
+    fn rom_bank(&self, value: u8) -> u16 {
+        (value as u16 % self.bank_count()).max(1)
+    }
+
+    fn offset(&self, bank: u16, address: u16) -> Option<usize> {
+        let offset = match (bank, address) {
+            (0, 0x0000..=0x3FFF) => address as usize,
+            (1.., 0x4000..=0x7FFF) => bank as usize * BANK_SIZE + address as usize - BANK_SIZE,
+            _ => return None,
+        };
+
+        (offset < self.rom.len()).then_some(offset)
+    }
+
+    /// Bank of `address` when jumped to from code in `bank`, `None` if it is unknown or not in ROM.
+    fn resolve(&self, bank: u16, switchable: Option<u16>, address: u16) -> Option<(u16, u16)> {
+        let bank = match address {
+            0x0000..=0x3FFF => 0,
+            0x4000..=0x7FFF if bank != 0 => bank,
+            0x4000..=0x7FFF if self.bank_count() == 2 => 1,
+            0x4000..=0x7FFF => switchable?,
+            _ => return None,
+        };
+
+        self.offset(bank, address).map(|_| (bank, address))
+    }
+
+    /// Label at `address`, only if it is at the start of an instruction or data.
+    fn label(&self, bank: u16, address: u16) -> Option<&str> {
+        let offset = self.offset(bank, address)?;
+        if self.covered[offset] && !self.code.contains_key(&offset) {
+            return None;
+        }
+
+        self.labels.get(bank, address)
+    }
+
+    fn constant(&self, address: u16) -> String {
+        self.constants
+            .get(&address)
+            .cloned()
+            .unwrap_or_else(|| format!("${address:04X}"))
+    }
+
+    /// `code` in rgbds syntax, with labels in place of addresses where possible.
+    fn assembly(&self, code: &Code) -> String {
+        let instruction = &code.instruction;
+        let bytes = &instruction.bytes;
+        let name = instruction
+            .opcode
+            .name
+            .to_lowercase()
+            .replace('(', "[")
+            .replace(')', "]");
+
+        match bytes[0] {
+            0xCB => return name,
+            // STOP is followed by a byte that rgbds always assembles as 0
+            0x10 if bytes[1] != 0 => return format!("db $10, ${:02X}", bytes[1]),
+            0x10 => return "stop".to_string(),
+            0xE0 => return format!("ldh [{}], a", self.constant(0xFF00 | bytes[1] as u16)),
+            0xF0 => return format!("ldh a, [{}]", self.constant(0xFF00 | bytes[1] as u16)),
+            0xE2 => return "ldh [c], a".to_string(),
+            0xF2 => return "ldh a, [c]".to_string(),
+            0xE8 => return format!("add sp, {}", bytes[1] as i8),
+            0xF8 => {
+                let offset = bytes[1] as i8;
+                let sign = if offset < 0 { '-' } else { '+' };
+                return format!("ld hl, sp {sign} {}", offset.unsigned_abs());
+            }
+            op if op & 0xC7 == 0xC7 => return format!("rst ${:02X}", op & 0x38),
+            _ => {}
+        }
+
+        let target = code
+            .target
+            .and_then(|(bank, address)| self.label(bank, address))
+            .map(str::to_string);
+
+        if name.contains("u16") || name.contains("a16") {
+            let value = u16::from_le_bytes([bytes[1], bytes[2]]);
+            let operand = target.unwrap_or_else(|| match name.contains('[') {
+                true => self.constant(value),
+                false => format!("${value:04X}"),
+            });
+
+            name.replace("u16", &operand).replace("a16", &operand)
+        } else if name.contains("s8") {
+            let address = instruction
+                .next_address()
+                .wrapping_add_signed(bytes[1] as i8 as i16);
+            let operand = target.unwrap_or_else(|| format!("${address:04X}"));
+
+            name.replace("s8", &operand)
+        } else if name.contains("u8") {
+            name.replace("u8", &format!("${:02X}", bytes[1]))
+        } else {
+            name
+        }
+    }
+}
+
+/// The `.asm` listing, one section per bank.
+impl Display for Disassembly<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "; Disassembled by kevboy")?;
+
+        if !self.constants.is_empty() {
+            writeln!(f)?;
+        }
+        for (address, name) in &self.constants {
+            writeln!(f, "DEF {name} EQU ${address:04X}")?;
+        }
+
+        for bank in 0..self.bank_count() {
+            let start = bank as usize * BANK_SIZE;
+            let end = (start + BANK_SIZE).min(self.rom.len());
+            if start >= end {
+                break;
+            }
+
+            let base = if bank == 0 { 0x0000 } else { 0x4000 };
+            let address = |offset: usize| (base + offset - start) as u16;
+
+            writeln!(f)?;
+            match bank {
+                0 => writeln!(f, "SECTION \"ROM Bank $000\", ROM0[$0000]")?,
+                _ => writeln!(
+                    f,
+                    "SECTION \"ROM Bank ${bank:03X}\", ROMX[$4000], BANK[${bank:X}]"
+                )?,
+            }
+            writeln!(f)?;
+
+            let mut offset = start;
+            while offset < end {
+                if let Some(label) = self.labels.get(bank, address(offset)) {
+                    writeln!(f, "{label}:")?;
+                }
+
+                if let Some(code) = self.code.get(&offset) {
+                    writeln!(f, "    {}", self.assembly(code))?;
+                    if code.ends_flow {
+                        writeln!(f)?;
+                    }
+
+                    offset += code.instruction.bytes.len();
+                } else {
+                    let data_end = (offset + 1..end)
+                        .find(|&o| self.covered[o] || self.labels.get(bank, address(o)).is_some())
+                        .unwrap_or(end);
+
+                    write_data(f, &self.rom[offset..data_end])?;
+                    offset = data_end;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes `data` as `db` lines of up to 8 bytes, long runs of the same byte as `ds`.
+fn write_data(f: &mut std::fmt::Formatter<'_>, mut data: &[u8]) -> std::fmt::Result {
+    const MIN_RUN: usize = 16;
+    let run_length = |data: &[u8]| data.iter().take_while(|&&b| b == data[0]).count();
+
+    while !data.is_empty() {
+        let run = run_length(data);
+        if run >= MIN_RUN {
+            writeln!(f, "    ds {run}, ${:02X}", data[0])?;
+            data = &data[run..];
+            continue;
+        }
+
+        let length = (1..data.len().min(8))
+            .find(|&i| run_length(&data[i..]) >= MIN_RUN)
+            .unwrap_or(data.len().min(8));
+
+        let bytes: Vec<String> = data[..length].iter().map(|b| format!("${b:02X}")).collect();
+        writeln!(f, "    db {}", bytes.join(", "))?;
+        data = &data[length..];
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two banks of illegal opcodes, so every entry point stops right away.
+    fn rom() -> Vec<u8> {
+        let mut rom = vec![0xDD; 2 * BANK_SIZE];
+        // nop, call $3FFE, jp $7FFD
+        rom[0x100..0x107].copy_from_slice(&[0x00, 0xCD, 0xFE, 0x3F, 0xC3, 0xFD, 0x7F]);
+        rom
+    }
+
+    #[test]
+    fn stops_at_illegal_opcodes() {
+        let mut rom = rom();
+        // RST 08: nop, illegal, nop
+        rom[0x08..0x0B].copy_from_slice(&[0x00, 0xDD, 0x00]);
+        let disassembly = Disassembly::new(&rom, &Symbols::default());
+
+        assert!(disassembly.code.contains_key(&0x08));
+        assert!(!disassembly.covered[0x09]);
+        assert!(!disassembly.covered[0x0A]);
+        assert!(disassembly
+            .to_string()
+            .contains("RST_08:\n    nop\n    db $DD, $00,"));
+    }
+
+    #[test]
+    fn stops_at_bank_ends() {
+        let mut rom = rom();
+        // nop, then ld bc, u16 across the end of bank 0
+        rom[0x3FFE..0x4002].copy_from_slice(&[0x00, 0x01, 0x00, 0x00]);
+        // nop, nop, then jp u16 across the end of bank 1
+        rom[0x7FFD..0x8000].copy_from_slice(&[0x00, 0x00, 0xC3]);
+        let disassembly = Disassembly::new(&rom, &Symbols::default());
+
+        assert!(disassembly.covered[0x3FFE]);
+        assert!(!disassembly.covered[0x3FFF]);
+        assert!(!disassembly.covered[0x4000]);
+        assert_eq!(disassembly.symbols().get(0, 0x3FFE), Some("Call_000_3FFE"));
+
+        assert!(disassembly.covered[0x7FFE]);
+        assert!(!disassembly.covered[0x7FFF]);
+        assert_eq!(disassembly.symbols().get(1, 0x7FFD), Some("Jump_001_7FFD"));
+    }
+}
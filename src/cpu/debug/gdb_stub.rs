@@ -0,0 +1,668 @@
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+};
+
+use crate::{
+    cpu::debug::{
+        debugger::{Break, Breakpoint, Debugger},
+        watchpoint::{Access, Watchpoint},
+    },
+    emulator::Emulator,
+    mmu::mmio::MMIO,
+};
+
+/// Largest packet GDB may send us, in bytes.
+const PACKET_SIZE: usize = 0x1000;
+
+/// Registers in the order of `g` and `p`, all 16 bits wide and little endian.
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.kevboy.sm83">
+    <reg name="af" bitsize="16" type="uint16" regnum="0"/>
+    <reg name="bc" bitsize="16" type="uint16"/>
+    <reg name="de" bitsize="16" type="uint16"/>
+    <reg name="hl" bitsize="16" type="uint16"/>
+    <reg name="sp" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>
+"#;
+
+/// Server for the GDB remote serial protocol on a local TCP port,
+/// so GDB and other tools can debug the running game.
+///
+/// It is polled every frame and answers requests right away. `c` lets emulation
+/// run until the debugger pauses it, which is then reported to the client.
+pub struct GdbStub {
+    listener: TcpListener,
+    client: Option<TcpStream>,
+    buffer: Vec<u8>,
+    /// Packets aren't acknowledged with `+` anymore after `QStartNoAckMode`
+    no_ack: bool,
+    /// The client continued and waits for a stop reply
+    running: bool,
+}
+
+impl GdbStub {
+    /// Listens for a client on `port` of the loopback interface, 0 picks a free port.
+    pub fn bind(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        listener.set_nonblocking(true)?;
+
+        Ok(Self {
+            listener,
+            client: None,
+            buffer: Vec::new(),
+            no_ack: false,
+            running: false,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.client.is_some()
+    }
+
+    /// Accepts a client, answers its requests and reports when emulation paused.
+    ///
+    /// `pause` is the pause flag of the frontend, emulation pauses as soon as a client attaches.
+    /// On errors the client is dropped and emulation continues.
+    pub fn poll(
+        &mut self,
+        emulator: &mut Emulator,
+        debugger: &mut Debugger,
+        pause: &mut bool,
+    ) -> io::Result<()> {
+        if self.client.is_none() {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    stream.set_nonblocking(true)?;
+                    stream.set_nodelay(true)?;
+
+                    self.client = Some(stream);
+                    self.buffer.clear();
+                    self.no_ack = false;
+                    self.running = false;
+                    *pause = true;
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e),
+            }
+        }
+
+        let result = self.receive(emulator, debugger, pause).and_then(|_| {
+            if self.running && *pause {
+                self.running = false;
+                self.send(&stop_reply(debugger.last_break()))?;
+            }
+            Ok(())
+        });
+
+        if result.is_err() {
+            self.detach(debugger, pause);
+        }
+
+        result
+    }
+
+    /// Reads everything the client sent and handles the complete packets.
+    fn receive(
+        &mut self,
+        emulator: &mut Emulator,
+        debugger: &mut Debugger,
+        pause: &mut bool,
+    ) -> io::Result<()> {
+        let mut chunk = [0; 1024];
+
+        while let Some(client) = &mut self.client {
+            match client.read(&mut chunk) {
+                Ok(0) => {
+                    self.detach(debugger, pause);
+                    return Ok(());
+                }
+                Ok(length) => self.buffer.extend_from_slice(&chunk[..length]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        while let Some(packet) = self.next_packet(pause)? {
+            if let Some(reply) = self.handle(&packet, emulator, debugger, pause) {
+                self.send(&reply)?;
+            }
+            if packet.starts_with('D') {
+                self.detach(debugger, pause);
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Takes the next complete packet `$<data>#<checksum>` out of the buffer.
+    ///
+    /// Acknowledgments in between are skipped, an interrupt (Ctrl-C) pauses emulation.
+    fn next_packet(&mut self, pause: &mut bool) -> io::Result<Option<String>> {
+        loop {
+            match self.buffer.first() {
+                None => return Ok(None),
+                Some(b'$') => {}
+                Some(0x03) => {
+                    *pause = true;
+                    self.buffer.remove(0);
+                    continue;
+                }
+                Some(_) => {
+                    self.buffer.remove(0);
+                    continue;
+                }
+            }
+
+            let Some(end) = self.buffer.iter().position(|&b| b == b'#') else {
+                return Ok(None);
+            };
+            if self.buffer.len() < end + 3 {
+                return Ok(None);
+            }
+
+            let packet: Vec<u8> = self.buffer.drain(..end + 3).collect();
+            let data = &packet[1..end];
+            let valid = std::str::from_utf8(&packet[end + 1..])
+                .ok()
+                .and_then(|checksum| u8::from_str_radix(checksum, 16).ok())
+                == Some(checksum(data));
+
+            if !self.no_ack {
+                self.write(if valid { b"+" } else { b"-" })?;
+            }
+            if valid {
+                return Ok(Some(String::from_utf8_lossy(data).into_owned()));
+            }
+        }
+    }
+
+    /// Executes a request and returns the reply, `None` if there is none (yet).
+    fn handle(
+        &mut self,
+        packet: &str,
+        emulator: &mut Emulator,
+        debugger: &mut Debugger,
+        pause: &mut bool,
+    ) -> Option<String> {
+        let error = || "E01".to_string();
+        let ok = |done: Option<()>| done.map_or_else(error, |_| "OK".to_string());
+
+        let Some(command) = packet.chars().next() else {
+            return Some(String::new());
+        };
+        let args = &packet[command.len_utf8()..];
+
+        let reply = match command {
+            '?' => stop_reply(debugger.last_break()),
+            'g' => registers(emulator)
+                .iter()
+                .map(|r| hex(&r.to_le_bytes()))
+                .collect(),
+            'G' => ok(unhex(args).and_then(|bytes| {
+                (bytes.len() == 12).then(|| {
+                    for (index, value) in bytes.chunks(2).enumerate() {
+                        set_register(emulator, index, u16::from_le_bytes([value[0], value[1]]));
+                    }
+                })
+            })),
+            'p' => usize::from_str_radix(args, 16)
+                .ok()
+                .and_then(|index| registers(emulator).get(index).copied())
+                .map_or_else(error, |r| hex(&r.to_le_bytes())),
+            'P' => ok(args.split_once('=').and_then(|(index, value)| {
+                let index = usize::from_str_radix(index, 16).ok().filter(|&i| i < 6)?;
+                let value = unhex(value).filter(|v| v.len() == 2)?;
+                set_register(emulator, index, u16::from_le_bytes([value[0], value[1]]));
+                Some(())
+            })),
+            'm' => address_length(args).map_or_else(error, |(address, length)| {
+                let length = length.min(PACKET_SIZE / 2 - 4);
+                let bytes: Vec<u8> = (0..length)
+                    .map(|i| emulator.bus.peek(address.wrapping_add(i as u16)))
+                    .collect();
+                hex(&bytes)
+            }),
+            'M' => ok(args.split_once(':').and_then(|(range, data)| {
+                let (address, length) = address_length(range)?;
+                let bytes = unhex(data).filter(|b| b.len() == length)?;
+                for (i, byte) in bytes.into_iter().enumerate() {
+                    emulator.bus.poke(address.wrapping_add(i as u16), byte);
+                }
+                Some(())
+            })),
+            'Z' | 'z' => return Some(breakpoint(command == 'Z', args, emulator, debugger)),
+            's' => {
+                let pc = emulator.cpu.registers.PC;
+                emulator.cycle_count += emulator.step() as u16;
+                debugger.check_watchpoints(&mut emulator.bus, pc);
+
+                stop_reply(debugger.last_break())
+            }
+            'c' => {
+                debugger.resume();
+                *pause = false;
+                self.running = true;
+                return None;
+            }
+            // The connection is closed once the reply went out
+            'D' => "OK".to_string(),
+            'k' => {
+                self.detach(debugger, pause);
+                return None;
+            }
+            'q' => query(args),
+            'Q' if args == "StartNoAckMode" => {
+                self.no_ack = true;
+                "OK".to_string()
+            }
+            'H' | 'T' => "OK".to_string(),
+            _ => String::new(),
+        };
+
+        Some(reply)
+    }
+
+    /// Drops the client and lets emulation continue.
+    fn detach(&mut self, debugger: &mut Debugger, pause: &mut bool) {
+        if self.client.take().is_some() {
+            debugger.resume();
+            *pause = false;
+        }
+        self.running = false;
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let packet = format!("${data}#{:02x}", checksum(data.as_bytes()));
+        self.write(packet.as_bytes())
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let Some(client) = &mut self.client else {
+            return Ok(());
+        };
+
+        // Replies are small, but a full socket buffer must not fail the write
+        client.set_nonblocking(false)?;
+        let result = client.write_all(bytes);
+        client.set_nonblocking(true)?;
+
+        result
+    }
+}
+
+/// The reply to `?` and to resuming: which signal stopped the target and why.
+fn stop_reply(reason: Option<Break>) -> String {
+    match reason {
+        Some(Break::Watchpoint { hit, .. }) => {
+            let kind = match hit.access {
+                Access::Read => "rwatch",
+                Access::Write => "watch",
+            };
+            format!("T05{kind}:{:04x};", hit.address)
+        }
+        _ => "S05".to_string(),
+    }
+}
+
+/// `qSupported` and the target description, everything else is unsupported.
+fn query(args: &str) -> String {
+    if args.starts_with("Supported") {
+        return format!("PacketSize={PACKET_SIZE:x};qXfer:features:read+;QStartNoAckMode+");
+    }
+
+    if let Some(range) = args.strip_prefix("Xfer:features:read:target.xml:") {
+        let Some((offset, length)) = range.split_once(',').and_then(|(offset, length)| {
+            Some((
+                usize::from_str_radix(offset, 16).ok()?,
+                usize::from_str_radix(length, 16).ok()?,
+            ))
+        }) else {
+            return "E01".to_string();
+        };
+
+        // 'm' means there is more, 'l' that it was the last part
+        let rest = TARGET_XML.get(offset..).unwrap_or_default();
+        return match rest.get(..length) {
+            Some(part) if part.len() < rest.len() => format!("m{part}"),
+            _ => format!("l{rest}"),
+        };
+    }
+
+    match args {
+        "Attached" => "1".to_string(),
+        "C" => "QC1".to_string(),
+        "fThreadInfo" => "m1".to_string(),
+        "sThreadInfo" => "l".to_string(),
+        _ => String::new(),
+    }
+}
+
+/// Inserts (`Z`) or removes (`z`) a breakpoint or watchpoint, `args` is `<type>,<address>,<kind>`.
+///
+/// Software and hardware breakpoints are the same to us, watchpoints watch `kind` bytes.
+fn breakpoint(
+    insert: bool,
+    args: &str,
+    emulator: &mut Emulator,
+    debugger: &mut Debugger,
+) -> String {
+    let mut parts = args.split(',');
+    let (Some(kind), Some((address, length))) = (
+        parts.next(),
+        parts
+            .next()
+            .zip(parts.next())
+            .and_then(|(address, length)| {
+                Some((
+                    u16::from_str_radix(address, 16).ok()?,
+                    u16::from_str_radix(length.split(';').next()?, 16).ok()?,
+                ))
+            }),
+    ) else {
+        return "E01".to_string();
+    };
+
+    match kind {
+        "0" | "1" => {
+            debugger
+                .breakpoints
+                .retain(|b| b.address != address || b.condition.is_some());
+            if insert {
+                debugger.breakpoints.push(Breakpoint {
+                    address,
+                    condition: None,
+                    enabled: true,
+                });
+            }
+        }
+        "2" | "3" | "4" => {
+            let (read, write) = match kind {
+                "2" => (false, true),
+                "3" => (true, false),
+                _ => (true, true),
+            };
+            let range = address..=address.saturating_add(length.max(1) - 1);
+
+            let list = &mut emulator.bus.watchpoints.list;
+            list.retain(|w| {
+                w.range != range || w.read != read || w.write != write || w.condition.is_some()
+            });
+            if insert {
+                list.push(Watchpoint {
+                    range,
+                    read,
+                    write,
+                    condition: None,
+                    enabled: true,
+                });
+            }
+        }
+        _ => return String::new(),
+    }
+
+    "OK".to_string()
+}
+
+/// AF, BC, DE, HL, SP and PC.
+fn registers(emulator: &Emulator) -> [u16; 6] {
+    let r = &emulator.cpu.registers;
+    [r.get_af(), r.get_bc(), r.get_de(), r.get_hl(), r.SP, r.PC]
+}
+
+fn set_register(emulator: &mut Emulator, index: usize, value: u16) {
+    let r = &mut emulator.cpu.registers;
+    match index {
+        0 => r.set_af(value),
+        1 => r.set_bc(value),
+        2 => r.set_de(value),
+        3 => r.set_hl(value),
+        4 => r.SP = value,
+        _ => r.PC = value,
+    }
+}
+
+/// Parses `<address>,<length>` of memory requests.
+fn address_length(args: &str) -> Option<(u16, usize)> {
+    let (address, length) = args.split_once(',')?;
+    Some((
+        u16::from_str_radix(address, 16).ok()?,
+        usize::from_str_radix(length, 16).ok()?,
+    ))
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn unhex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{ErrorKind, Read, Write},
+        net::TcpStream,
+        time::{Duration, Instant},
+    };
+
+    use super::{checksum, GdbStub};
+    use crate::{
+        cpu::debug::debugger::Debugger,
+        emulator::{tests::test_rom, Emulator},
+    };
+
+    /// Writes increasing values to $C000 forever:
+    ///
+    /// ```text
+    /// 0100: ld a, $12
+    /// 0102: ld [$C000], a
+    /// 0105: inc a
+    /// 0106: jr $0102
+    /// ```
+    fn rom() -> Vec<u8> {
+        test_rom(&[0x3E, 0x12, 0xEA, 0x00, 0xC0, 0x3C, 0x18, 0xFA])
+    }
+
+    /// The stub and the run loop of the frontend, driven by a scripted client.
+    struct Session {
+        emulator: Emulator,
+        debugger: Debugger,
+        stub: GdbStub,
+        pause: bool,
+        client: TcpStream,
+    }
+
+    impl Session {
+        fn start() -> Self {
+            let mut emulator = Emulator::new();
+            emulator.load_rom(&rom(), None).unwrap();
+
+            let stub = GdbStub::bind(0).unwrap();
+            let client = TcpStream::connect(stub.local_addr().unwrap()).unwrap();
+            client.set_nonblocking(true).unwrap();
+
+            let mut session = Self {
+                emulator,
+                debugger: Debugger::new(),
+                stub,
+                pause: false,
+                client,
+            };
+
+            let start = Instant::now();
+            while !session.stub.is_connected() {
+                assert!(start.elapsed() < Duration::from_secs(5), "never connected");
+                session.poll();
+            }
+            assert!(session.pause);
+
+            session
+        }
+
+        fn poll(&mut self) {
+            self.stub
+                .poll(&mut self.emulator, &mut self.debugger, &mut self.pause)
+                .unwrap();
+
+            // Like the frontend, but a few instructions at a time instead of whole frames
+            for _ in 0..100 {
+                if self.pause {
+                    break;
+                }
+                if self.debugger.check(&self.emulator.cpu).is_some() {
+                    self.pause = true;
+                    break;
+                }
+
+                let pc = self.emulator.cpu.registers.PC;
+                self.emulator.step();
+                if self
+                    .debugger
+                    .check_watchpoints(&mut self.emulator.bus, pc)
+                    .is_some()
+                {
+                    self.pause = true;
+                }
+            }
+        }
+
+        /// Sends `data` as a packet and returns the reply without its framing.
+        fn request(&mut self, data: &str) -> String {
+            let packet = format!("${data}#{:02x}", checksum(data.as_bytes()));
+            self.client.write_all(packet.as_bytes()).unwrap();
+
+            let mut received = Vec::new();
+            let start = Instant::now();
+            loop {
+                assert!(
+                    start.elapsed() < Duration::from_secs(5),
+                    "no reply to {data}"
+                );
+                self.poll();
+
+                let mut chunk = [0; 256];
+                match self.client.read(&mut chunk) {
+                    Ok(length) => received.extend_from_slice(&chunk[..length]),
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                    Err(e) => panic!("{e}"),
+                }
+
+                let text = String::from_utf8_lossy(&received);
+                let text = text.trim_start_matches('+');
+                if let Some((reply, rest)) = text.strip_prefix('$').and_then(|t| t.split_once('#'))
+                {
+                    if rest.len() >= 2 {
+                        assert_eq!(rest[..2], format!("{:02x}", checksum(reply.as_bytes())));
+                        self.client.write_all(b"+").unwrap();
+                        return reply.to_string();
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn registers() {
+        let mut session = Session::start();
+
+        let registers = session.request("g");
+        assert_eq!(registers.len(), 6 * 4);
+        assert!(registers.ends_with("0001"), "{registers}");
+
+        assert_eq!(session.request("P5=0201"), "OK");
+        assert_eq!(session.request("p5"), "0201");
+        assert_eq!(session.emulator.cpu.registers.PC, 0x0102);
+
+        assert_eq!(session.request("P1=3412"), "OK");
+        assert_eq!(session.emulator.cpu.registers.get_bc(), 0x1234);
+        assert_eq!(session.request("p6"), "E01");
+    }
+
+    #[test]
+    fn memory() {
+        let mut session = Session::start();
+
+        assert_eq!(session.request("Mc000,2:abcd"), "OK");
+        assert_eq!(session.request("mc000,2"), "abcd");
+        assert_eq!(session.request("m100,3"), "3e12ea");
+        assert_eq!(session.request("Mc000,2:ab"), "E01");
+
+        // ROM is patched instead of written to the MBC, and no time passes
+        let div = session.emulator.bus.timer.div;
+        assert_eq!(session.request("M2000,1:05"), "OK");
+        assert_eq!(session.request("m2000,1"), "05");
+        assert_eq!(session.emulator.bus.timer.div, div);
+    }
+
+    #[test]
+    fn step_and_breakpoint() {
+        let mut session = Session::start();
+
+        assert_eq!(session.request("s"), "S05");
+        assert_eq!(session.emulator.cpu.registers.PC, 0x0102);
+        assert_eq!(session.emulator.cpu.registers.A, 0x12);
+
+        assert_eq!(session.request("Z0,105,1"), "OK");
+        assert_eq!(session.request("c"), "S05");
+        assert_eq!(session.emulator.cpu.registers.PC, 0x0105);
+
+        // Continuing runs through the loop once before it hits again
+        assert_eq!(session.request("c"), "S05");
+        assert_eq!(session.emulator.cpu.registers.PC, 0x0105);
+        assert_eq!(session.emulator.cpu.registers.A, 0x13);
+
+        assert_eq!(session.request("z0,105,1"), "OK");
+        assert!(session.debugger.breakpoints.is_empty());
+    }
+
+    #[test]
+    fn watchpoint() {
+        let mut session = Session::start();
+
+        assert_eq!(session.request("Z2,c000,1"), "OK");
+        assert_eq!(session.request("c"), "T05watch:c000;");
+        assert_eq!(session.emulator.cpu.registers.PC, 0x0105);
+        assert_eq!(session.request("?"), "T05watch:c000;");
+
+        assert_eq!(session.request("z2,c000,1"), "OK");
+        assert!(session.emulator.bus.watchpoints.list.is_empty());
+    }
+
+    #[test]
+    fn detach() {
+        let mut session = Session::start();
+
+        assert!(session
+            .request("qSupported:multiprocess+")
+            .contains("PacketSize=1000"));
+        assert!(session
+            .request("qXfer:features:read:target.xml:0,20")
+            .starts_with('m'));
+        assert_eq!(session.request("D"), "OK");
+        assert!(!session.stub.is_connected());
+        assert!(!session.pause);
+    }
+}
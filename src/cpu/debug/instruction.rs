@@ -0,0 +1,89 @@
+use std::fmt::Display;
+
+use crate::cpu::debug::opcode::{OpCode, CB_INSTRUCTIONS, INSTRUCTIONS};
+
+/// A decoded instruction, its operands are taken from `bytes`.
+#[derive(Debug, Clone)]
+pub struct Instruction {
+    pub address: u16,
+    pub opcode: OpCode<'static>,
+    pub bytes: Vec<u8>,
+}
+
+impl Instruction {
+    /// Decodes the instruction at `address`, `read` returns the byte at an address.
+    ///
+    /// CB-prefixed instructions are looked up in `CB_INSTRUCTIONS`.
+    pub fn decode(address: u16, mut read: impl FnMut(u16) -> u8) -> Self {
+        let first = read(address);
+        let opcode = if first == 0xCB {
+            CB_INSTRUCTIONS[read(address.wrapping_add(1)) as usize]
+        } else {
+            INSTRUCTIONS[first as usize]
+        };
+
+        let bytes = (0..opcode.bytes as u16)
+            .map(|i| read(address.wrapping_add(i)))
+            .collect();
+
+        Self {
+            address,
+            opcode,
+            bytes,
+        }
+    }
+
+    /// Address of the instruction that follows in memory.
+    pub fn next_address(&self) -> u16 {
+        self.address.wrapping_add(self.bytes.len() as u16)
+    }
+
+    /// `CALL` and `RST` push a return address, stepping over them runs until it is reached.
+    pub fn is_call(&self) -> bool {
+        self.bytes[0] != 0xCB
+            && (self.opcode.name.starts_with("CALL") || self.opcode.name.starts_with("RST"))
+    }
+
+    fn operand_u8(&self) -> u8 {
+        self.bytes[1]
+    }
+
+    fn operand_u16(&self) -> u16 {
+        u16::from_le_bytes([self.bytes[1], self.bytes[2]])
+    }
+}
+
+/// Mnemonic with the operands filled in, e.g. `JP NZ, $0150`.
+impl Display for Instruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = self.opcode.name;
+
+        if self.bytes[0] == 0xCB || self.bytes.len() == 1 {
+            return write!(f, "{name}");
+        }
+
+        let text = if name.contains("u16") || name.contains("a16") {
+            let operand = format!("${:04X}", self.operand_u16());
+            name.replace("u16", &operand).replace("a16", &operand)
+        } else if name.contains("(u8)") {
+            name.replace("(u8)", &format!("($FF{:02X})", self.operand_u8()))
+        } else if name.contains("u8") {
+            name.replace("u8", &format!("${:02X}", self.operand_u8()))
+        } else if name.starts_with("JR") {
+            let offset = self.operand_u8() as i8;
+            let target = self.next_address().wrapping_add_signed(offset as i16);
+            name.replace("s8", &format!("${target:04X}"))
+        } else if name.contains("+ s8") {
+            let offset = self.operand_u8() as i8;
+            let sign = if offset < 0 { '-' } else { '+' };
+            name.replace("+ s8", &format!("{sign} {}", offset.unsigned_abs()))
+        } else if name.contains("s8") {
+            name.replace("s8", &(self.operand_u8() as i8).to_string())
+        } else {
+            // STOP is followed by a byte that is not an operand
+            name.to_string()
+        };
+
+        write!(f, "{text}")
+    }
+}
@@ -1 +1,8 @@
+pub mod debugger;
+pub mod disassembler;
+pub mod gdb_stub;
+pub mod instruction;
 pub mod opcode;
+pub mod symbols;
+pub mod trace;
+pub mod watchpoint;
@@ -5,7 +5,7 @@ pub const INSTRUCTIONS: [OpCode; 256] = [
     OpCode {name: "LD (BC), A", op_code: 0x02, bytes: 1, m_cycles: 2, t_cycles: 8},
     OpCode {name: "INC BC", op_code: 0x03, bytes: 1, m_cycles: 2, t_cycles: 8},
     OpCode {name: "INC B", op_code: 0x04, bytes: 1, m_cycles: 1, t_cycles: 4},
-    OpCode {name: "DEC BC", op_code: 0x05, bytes: 1, m_cycles: 1, t_cycles: 4},
+    OpCode {name: "DEC B", op_code: 0x05, bytes: 1, m_cycles: 1, t_cycles: 4},
     OpCode {name: "LD B, u8", op_code: 0x06, bytes: 2, m_cycles: 2, t_cycles: 8},
     OpCode {name: "RLCA", op_code: 0x07, bytes: 1, m_cycles: 1, t_cycles: 4},
     OpCode {name: "LD (a16), SP", op_code: 0x08, bytes: 3, m_cycles: 5, t_cycles: 20},
@@ -0,0 +1,132 @@
+use std::{collections::BTreeMap, fmt::Display, str::FromStr};
+
+/// Labels by bank and address, as listed in the `.sym` files of rgbds.
+///
+/// Addresses in bank 0 are used for ROM0, WRAM0, HRAM and everything
+/// else that can't be switched.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Symbols {
+    labels: BTreeMap<(u16, u16), String>,
+}
+
+impl Symbols {
+    pub fn get(&self, bank: u16, address: u16) -> Option<&str> {
+        self.labels.get(&(bank, address)).map(String::as_str)
+    }
+
+    /// Adds `label`, keeps the label that is already at this address.
+    pub fn insert(&mut self, bank: u16, address: u16, label: String) {
+        self.labels.entry((bank, address)).or_insert(label);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u16, u16, &str)> {
+        self.labels
+            .iter()
+            .map(|(&(bank, address), label)| (bank, address, label.as_str()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+}
+
+/// Parses lines of `bank:address label`, both in hexadecimal.
+///
+/// Everything after a `;` is a comment.
+impl FromStr for Symbols {
+    type Err = SymbolError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut symbols = Symbols::default();
+
+        for (i, line) in s.lines().enumerate() {
+            let line = line.split(';').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let error = || SymbolError {
+                line: i + 1,
+                text: line.to_string(),
+            };
+
+            let (location, label) = line.split_once(char::is_whitespace).ok_or_else(error)?;
+            let (bank, address) = location.split_once(':').ok_or_else(error)?;
+            let bank = u16::from_str_radix(bank, 16).map_err(|_| error())?;
+            let address = u16::from_str_radix(address, 16).map_err(|_| error())?;
+
+            symbols.insert(bank, address, label.trim().to_string());
+        }
+
+        Ok(symbols)
+    }
+}
+
+/// Writes the symbols in the same format as rgblink.
+impl Display for Symbols {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "; File generated by kevboy")?;
+        for (bank, address, label) in self.iter() {
+            writeln!(f, "{bank:02x}:{address:04x} {label}")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SymbolError {
+    pub line: usize,
+    pub text: String,
+}
+
+impl Display for SymbolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Line {} is not of the form \"bank:address label\": {}",
+            self.line, self.text
+        )
+    }
+}
+
+impl std::error::Error for SymbolError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_labels_and_comments() {
+        let text = "; File generated by rgblink\n\
+                    00:0150 Main ; entry after the header\n\
+                    \n\
+                    01:4a2f Bank1.loop\n\
+                    00:ff80 hVBlankFlag\n";
+        let symbols: Symbols = text.parse().unwrap();
+
+        assert_eq!(symbols.get(0, 0x0150), Some("Main"));
+        assert_eq!(symbols.get(1, 0x4A2F), Some("Bank1.loop"));
+        assert_eq!(symbols.get(0, 0xFF80), Some("hVBlankFlag"));
+        assert_eq!(symbols.get(1, 0x0150), None);
+        assert_eq!(symbols.iter().count(), 3);
+    }
+
+    #[test]
+    fn rejects_lines_without_bank() {
+        let error = "00:0150 Main\n0150 Other\n".parse::<Symbols>().unwrap_err();
+        assert_eq!(error.line, 2);
+        assert_eq!(error.text, "0150 Other");
+    }
+
+    #[test]
+    fn export_then_import_round_trips() {
+        let mut symbols = Symbols::default();
+        symbols.insert(0, 0x0100, "EntryPoint".to_string());
+        symbols.insert(0x1F, 0x7FFF, "LastByte".to_string());
+        symbols.insert(2, 0x4000, "Bank2.start".to_string());
+
+        let imported: Symbols = symbols.to_string().parse().unwrap();
+        assert_eq!(imported, symbols);
+    }
+}
@@ -0,0 +1,122 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    ops::RangeInclusive,
+    path::Path,
+};
+
+use crate::{
+    cpu::CPU,
+    mmu::{bus::Bus, mmio::MMIO},
+};
+
+/// Which instructions are written to the trace, everything if all are `None`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TraceFilter {
+    /// ROM bank the code runs from, code outside of ROM never matches
+    pub bank: Option<usize>,
+    pub pc: Option<RangeInclusive<u16>>,
+    /// Number of frames after which the trace ends
+    pub frames: Option<u32>,
+}
+
+/// Logs the state before every instruction in the format of Gameboy Doctor:
+///
+/// `A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02`
+///
+/// so traces can be diffed line by line against the ones of other emulators.
+pub struct Tracer {
+    writer: BufWriter<File>,
+    filter: TraceFilter,
+    lines: u64,
+    /// Frames are counted every time LY wraps around to 0
+    frames: u32,
+    last_ly: u8,
+    /// Set once the frame limit is reached or writing failed
+    finished: bool,
+    error: Option<io::Error>,
+}
+
+impl Tracer {
+    /// Creates or truncates the trace file at `path`.
+    pub fn create(path: &Path, filter: TraceFilter) -> io::Result<Self> {
+        Ok(Self {
+            writer: BufWriter::new(File::create(path)?),
+            filter,
+            lines: 0,
+            frames: 0,
+            last_ly: 0,
+            finished: false,
+            error: None,
+        })
+    }
+
+    /// Writes the line for the instruction at PC if it passes the filter.
+    ///
+    /// `bank` is the ROM bank PC is in, `None` if it is outside of ROM.
+    pub fn log(&mut self, cpu: &CPU, bus: &mut Bus, bank: Option<usize>) {
+        if self.finished {
+            return;
+        }
+
+        let ly = bus.peek(0xFF44);
+        if ly < self.last_ly {
+            self.frames += 1;
+        }
+        self.last_ly = ly;
+
+        if self
+            .filter
+            .frames
+            .is_some_and(|frames| self.frames >= frames)
+        {
+            let result = self.writer.flush();
+            self.finish_with(result);
+            return;
+        }
+
+        let r = &cpu.registers;
+        let pc_matches = self.filter.pc.as_ref().is_none_or(|pc| pc.contains(&r.PC));
+        let bank_matches = self.filter.bank.is_none_or(|b| bank == Some(b));
+        if !pc_matches || !bank_matches {
+            return;
+        }
+
+        let pcmem = [0, 1, 2, 3].map(|i| bus.peek(r.PC.wrapping_add(i)));
+        let result = writeln!(
+            self.writer,
+            "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} PCMEM:{:02X},{:02X},{:02X},{:02X}",
+            r.A, r.F, r.B, r.C, r.D, r.E, r.H, r.L, r.SP, r.PC, pcmem[0], pcmem[1], pcmem[2], pcmem[3]
+        );
+
+        match result {
+            Ok(()) => self.lines += 1,
+            Err(e) => self.finish_with(Err(e)),
+        }
+    }
+
+    /// Lines written so far.
+    pub fn lines(&self) -> u64 {
+        self.lines
+    }
+
+    /// Whether the frame limit was reached or writing failed, nothing is logged anymore.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Flushes the file and returns the number of lines, or the error that stopped the trace.
+    pub fn finish(mut self) -> io::Result<u64> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+
+        self.writer.flush()?;
+        Ok(self.lines)
+    }
+
+    fn finish_with(&mut self, result: io::Result<()>) {
+        self.finished = true;
+        self.error = result.err();
+    }
+}
@@ -0,0 +1,125 @@
+use std::{fmt::Display, ops::RangeInclusive, str::FromStr};
+
+use crate::cpu::debug::debugger::{parse_hex, Comparison, ConditionError};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// Compares the value read or written with a constant, e.g. `== 0`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ValueCondition {
+    pub comparison: Comparison,
+    pub value: u8,
+}
+
+/// Parses `<operator> <value>`, a value without an operator has to match exactly.
+impl FromStr for ValueCondition {
+    type Err = ConditionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (comparison, value) = match Comparison::find(s) {
+            Some((0, length, comparison)) => (comparison, s[length..].trim()),
+            Some(_) => return Err(ConditionError::InvalidValue(s.trim().to_string())),
+            None => (Comparison::Equal, s.trim()),
+        };
+
+        let value = parse_hex(value)
+            .and_then(|value| u8::try_from(value).ok())
+            .ok_or_else(|| ConditionError::InvalidValue(value.to_string()))?;
+
+        Ok(Self { comparison, value })
+    }
+}
+
+impl Display for ValueCondition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ${:02X}", self.comparison.symbol(), self.value)
+    }
+}
+
+/// Pauses emulation when the CPU accesses an address in `range`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watchpoint {
+    pub range: RangeInclusive<u16>,
+    pub read: bool,
+    pub write: bool,
+    pub condition: Option<ValueCondition>,
+    pub enabled: bool,
+}
+
+impl Watchpoint {
+    fn matches(&self, access: Access, address: u16, value: u8) -> bool {
+        let kind = match access {
+            Access::Read => self.read,
+            Access::Write => self.write,
+        };
+
+        self.enabled
+            && kind
+            && self.range.contains(&address)
+            && self
+                .condition
+                .is_none_or(|c| c.comparison.holds(value as u16, c.value as u16))
+    }
+}
+
+/// An access that matched a watchpoint.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WatchHit {
+    pub access: Access,
+    pub address: u16,
+    pub value: u8,
+}
+
+impl Display for WatchHit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.access {
+            Access::Read => write!(f, "Read ${:02X} from ${:04X}", self.value, self.address),
+            Access::Write => write!(f, "Wrote ${:02X} to ${:04X}", self.value, self.address),
+        }
+    }
+}
+
+/// Watchpoints checked on every `Bus::read` and `Bus::write`.
+///
+/// The first hit is kept until the debugger takes it after the instruction.
+#[derive(Default)]
+pub struct Watchpoints {
+    pub list: Vec<Watchpoint>,
+    hit: Option<WatchHit>,
+}
+
+impl Watchpoints {
+    #[inline]
+    pub fn check(&mut self, access: Access, address: u16, value: u8) {
+        if self.list.is_empty() || self.hit.is_some() {
+            return;
+        }
+
+        if self.list.iter().any(|w| w.matches(access, address, value)) {
+            self.hit = Some(WatchHit {
+                access,
+                address,
+                value,
+            });
+        }
+    }
+
+    pub fn take_hit(&mut self) -> Option<WatchHit> {
+        self.hit.take()
+    }
+}
+
+/// Parses a single hexadecimal address or a range like `C000-C0FF`.
+pub fn parse_range(s: &str) -> Option<RangeInclusive<u16>> {
+    match s.split_once('-') {
+        Some((start, end)) => {
+            let (start, end) = (parse_hex(start)?, parse_hex(end)?);
+            (start <= end).then_some(start..=end)
+        }
+        None => parse_hex(s).map(|address| address..=address),
+    }
+}
@@ -1,3 +1,5 @@
+use crate::save_state::{Snapshot, StateError, StateReader, StateWriter};
+
 #[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Clone, Copy)]
 pub enum Interrupt {
@@ -64,3 +66,17 @@ impl InterruptHandler {
         }
     }
 }
+
+impl Snapshot for InterruptHandler {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.inte);
+        writer.write_u8(self.intf);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.inte = reader.read_u8()?;
+        self.intf = reader.read_u8()?;
+
+        Ok(())
+    }
+}
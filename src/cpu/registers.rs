@@ -1,3 +1,5 @@
+use crate::save_state::{Snapshot, StateError, StateReader, StateWriter};
+
 #[allow(non_snake_case)]
 #[derive(Default)]
 pub struct Registers {
@@ -146,3 +148,25 @@ impl Registers {
         }
     }
 }
+
+impl Snapshot for Registers {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.get_af());
+        writer.write_u16(self.get_bc());
+        writer.write_u16(self.get_de());
+        writer.write_u16(self.get_hl());
+        writer.write_u16(self.SP);
+        writer.write_u16(self.PC);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.set_af(reader.read_u16()?);
+        self.set_bc(reader.read_u16()?);
+        self.set_de(reader.read_u16()?);
+        self.set_hl(reader.read_u16()?);
+        self.SP = reader.read_u16()?;
+        self.PC = reader.read_u16()?;
+
+        Ok(())
+    }
+}
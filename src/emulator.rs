@@ -1,12 +1,35 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::apu::audio_output::{AudioOutput, NullOutput};
 use crate::cartridge::base_cartridge::{Cartridge, CartridgeType};
+use crate::cartridge::mbc::huc1::HuC1;
+use crate::cartridge::mbc::huc3::HuC3;
 use crate::cartridge::mbc::mbc1::MBC1;
 use crate::cartridge::mbc::mbc2::MBC2;
 use crate::cartridge::mbc::mbc3::MBC3;
 use crate::cartridge::mbc::mbc5::MBC5;
+use crate::cartridge::mbc::mbc6::MBC6;
+use crate::cartridge::mbc::mbc7::MBC7;
+use crate::cartridge::mbc::mmm01::MMM01;
 use crate::cartridge::mbc::no_mbc::NoMBC;
+use crate::cartridge::mbc::pocket_camera::PocketCamera;
+use crate::cartridge::rom_error::RomError;
+use crate::cpu::debug::trace::Tracer;
 use crate::cpu::registers::Registers;
 use crate::cpu::CPU;
 use crate::mmu::bus::Bus;
+use crate::mmu::serial_device::Disconnected;
+use crate::ppu::compat_palettes::{title_combination, CompatPalettes};
+use crate::save_state::{
+    Snapshot, StateError, StateReader, StateWriter, STATE_MAGIC, STATE_VERSION,
+};
+
+/// DMG and MGB boot ROMs only cover 0x0000-0x00FF.
+const DMG_BOOT_ROM_SIZE: usize = 0x100;
+/// CGB boot ROMs continue at 0x0200-0x08FF, 0x0100-0x01FF is unused.
+const CGB_BOOT_ROM_SIZE: usize = 0x900;
 
 pub struct Emulator {
     pub cpu: CPU,
@@ -14,6 +37,23 @@ pub struct Emulator {
     pub rom: Vec<u8>,
     pub cycle_count: u16,
     cgb: bool,
+
+    /// `<rom>.sav` for cartridges with a battery, `None` otherwise
+    sav_path: Option<PathBuf>,
+    /// SRAM as it was last read from or written to `sav_path`
+    saved_sram: Option<Vec<u8>>,
+
+    /// Boot ROMs run before the game, the post-boot state is emulated without them
+    dmg_boot_rom: Option<Vec<u8>>,
+    cgb_boot_rom: Option<Vec<u8>>,
+
+    /// Run DMG games on a CGB in DMG compatibility mode when there is no boot ROM
+    pub colorize_dmg: bool,
+    /// Palette combination for colorized DMG games instead of the one picked by title
+    dmg_palette: Option<usize>,
+
+    /// Logs every instruction to a file while set
+    pub tracer: Option<Tracer>,
 }
 
 impl Emulator {
@@ -24,42 +64,116 @@ impl Emulator {
             rom: Vec::new(),
             cycle_count: 0,
             cgb: false,
+
+            sav_path: None,
+            saved_sram: None,
+
+            dmg_boot_rom: None,
+            cgb_boot_rom: None,
+
+            colorize_dmg: false,
+            dmg_palette: None,
+
+            tracer: None,
         }
     }
 
+    /// Same as `new` but the APU plays its samples on `output`.
+    ///
+    /// `Emulator::new` discards all audio so it runs without a sound device.
+    pub fn with_audio_output(output: Box<dyn AudioOutput>) -> Self {
+        let mut emulator = Self::new();
+        emulator.bus.apu.replace_output(output);
+        emulator
+    }
+
     /// Load ROM and dispatch correct MBC based on header bytes.
     ///
     /// Read out title, RAM and ROM size and set flags based on header
     /// checksum. Initializes `Cartridge` for the Bus.
-    pub fn load_rom(&mut self, rom: &[u8]) {
-        self.reset();
+    ///
+    /// If the cartridge has a battery and `rom_path` is given, the
+    /// battery save next to it (`game.gb` -> `game.sav`) is loaded as well.
+    ///
+    /// The header is validated before anything is reset, so on error
+    /// the previously loaded game keeps running.
+    pub fn load_rom(&mut self, rom: &[u8], rom_path: Option<&Path>) -> Result<(), RomError> {
+        if rom.len() < 0x0150 {
+            return Err(RomError::TruncatedHeader(rom.len()));
+        }
 
-        let rom_size_kb = 32 * (1 << rom[0x0148]);
-        let ram_size_kb = match rom[0x0149] {
+        let header = header(rom);
+
+        let checksum = header[0x0134..=0x014C]
+            .iter()
+            .fold(0u8, |acc, b| acc.wrapping_sub(*b).wrapping_sub(1));
+        if checksum != header[0x014D] {
+            return Err(RomError::HeaderChecksumMismatch {
+                expected: header[0x014D],
+                actual: checksum,
+            });
+        }
+
+        let rom_size_kb = match header[0x0148] {
+            code @ 0x00..=0x08 => 32 * (1 << code),
+            code => return Err(RomError::InvalidRomSize(code)),
+        };
+        if rom.len() < rom_size_kb * 1024 {
+            return Err(RomError::TruncatedRom {
+                expected: rom_size_kb * 1024,
+                actual: rom.len(),
+            });
+        }
+
+        let ram_size_kb = match header[0x0149] {
             0x00 => 0,
             0x02 => 8,
             0x03 => 32,
             0x04 => 128,
             0x05 => 64,
-            _ => unimplemented!("RAM size not supported!"),
+            code => return Err(RomError::InvalidRamSize(code)),
         };
 
-        let cartridge_type = match rom[0x0147] {
+        let cartridge_type = match header[0x0147] {
             0x00 => CartridgeType::NoMBC(NoMBC::new(rom)),
             0x01..=0x03 => CartridgeType::MBC1(MBC1::new(rom, rom_size_kb, ram_size_kb)),
             0x05 | 0x06 => CartridgeType::MBC2(Box::new(MBC2::new(rom))),
-            0x0F..=0x13 => CartridgeType::MBC3(MBC3::new(rom)),
+            0x0F..=0x13 => CartridgeType::MBC3(MBC3::new(rom, ram_size_kb)),
             0x19..=0x1E => CartridgeType::MBC5(MBC5::new(rom, rom_size_kb, ram_size_kb)),
-            _ => unimplemented!("Cartridge type not supported!"),
+            0x0B..=0x0D => CartridgeType::MMM01(MMM01::new(rom, ram_size_kb)),
+            0x20 => CartridgeType::MBC6(MBC6::new(rom, ram_size_kb)),
+            0x22 => CartridgeType::MBC7(Box::new(MBC7::new(rom))),
+            0xFC => CartridgeType::PocketCamera(PocketCamera::new(rom)),
+            0xFE => CartridgeType::HuC3(HuC3::new(rom, ram_size_kb)),
+            0xFF => CartridgeType::HuC1(HuC1::new(rom, ram_size_kb)),
+            t => return Err(RomError::UnsupportedCartridgeType(t)),
         };
 
-        let title = std::str::from_utf8(&rom[0x0134..=0x0143])
-            .or_else(|_| std::str::from_utf8(&rom[0x0134..=0x0142]))
-            .or_else(|_| std::str::from_utf8(&rom[0x0134..=0x013E]))
-            .unwrap();
+        let title = std::str::from_utf8(&header[0x0134..=0x0143])
+            .or_else(|_| std::str::from_utf8(&header[0x0134..=0x0142]))
+            .or_else(|_| std::str::from_utf8(&header[0x0134..=0x013E]))
+            .map(str::to_string)
+            .unwrap_or_else(|_| String::from_utf8_lossy(&header[0x0134..=0x013E]).into_owned());
+
+        self.reset();
+
+        // DMG games prefer the DMG boot ROM, with only a CGB one they run
+        // on a CGB in DMG compatibility mode like on real hardware.
+        let cgb_game = header[0x0143] == 0x80 || header[0x0143] == 0xC0;
+        let boot_rom = if cgb_game {
+            self.cgb_boot_rom.clone()
+        } else {
+            self.dmg_boot_rom
+                .clone()
+                .or_else(|| self.cgb_boot_rom.clone())
+        };
 
-        self.cgb = rom[0x0143] == 0x80 || rom[0x0143] == 0xC0;
-        self.bus.cartridge = Cartridge::new(cartridge_type, title);
+        // Without a boot ROM the palettes it would pick are set up directly
+        let colorize = !cgb_game && boot_rom.is_none() && self.colorize_dmg;
+
+        self.cgb =
+            cgb_game || colorize || boot_rom.as_ref().map(Vec::len) == Some(CGB_BOOT_ROM_SIZE);
+        self.bus.cartridge = Cartridge::new(cartridge_type, &title);
         self.rom = rom.to_vec(); // TODO: redundant?
 
         if self.cgb {
@@ -67,8 +181,25 @@ impl Emulator {
             self.cpu.registers = Registers::new_cgb();
             self.bus.ppu.enable_cgb();
         } else {
-            self.cpu.registers = Registers::new_dmg(rom[0x014D]);
+            self.cpu.registers = Registers::new_dmg(header[0x014D]);
+        }
+
+        if colorize {
+            self.bus
+                .enter_dmg_compat(&CompatPalettes::new(self.compat_combination()));
+        }
+
+        if let Some(boot_rom) = boot_rom {
+            self.cpu.registers = Registers::default();
+            self.bus.map_boot_rom(boot_rom);
+        }
+
+        if self.has_battery() {
+            self.sav_path = rom_path.map(|p| p.with_extension("sav"));
+            self.load_battery_save();
         }
+
+        Ok(())
     }
 
     /// Step emulator by ticking CPU, advancing it one instruction and returning
@@ -78,27 +209,210 @@ impl Emulator {
     /// and its components can tick during instructions.
     ///
     /// Handles interrupts and returns the appropriate amount of cycles if one occured.
+    /// The first instruction of the interrupt handler is left to the next step,
+    /// so that every step starts at an instruction the debugger can stop at.
+    ///
+    /// With a `tracer`, the state before the instruction is logged.
     pub fn step(&mut self) -> u8 {
         if self.cpu.handle_interrupts(&mut self.bus) {
-            self.cycle_count += 5;
+            return 5;
+        }
+
+        if let Some(tracer) = self.tracer.as_mut().filter(|_| !self.cpu.halt) {
+            let pc = self.cpu.registers.PC;
+            let banks = (self.rom.len() / 0x4000).max(1);
+            let bank = (pc < 0x8000).then(|| self.bus.cartridge.rom_bank(pc) % banks);
+
+            tracer.log(&self.cpu, &mut self.bus, bank);
         }
 
         self.cpu.tick(&mut self.bus)
     }
 
+    // ------------ BOOT ROMS ---------------
+    /// Runs `boot_rom` before every game loaded from now on, replacing
+    /// the previous boot ROM of the same model.
+    ///
+    /// The model is told by the size: DMG and MGB boot ROMs are 256 bytes,
+    /// CGB ones 2304 bytes.
+    pub fn set_boot_rom(&mut self, boot_rom: Vec<u8>) -> Result<(), RomError> {
+        match boot_rom.len() {
+            DMG_BOOT_ROM_SIZE => self.dmg_boot_rom = Some(boot_rom),
+            CGB_BOOT_ROM_SIZE => self.cgb_boot_rom = Some(boot_rom),
+            len => return Err(RomError::InvalidBootRomSize(len)),
+        }
+
+        Ok(())
+    }
+
+    /// Games loaded from now on start in the emulated post-boot state again.
+    pub fn remove_boot_roms(&mut self) {
+        self.dmg_boot_rom = None;
+        self.cgb_boot_rom = None;
+    }
+    // ------------ BOOT ROMS ---------------
+
+    // ------------ DMG COLORIZATION ---------------
+    /// Overrides the palette combination colorized DMG games get, `None`
+    /// goes back to the one the CGB boot ROM picks by title.
+    ///
+    /// A game that is already running in DMG compatibility mode is recolored right away.
+    pub fn set_dmg_palette(&mut self, combination: Option<usize>) {
+        self.dmg_palette = combination;
+
+        if self.bus.ppu.is_dmg_compat() {
+            let palettes = CompatPalettes::new(self.compat_combination());
+            self.bus.ppu.set_compat_palettes(&palettes);
+        }
+    }
+
+    pub fn dmg_palette(&self) -> Option<usize> {
+        self.dmg_palette
+    }
+
+    fn compat_combination(&self) -> usize {
+        self.dmg_palette
+            .unwrap_or_else(|| title_combination(header(&self.rom)))
+    }
+    // ------------ DMG COLORIZATION ---------------
+
+    // ------------ BATTERY SAVES ---------------
+    /// Writes cartridge RAM back to `<rom>.sav`.
+    ///
+    /// Does nothing if the cartridge has no battery or its RAM
+    /// did not change since it was last loaded or written.
+    pub fn save_sram(&mut self) -> io::Result<()> {
+        let Some(path) = &self.sav_path else {
+            return Ok(());
+        };
+
+        if let Some(sram) = self.bus.cartridge.dump_sram() {
+            if self.saved_sram.as_ref() != Some(&sram) {
+                fs::write(path, &sram)?;
+                self.saved_sram = Some(sram);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Without a save file the game starts with empty cartridge RAM.
+    fn load_battery_save(&mut self) {
+        let Some(path) = &self.sav_path else {
+            return;
+        };
+
+        if let Ok(save) = fs::read(path) {
+            self.bus.cartridge.load_sram(&save);
+        }
+
+        self.saved_sram = self.bus.cartridge.dump_sram();
+    }
+
+    /// Cartridge types with a battery, see `get_full_mbc_title`.
+    fn has_battery(&self) -> bool {
+        matches!(
+            header(&self.rom).get(0x0147),
+            Some(
+                0x03 | 0x06
+                    | 0x0D
+                    | 0x0F
+                    | 0x10
+                    | 0x13
+                    | 0x1B
+                    | 0x1E
+                    | 0x20
+                    | 0x22
+                    | 0xFC
+                    | 0xFE
+                    | 0xFF
+            )
+        )
+    }
+    // ------------ BATTERY SAVES ---------------
+
+    // ------------ SAVE STATES ---------------
+    /// Serializes the whole machine into a versioned save state.
+    ///
+    /// ROM contents are not included, so a state can only be
+    /// loaded back while the same game is running.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+
+        writer.write_bytes(STATE_MAGIC);
+        writer.write_u16(STATE_VERSION);
+        writer.write_bytes(&self.rom_checksums());
+        writer.write_bool(self.cgb);
+        writer.write_u16(self.cycle_count);
+
+        self.cpu.save_state(&mut writer);
+        self.bus.save_state(&mut writer);
+
+        writer.into_inner()
+    }
+
+    /// Restores a state created by `save_state`.
+    ///
+    /// On error the emulator keeps running from where it was before.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        let backup = self.save_state();
+        let result = self.restore_state(state);
+
+        if result.is_err() {
+            self.restore_state(&backup)
+                .expect("Restoring the previous state failed!");
+        }
+
+        result
+    }
+
+    fn restore_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        let mut reader = StateReader::new(state);
+
+        if reader.read_bytes(STATE_MAGIC.len())? != STATE_MAGIC {
+            return Err(StateError::InvalidMagic);
+        }
+
+        let version = reader.read_u16()?;
+        if version != STATE_VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+
+        if reader.read_bytes(3)? != self.rom_checksums() || reader.read_bool()? != self.cgb {
+            return Err(StateError::RomMismatch);
+        }
+
+        self.cycle_count = reader.read_u16()?;
+
+        self.cpu.load_state(&mut reader)?;
+        self.bus.load_state(&mut reader)
+    }
+
+    /// Header and global checksum, used to tell which game a state belongs to.
+    fn rom_checksums(&self) -> [u8; 3] {
+        match header(&self.rom).get(0x014D..=0x014F) {
+            Some(checksums) => checksums.try_into().unwrap(),
+            None => [0; 3],
+        }
+    }
+    // ------------ SAVE STATES ---------------
+
     // ------------ CARTRIDGE INFO FOR DISPLAY ---------------
     pub fn get_full_mbc_title(&self) -> Option<&str> {
         if self.rom.is_empty() {
             return None;
         }
 
-        match self.rom[0x0147] {
+        match header(&self.rom)[0x0147] {
             0x00 => Some("ROM ONLY"),
             0x01 => Some("MBC1"),
             0x02 => Some("MBC1+RAM"),
             0x03 => Some("MBC1+RAM+BATTERY"),
             0x05 => Some("MBC2"),
             0x06 => Some("MBC2+BATTERY"),
+            0x0B => Some("MMM01"),
+            0x0C => Some("MMM01+RAM"),
+            0x0D => Some("MMM01+RAM+BATTERY"),
             0x0F => Some("MBC3+TIMER+BATTERY"),
             0x10 => Some("MBC3+TIMER+RAM+BATTERY"),
             0x11 => Some("MBC3"),
@@ -112,6 +426,9 @@ impl Emulator {
             0x1E => Some("MBC5+RUMBLE+RAM+BATTERY"),
             0x20 => Some("MBC6"),
             0x22 => Some("MBC7+SENSOR+RUMBLE+RAM+BATTERY"),
+            0xFC => Some("POCKET CAMERA"),
+            0xFE => Some("HuC3"),
+            0xFF => Some("HuC1+RAM+BATTERY"),
             _ => None,
         }
     }
@@ -121,7 +438,7 @@ impl Emulator {
             return None;
         }
 
-        match self.rom[0x14A] {
+        match header(&self.rom)[0x14A] {
             0x00 => Some("Japan (and possibly overseas)"),
             0x01 => Some("Overseas only"),
             _ => None,
@@ -133,7 +450,7 @@ impl Emulator {
             return None;
         }
 
-        Some(32 * (1 << self.rom[0x0148]))
+        Some(32 * (1 << header(&self.rom)[0x0148]))
     }
 
     pub fn get_ram_size(&self) -> Option<u8> {
@@ -141,26 +458,154 @@ impl Emulator {
             return None;
         }
 
-        match self.rom[0x0149] {
+        match header(&self.rom)[0x0149] {
             0x00 => Some(0),
             0x02 => Some(8),
             0x03 => Some(32),
             0x04 => Some(128),
             0x05 => Some(64),
-            _ => unimplemented!("RAM size not supported!"),
+            _ => None,
         }
     }
     // ------------ CARTRIDGE INFO FOR DISPLAY ---------------
 
+    /// Resets all hardware state, the audio output, link port device,
+    /// watchpoints and trace are kept.
+    ///
+    /// Unsaved cartridge RAM is lost, call `save_sram` first.
     pub fn reset(&mut self) {
+        let output = self.bus.apu.replace_output(Box::new(NullOutput));
+        let device = self.bus.serial.replace_device(Box::new(Disconnected));
+        let watchpoints = std::mem::take(&mut self.bus.watchpoints);
+
         self.cpu = CPU::new();
         self.bus = Bus::new();
+        self.bus.apu.replace_output(output);
+        self.bus.serial.replace_device(device);
+        self.bus.watchpoints = watchpoints;
         self.rom = Vec::new();
         self.cycle_count = 0;
         self.cgb = false;
+        self.sav_path = None;
+        self.saved_sram = None;
     }
 
     pub fn is_cgb(&self) -> bool {
         self.cgb
     }
 }
+
+/// The 32 KiB that hold the cartridge header.
+///
+/// That is the start of the ROM, except for MMM01 multicarts which boot
+/// into a menu stored in their last 32 KiB.
+fn header(rom: &[u8]) -> &[u8] {
+    let menu = rom.len().saturating_sub(0x8000);
+
+    match rom.get(menu + 0x0147) {
+        Some(0x0B..=0x0D) if menu > 0 => &rom[menu..],
+        _ => rom,
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::Emulator;
+    use crate::save_state::{StateError, STATE_VERSION};
+
+    /// T-cycles of one frame at normal speed.
+    const FRAME_CYCLES: u64 = 70224;
+
+    /// 32 KiB ROM without MBC that runs `code` from 0x0100, with a valid header checksum.
+    pub(crate) fn test_rom(code: &[u8]) -> Vec<u8> {
+        let mut rom = vec![0; 0x8000];
+        rom[0x100..0x100 + code.len()].copy_from_slice(code);
+        rom[0x14D] = rom[0x134..=0x14C]
+            .iter()
+            .fold(0u8, |acc, b| acc.wrapping_sub(*b).wrapping_sub(1));
+
+        rom
+    }
+
+    /// Counts up in A and writes it to $C000 forever.
+    fn counter() -> Emulator {
+        let mut emulator = Emulator::new();
+        emulator
+            .load_rom(&test_rom(&[0x3C, 0xEA, 0x00, 0xC0, 0x18, 0xFA]), None)
+            .unwrap();
+
+        emulator
+    }
+
+    fn run_frames(emulator: &mut Emulator, frames: u64) {
+        let mut cycles = 0;
+        while cycles < frames * FRAME_CYCLES {
+            cycles += emulator.step() as u64 * 4;
+        }
+    }
+
+    #[test]
+    fn save_state_round_trip() {
+        let mut emulator = counter();
+        run_frames(&mut emulator, 2);
+
+        let state = emulator.save_state();
+        let (pc, a) = (emulator.cpu.registers.PC, emulator.cpu.registers.A);
+
+        run_frames(&mut emulator, 5);
+        assert_ne!(emulator.save_state(), state);
+
+        emulator.load_state(&state).unwrap();
+        assert_eq!(emulator.save_state(), state);
+        assert_eq!(emulator.cpu.registers.PC, pc);
+        assert_eq!(emulator.cpu.registers.A, a);
+    }
+
+    #[test]
+    fn rejected_states_keep_running_state() {
+        let mut emulator = counter();
+        run_frames(&mut emulator, 1);
+        let state = emulator.save_state();
+
+        let mut bad_magic = state.clone();
+        bad_magic[0] ^= 0xFF;
+        assert_eq!(
+            emulator.load_state(&bad_magic),
+            Err(StateError::InvalidMagic)
+        );
+
+        let mut wrong_version = state.clone();
+        wrong_version[4..6].copy_from_slice(&(STATE_VERSION + 1).to_le_bytes());
+        assert_eq!(
+            emulator.load_state(&wrong_version),
+            Err(StateError::UnsupportedVersion(STATE_VERSION + 1))
+        );
+
+        let truncated = &state[..state.len() - 1];
+        assert_eq!(
+            emulator.load_state(truncated),
+            Err(StateError::UnexpectedEof)
+        );
+
+        assert_eq!(emulator.save_state(), state);
+    }
+
+    #[test]
+    fn rejects_state_of_other_rom() {
+        let mut other = Emulator::new();
+        let mut rom = test_rom(&[0x18, 0xFE]);
+        // Global checksum, the header checksum stays valid
+        rom[0x14E] = 0x12;
+        other.load_rom(&rom, None).unwrap();
+
+        let mut emulator = counter();
+        run_frames(&mut emulator, 1);
+        let before = emulator.save_state();
+
+        assert_eq!(
+            emulator.load_state(&other.save_state()),
+            Err(StateError::RomMismatch)
+        );
+        assert_eq!(emulator.save_state(), before);
+    }
+}
@@ -7,6 +7,7 @@ use hashlink::LinkedHashMap;
 use crate::{
     cpu::interrupts::{Interrupt, InterruptHandler},
     mmu::mmio::MMIO,
+    save_state::{Snapshot, StateError, StateReader, StateWriter},
 };
 
 #[derive(Debug, Clone, Copy)]
@@ -42,7 +43,7 @@ impl Default for Joypad {
 }
 
 impl MMIO for Joypad {
-    fn read(&mut self, _address: u16) -> u8 {
+    fn peek(&self, _address: u16) -> u8 {
         match self.get_button_type() {
             ButtonType::Action => 0xC0 | (self.joyp & 0x30) | self.action_state,
             ButtonType::Direction => 0xC0 | (self.joyp & 0x30) | self.dir_state,
@@ -155,3 +156,21 @@ impl Joypad {
         }
     }
 }
+
+impl Snapshot for Joypad {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.joyp);
+        writer.write_u8(self.prev_joyp);
+        writer.write_u8(self.dir_state);
+        writer.write_u8(self.action_state);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.joyp = reader.read_u8()?;
+        self.prev_joyp = reader.read_u8()?;
+        self.dir_state = reader.read_u8()?;
+        self.action_state = reader.read_u8()?;
+
+        Ok(())
+    }
+}
@@ -1 +1,2 @@
 pub mod joypad;
+pub mod rumble;
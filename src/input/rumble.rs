@@ -0,0 +1,68 @@
+use gilrs::{
+    ff::{BaseEffect, BaseEffectType, Effect, EffectBuilder, Replay, Ticks},
+    GamepadId, Gilrs,
+};
+
+/// Duty cycles below this don't spin up a real motor either.
+const MIN_DUTY: f32 = 0.05;
+
+/// Plays the cartridge rumble on every connected gamepad with force feedback.
+///
+/// One looping effect at full magnitude is created per set of gamepads,
+/// its gain is then set to the motor duty cycle of each frame.
+#[derive(Default)]
+pub struct Rumble {
+    effect: Option<Effect>,
+    gamepads: Vec<GamepadId>,
+    strength: f32,
+}
+
+impl Rumble {
+    /// Called once per frame with the duty cycle of the rumble motor.
+    pub fn update(&mut self, gilrs: &mut Gilrs, duty: f32) {
+        let gamepads = gilrs
+            .gamepads()
+            .filter(|(_, g)| g.is_ff_supported())
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+
+        if gamepads != self.gamepads {
+            self.gamepads = gamepads;
+            self.strength = 0.0;
+            self.effect = EffectBuilder::new()
+                .add_effect(BaseEffect {
+                    kind: BaseEffectType::Strong {
+                        magnitude: u16::MAX,
+                    },
+                    scheduling: Replay {
+                        play_for: Ticks::from_ms(100),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .gamepads(&self.gamepads)
+                .finish(gilrs)
+                .ok();
+        }
+
+        let strength = if duty < MIN_DUTY { 0.0 } else { duty.min(1.0) };
+        if strength == self.strength {
+            return;
+        }
+
+        if let Some(effect) = &self.effect {
+            let _ = if strength == 0.0 {
+                effect.stop()
+            } else {
+                effect.set_gain(strength).and_then(|_| effect.play())
+            };
+        }
+
+        self.strength = strength;
+    }
+
+    /// Stops the motor, e.g. when the emulation is paused.
+    pub fn stop(&mut self, gilrs: &mut Gilrs) {
+        self.update(gilrs, 0.0);
+    }
+}
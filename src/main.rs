@@ -19,6 +19,9 @@ mod input;
 mod mmu;
 #[path = "ppu/ppu.rs"]
 mod ppu;
+mod save_state;
+#[cfg(test)]
+mod test_roms;
 #[path = "ui/ui.rs"]
 mod ui;
 
@@ -47,7 +50,7 @@ fn main() -> Result<()> {
 
             // Read in rom per command line
             let kevboy = match std::env::args().nth(1) {
-                Some(rom) => Kevboy::with_rom(&std::fs::read(rom).unwrap(), cc),
+                Some(rom) => Kevboy::with_rom(rom.into(), cc),
                 None => Kevboy::new(cc),
             };
             Box::new(kevboy)
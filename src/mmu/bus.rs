@@ -1,10 +1,14 @@
 use crate::{
     apu::APU,
     cartridge::base_cartridge::Cartridge,
-    cpu::interrupts::{Interrupt, InterruptHandler},
+    cpu::{
+        debug::watchpoint::{Access, Watchpoints},
+        interrupts::{Interrupt, InterruptHandler},
+    },
     input::joypad::Joypad,
     mmu::{mmio::MMIO, serial::Serial, timer::Timers},
-    ppu::{DMATransferState, PPU},
+    ppu::{compat_palettes::CompatPalettes, DMATransferState, PPU},
+    save_state::{Snapshot, StateError, StateReader, StateWriter},
 };
 
 use super::hdma_transfer::Hdma;
@@ -26,7 +30,11 @@ pub struct Bus {
     pub hram: [u8; 0xAF],
     pub interrupt_handler: InterruptHandler,
 
+    /// Mapped over the start of the cartridge until 0xFF50 is written
+    boot_rom: Option<Vec<u8>>,
     disable_boot_rom: u8,
+    /// CGB mode or DMG compatibility mode, only writable by the boot ROM
+    key0: u8,
     vbk: u8,
     svbk: u8,
 
@@ -34,28 +42,34 @@ pub struct Bus {
 
     pub double_speed: bool,
     pub key1: u8,
+
+    /// Checked on every read and write of the CPU
+    pub watchpoints: Watchpoints,
 }
 
 // ----------------------------
-// MMIO trait for read/write (access via bus causes tick)
+// MMIO trait for read/write (access via bus causes tick, peek doesn't)
 // ----------------------------
 
 impl MMIO for Bus {
+    /// What the CPU would read at `address`, without advancing time or checking watchpoints.
     #[rustfmt::skip]
-    fn read(&mut self, address: u16) -> u8 {
-        if self.ppu.get_dma_state() != DMATransferState::Transferring && !self.hdma.halted {
-            self.tick(1);
-        }
-
+    fn peek(&self, address: u16) -> u8 {
         // Only matching on the top 4 bits seems to give better codegen and a
         // better jump table with less checks. (this function gets called a lot!)
         match (address & 0xF000) >> 12 {
-            0x0..=0x7 => self.cartridge.read(address),
+            0x0..=0x7 => match &self.boot_rom {
+                // 0x0100-0x01FF always shows the cartridge header, CGB boot ROMs continue after it
+                Some(boot_rom) if (address as usize) < boot_rom.len() && !(0x0100..0x0200).contains(&address) => {
+                    boot_rom[address as usize]
+                }
+                _ => self.cartridge.peek(address),
+            },
             0x8 | 0x9 => {
                 let vbk = if self.ppu.cgb { self.vbk & 1 } else { 0 };
                 self.vram[vbk as usize][address as usize - 0x8000]
             },
-            0xA | 0xB => self.cartridge.read(address),
+            0xA | 0xB => self.cartridge.peek(address),
             0xC => self.wram[0][address as usize & 0x0FFF],
             0xD | 0xE => {
                 // Echo RAM.
@@ -76,16 +90,17 @@ impl MMIO for Bus {
                     0xE00..=0xE9F => self.oam[address as usize - 0xFE00],
                     0xEA0..=0xEFF => 0xFF, // usage of this area not prohibited, may trigger oam corruption
                     0xF00..=0xF7F => match address {
-                        0xFF00 => self.joypad.read(address),
-                        0xFF01 | 0xFF02 => self.serial.read(address),
-                        0xFF04..=0xFF07 => self.timer.read(address),
+                        0xFF00 => self.joypad.peek(address),
+                        0xFF01 | 0xFF02 => self.serial.peek(address),
+                        0xFF04..=0xFF07 => self.timer.peek(address),
                         0xFF0F => self.interrupt_handler.intf,
-                        0xFF10..=0xFF3F => self.apu.read(address),
-                        0xFF40..=0xFF4B | 0xFF68..=0xFF6B => self.ppu.read(address),
+                        0xFF10..=0xFF3F => self.apu.peek(address),
+                        0xFF40..=0xFF4B | 0xFF68..=0xFF6B => self.ppu.peek(address),
+                        0xFF4C => self.key0,
                         0xFF4D => self.key1,
                         0xFF4F => self.vbk,
                         0xFF50 => self.disable_boot_rom,
-                        0xFF51..=0xFF55 => self.hdma.read(address),
+                        0xFF51..=0xFF55 => self.hdma.peek(address),
                         0xFF70 => self.svbk,
                         _ => 0xFF,
                     },
@@ -98,59 +113,104 @@ impl MMIO for Bus {
         }
     }
 
+    #[rustfmt::skip]
+    fn read(&mut self, address: u16) -> u8 {
+        if self.ppu.get_dma_state() != DMATransferState::Transferring && !self.hdma.halted {
+            self.tick(1);
+        }
+
+        let value = self.peek(address);
+        self.watchpoints.check(Access::Read, address, value);
+
+        value
+    }
+
     #[rustfmt::skip]
     fn write(&mut self, address: u16, value: u8) {
         if self.ppu.get_dma_state() != DMATransferState::Transferring {
             self.tick(1);
         }
 
+        self.watchpoints.check(Access::Write, address, value);
         match address {
-            0x0000..=0x7FFF => self.cartridge.write(address, value),
-            0x8000..=0x9FFF => {
-                let vbk = if self.ppu.cgb { self.vbk & 1 } else { 0 };
-                self.vram[vbk as usize][address as usize - 0x8000] = value;
-            },
-            0xA000..=0xBFFF => self.cartridge.write(address, value),
-            0xC000..=0xCFFF => self.wram[0][address as usize & 0x0FFF] = value,
-            0xD000..=0xFDFF => {
-                // Echo RAM.
-                if address > 0xDFFF && address < 0xF000 {
-                    self.wram[0][address as usize & 0x0FFF] = value;
-                } else {
-                    let wram_bank = if self.svbk & 0x07 == 0 { 1 } else { (self.svbk & 0x07) as usize };
-                    self.wram[if self.ppu.cgb { wram_bank } else { 1 }][address as usize & 0x0FFF] = value;
-                }
-            }
-            0xFE00..=0xFE9F => self.oam[address as usize - 0xFE00] = value,
-            0xFEA0..=0xFEFF => {} // not usable area
-            0xFF00..=0xFF7F => match address {
-                0xFF00 => self.joypad.write(address, value),
-                0xFF01 | 0xFF02 => self.serial.write(address, value),
-                0xFF04..=0xFF07 => self.timer.write(address, value),
-                0xFF0F => self.interrupt_handler.intf = value | 0b1110_0000,
-                0xFF10..=0xFF3F => self.apu.write(address, value),
-                0xFF40..=0xFF4B | 0xFF68..=0xFF6B => {
-                    self.ppu
-                        .write_with_callback(address, value, || self.interrupt_handler.request_interrupt(Interrupt::STAT))
-                }
-                0xFF4D => self.key1 = (self.key1 & 0xFE) | (value & 1),
-                0xFF4F => if self.ppu.cgb { self.vbk = 0xFE | value },
-                0xFF51..=0xFF55 => {
-                    self.hdma.write(address, value);
-                    if address == 0xFF55 && self.ppu.cgb {
-                        if (value & (1 << 7)) >> 7 == 0 && self.hdma.hdma_in_progress {
-                            self.hdma.terminate_transfer();
-                        } else {
-                            self.vram_dma_transfer();
-                        }
-                    }
-                },
-                0xFF70 => if self.ppu.cgb { self.svbk = 0xF8 | (value & 0x07) },
-                _ => {}
-            },
-            0xFF80..=0xFFFE => self.hram[address as usize - 0xFF80] = value,
-            0xFFFF => self.interrupt_handler.inte = value,
+            0x0000..=0x7FFF | 0xA000..=0xBFFF => self.cartridge.write(address, value),
+            _ => self.poke(address, value),
+        }
+    }
+}
+
+// ----------------------------
+// Save states
+// ----------------------------
+
+impl Snapshot for Bus {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.cartridge.save_state(writer);
+
+        for bank in &self.vram {
+            writer.write_bytes(bank);
+        }
+        for bank in &self.wram {
+            writer.write_bytes(bank);
+        }
+        writer.write_bytes(&self.oam);
+        writer.write_bytes(&self.hram);
+
+        self.joypad.save_state(writer);
+        self.serial.save_state(writer);
+        self.timer.save_state(writer);
+        self.ppu.save_state(writer);
+        self.apu.save_state(writer);
+        self.interrupt_handler.save_state(writer);
+        self.hdma.save_state(writer);
+
+        writer.write_bool(self.boot_rom.is_some());
+        if let Some(boot_rom) = &self.boot_rom {
+            writer.write_u16(boot_rom.len() as u16);
+            writer.write_bytes(boot_rom);
+        }
+        writer.write_u8(self.disable_boot_rom);
+        writer.write_u8(self.key0);
+        writer.write_u8(self.vbk);
+        writer.write_u8(self.svbk);
+        writer.write_bool(self.double_speed);
+        writer.write_u8(self.key1);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.cartridge.load_state(reader)?;
+
+        for bank in &mut self.vram {
+            reader.read_into(bank)?;
+        }
+        for bank in &mut self.wram {
+            reader.read_into(bank)?;
         }
+        reader.read_into(&mut self.oam)?;
+        reader.read_into(&mut self.hram)?;
+
+        self.joypad.load_state(reader)?;
+        self.serial.load_state(reader)?;
+        self.timer.load_state(reader)?;
+        self.ppu.load_state(reader)?;
+        self.apu.load_state(reader)?;
+        self.interrupt_handler.load_state(reader)?;
+        self.hdma.load_state(reader)?;
+
+        self.boot_rom = if reader.read_bool()? {
+            let len = reader.read_u16()? as usize;
+            Some(reader.read_bytes(len)?.to_vec())
+        } else {
+            None
+        };
+        self.disable_boot_rom = reader.read_u8()?;
+        self.key0 = reader.read_u8()?;
+        self.vbk = reader.read_u8()?;
+        self.svbk = reader.read_u8()?;
+        self.double_speed = reader.read_bool()?;
+        self.key1 = reader.read_u8()?;
+
+        Ok(())
     }
 }
 
@@ -176,7 +236,9 @@ impl Bus {
 
             hram: [0xFF; 0xAF],
             interrupt_handler: InterruptHandler::default(),
+            boot_rom: None,
             disable_boot_rom: 0xFF, // not writable once unmapped
+            key0: 0xFF,
             vbk: 0xFF,
             svbk: 0xF8,
 
@@ -184,13 +246,15 @@ impl Bus {
 
             double_speed: false,
             key1: 0x7E,
+
+            watchpoints: Watchpoints::default(),
         }
     }
 
     /// Ticks the bus in M-Cycles. Called every mem read/write
     /// and for extra cycles in certain instructions.
     ///
-    /// Advances timer, serial and PPU for now.
+    /// Advances timer, serial, cartridge clock and PPU for now.
     pub fn tick(&mut self, cycles_passed: u16) {
         let double_factor = if self.double_speed { 2 } else { 1 };
 
@@ -219,11 +283,13 @@ impl Bus {
         if self.ppu.cgb && self.hdma.hdma_in_progress {
             self.hdma.halted = true;
             for i in 0..0x10 {
-                self.hdma.bytes[i] = self.read(self.hdma.source() + i as u16);
+                self.hdma.bytes[i] = self.read(self.hdma.source().wrapping_add(i as u16));
             }
             self.hdma.halted = false;
         }
 
+        self.cartridge.tick((cycles_passed * 4) / double_factor);
+
         // PPU ticks 4 times per M-cycle
         for _ in 0..((cycles_passed * 4) / double_factor) {
             self.ppu.tick(
@@ -257,6 +323,95 @@ impl Bus {
         self.write(address + 1, bytes[1]);
     }
 
+    /// Writes `address` without advancing time or checking watchpoints, for debugging tools and cheats.
+    ///
+    /// Cartridge ROM and RAM are written where `peek` reads them instead of switching banks,
+    /// everything else like the CPU would, so registers keep their write side effects.
+    #[rustfmt::skip]
+    pub fn poke(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x7FFF => match &mut self.boot_rom {
+                Some(boot_rom) if (address as usize) < boot_rom.len() && !(0x0100..0x0200).contains(&address) => {
+                    boot_rom[address as usize] = value;
+                }
+                _ => self.cartridge.poke(address, value),
+            },
+            0x8000..=0x9FFF => {
+                let vbk = if self.ppu.cgb { self.vbk & 1 } else { 0 };
+                self.vram[vbk as usize][address as usize - 0x8000] = value;
+            },
+            0xA000..=0xBFFF => self.cartridge.poke(address, value),
+            0xC000..=0xCFFF => self.wram[0][address as usize & 0x0FFF] = value,
+            0xD000..=0xFDFF => {
+                // Echo RAM.
+                if address > 0xDFFF && address < 0xF000 {
+                    self.wram[0][address as usize & 0x0FFF] = value;
+                } else {
+                    let wram_bank = if self.svbk & 0x07 == 0 { 1 } else { (self.svbk & 0x07) as usize };
+                    self.wram[if self.ppu.cgb { wram_bank } else { 1 }][address as usize & 0x0FFF] = value;
+                }
+            }
+            0xFE00..=0xFE9F => self.oam[address as usize - 0xFE00] = value,
+            0xFEA0..=0xFEFF => {} // not usable area
+            0xFF00..=0xFF7F => match address {
+                0xFF00 => self.joypad.write(address, value),
+                0xFF01 | 0xFF02 => self.serial.write(address, value),
+                0xFF04..=0xFF07 => self.timer.write(address, value),
+                0xFF0F => self.interrupt_handler.intf = value | 0b1110_0000,
+                0xFF10..=0xFF3F => self.apu.write(address, value),
+                0xFF40..=0xFF4B | 0xFF68..=0xFF6B => {
+                    self.ppu
+                        .write_with_callback(address, value, || self.interrupt_handler.request_interrupt(Interrupt::STAT))
+                }
+                0xFF4C => if self.boot_rom.is_some() && self.ppu.cgb { self.key0 = value },
+                0xFF4D => self.key1 = (self.key1 & 0xFE) | (value & 1),
+                0xFF50 => if value != 0 { self.unmap_boot_rom() },
+                0xFF4F => if self.ppu.cgb { self.vbk = 0xFE | value },
+                0xFF51..=0xFF55 => {
+                    self.hdma.write(address, value);
+                    if address == 0xFF55 && self.ppu.cgb {
+                        if (value & (1 << 7)) >> 7 == 0 && self.hdma.hdma_in_progress {
+                            self.hdma.terminate_transfer();
+                        } else {
+                            self.vram_dma_transfer();
+                        }
+                    }
+                },
+                0xFF70 => if self.ppu.cgb { self.svbk = 0xF8 | (value & 0x07) },
+                _ => {}
+            },
+            0xFF80..=0xFFFE => self.hram[address as usize - 0xFF80] = value,
+            0xFFFF => self.interrupt_handler.inte = value,
+        }
+    }
+
+    /// Maps `boot_rom` and puts the hardware in its power on state,
+    /// setting up everything else is left to the boot ROM.
+    pub fn map_boot_rom(&mut self, boot_rom: Vec<u8>) {
+        self.boot_rom = Some(boot_rom);
+        self.key0 = 0x00;
+
+        self.timer.div = 0;
+        self.ppu.power_on();
+    }
+
+    /// Puts a CGB in DMG compatibility mode with `palettes`, the way the
+    /// CGB boot ROM leaves it for a DMG game.
+    pub fn enter_dmg_compat(&mut self, palettes: &CompatPalettes) {
+        self.key0 = 0x04;
+        self.ppu.set_compat_palettes(palettes);
+        self.ppu.enable_dmg_compat();
+    }
+
+    /// Hands the start of the address space back to the cartridge.
+    ///
+    /// A CGB boot ROM that was running a DMG game leaves the PPU in DMG compatibility mode.
+    fn unmap_boot_rom(&mut self) {
+        if self.boot_rom.take().is_some() && self.ppu.cgb && self.key0 & 0x0C == 0x04 {
+            self.ppu.enable_dmg_compat();
+        }
+    }
+
     pub fn change_speed(&mut self) {
         let current_speed = (self.key1 & 0x80) >> 7;
         if current_speed == 0 {
@@ -287,10 +442,11 @@ impl Bus {
         let len = self.hdma.length();
 
         if self.hdma.is_gdma() && !self.hdma.hdma_in_progress {
-            self.hdma.halted = true;
-
+            // Copied through peek, so neither the CPU's writes nor pokes from the debugger tick
+            // or trigger watchpoints.
             for i in 0..len {
-                self.vram[(self.vbk & 1) as usize][(dest + i) as usize] = self.read(source + i);
+                self.vram[(self.vbk & 1) as usize][(dest + i) as usize] =
+                    self.peek(source.wrapping_add(i));
             }
 
             self.hdma.complete_transfer();
@@ -301,3 +457,62 @@ impl Bus {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cpu::debug::watchpoint::Watchpoint, emulator::tests::test_rom, emulator::Emulator,
+        mmu::mmio::MMIO,
+    };
+
+    #[test]
+    fn peek_and_poke_leave_time_alone() {
+        let mut rom = test_rom(&[0x18, 0xFE]);
+        // CGB only, the header checksum drops by what the flag adds
+        rom[0x143] = 0x80;
+        rom[0x14D] = rom[0x14D].wrapping_sub(0x80);
+
+        let mut emulator = Emulator::new();
+        emulator.load_rom(&rom, None).unwrap();
+        for _ in 0..1000 {
+            emulator.step();
+        }
+
+        let bus = &mut emulator.bus;
+        bus.watchpoints.list.push(Watchpoint {
+            range: 0x0000..=0xFFFF,
+            read: true,
+            write: true,
+            condition: None,
+            enabled: true,
+        });
+        let div = bus.timer.div;
+        let ly = bus.peek(0xFF44);
+        let cycles = emulator.cycle_count;
+
+        for i in 0..0x10 {
+            bus.poke(0xC000 + i, i as u8);
+        }
+        // General purpose DMA of 0x10 bytes from 0xC000 to 0x8000
+        for (address, value) in [
+            (0xFF51, 0xC0),
+            (0xFF52, 0x00),
+            (0xFF53, 0x80),
+            (0xFF54, 0x00),
+        ] {
+            bus.poke(address, value);
+        }
+        bus.poke(0xFF55, 0x00);
+
+        assert_eq!(bus.peek(0x800F), 0x0F);
+        assert_eq!(bus.peek(0xFF55), 0xFF);
+        for address in 0..=0xFFFF {
+            bus.peek(address);
+        }
+
+        assert_eq!(bus.timer.div, div);
+        assert_eq!(bus.peek(0xFF44), ly);
+        assert_eq!(bus.watchpoints.take_hit(), None);
+        assert_eq!(emulator.cycle_count, cycles);
+    }
+}
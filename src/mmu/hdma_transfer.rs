@@ -1,4 +1,5 @@
 use super::mmio::MMIO;
+use crate::save_state::{Snapshot, StateError, StateReader, StateWriter};
 
 pub struct Hdma {
     /// HDMA source (high, low)
@@ -77,7 +78,7 @@ impl Default for Hdma {
 }
 
 impl MMIO for Hdma {
-    fn read(&mut self, address: u16) -> u8 {
+    fn peek(&self, address: u16) -> u8 {
         match address {
             0xFF51 => self.hdma1,
             0xFF52 => self.hdma2,
@@ -99,3 +100,25 @@ impl MMIO for Hdma {
         }
     }
 }
+
+impl Snapshot for Hdma {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&[self.hdma1, self.hdma2, self.hdma3, self.hdma4, self.hdma5]);
+        writer.write_bool(self.halted);
+        writer.write_bool(self.hdma_in_progress);
+        writer.write_bytes(&self.bytes);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.hdma1 = reader.read_u8()?;
+        self.hdma2 = reader.read_u8()?;
+        self.hdma3 = reader.read_u8()?;
+        self.hdma4 = reader.read_u8()?;
+        self.hdma5 = reader.read_u8()?;
+        self.halted = reader.read_bool()?;
+        self.hdma_in_progress = reader.read_bool()?;
+        reader.read_into(&mut self.bytes)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,326 @@
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+use crate::mmu::serial_device::SerialDevice;
+
+/// Serial clock cycles it takes to shift out all 8 bits at 8192 Hz.
+pub const TRANSFER_CYCLES: u64 = 8 * 512;
+
+/// How often the own clock is sent to the peer.
+const SYNC_INTERVAL: u64 = 1024;
+
+/// How far one side may run ahead of the last clock the peer sent.
+///
+/// Has to stay below `TRANSFER_CYCLES`: a transfer the peer starts at cycle `t`
+/// is then always received before the own clock passes `t + TRANSFER_CYCLES`,
+/// so both sides complete it at exactly the same cycle.
+const MAX_LEAD: u64 = 2048;
+
+/// A peer that does not answer for this long is considered disconnected.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+// Message tags, every message is the tag followed by its payload.
+const MSG_SYNC: u8 = 0x00; // cycle: u64
+const MSG_TRANSFER: u8 = 0x01; // cycle: u64, data: u8
+const MSG_REPLY: u8 = 0x02; // data: u8
+
+/// Connects the serial port to another emulator over TCP.
+///
+/// Whichever side starts a transfer with the internal clock is the clock master,
+/// the other side takes part if it has a transfer pending with the external clock.
+/// Both sides count the serial clock (DIV) cycles since the connection was made
+/// and run in lock step, so transfers complete at the same cycle on both ends.
+pub struct LinkCable {
+    stream: TcpStream,
+
+    cycles: u64,
+    peer_cycles: u64,
+    last_sync: u64,
+
+    /// Bytes and end cycles of transfers the peer clocked
+    incoming: VecDeque<(u8, u64)>,
+    reply: Option<u8>,
+    /// Start cycle of our own transfer with the internal clock
+    transfer_start: Option<u64>,
+}
+
+impl LinkCable {
+    pub const NAME: &'static str = "Link cable";
+
+    /// Wraps an established connection, it does not matter which side accepted it.
+    pub fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+
+        Ok(Self {
+            stream,
+
+            cycles: 0,
+            peer_cycles: 0,
+            last_sync: 0,
+
+            incoming: VecDeque::new(),
+            reply: None,
+            transfer_start: None,
+        })
+    }
+
+    pub fn peer_address(&self) -> Option<String> {
+        self.stream.peer_addr().ok().map(|addr| addr.to_string())
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        self.last_sync = self.cycles;
+
+        let mut message = vec![MSG_SYNC];
+        message.extend(self.cycles.to_le_bytes());
+        self.stream.write_all(&message)
+    }
+
+    /// Blocks until the next message of the peer arrived and handles it.
+    fn receive(&mut self) -> io::Result<()> {
+        let mut tag = [0; 1];
+        self.stream.read_exact(&mut tag)?;
+
+        match tag[0] {
+            MSG_SYNC => self.peer_cycles = self.read_u64()?,
+            MSG_TRANSFER => {
+                let start = self.read_u64()?;
+                self.peer_cycles = start;
+                let data = self.read_u8()?;
+
+                self.incoming.push_back((data, start + TRANSFER_CYCLES));
+            }
+            MSG_REPLY => self.reply = Some(self.read_u8()?),
+            tag => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unknown link cable message {tag:#04X}"),
+                ))
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Completes the oldest transfer the peer clocked if it ended by cycle `now`.
+    ///
+    /// Returns the byte shifted in if the port was `waiting` for the external clock.
+    fn answer(&mut self, now: u64, sb: u8, waiting: bool) -> io::Result<Option<u8>> {
+        let Some(&(data, end)) = self.incoming.front() else {
+            return Ok(None);
+        };
+        if now < end {
+            return Ok(None);
+        }
+
+        self.incoming.pop_front();
+
+        // Only a port waiting for the external clock shifts, otherwise
+        // the line stays high and the master reads 0xFF
+        let reply = if waiting { sb } else { 0xFF };
+        self.stream.write_all(&[MSG_REPLY, reply])?;
+
+        Ok(waiting.then_some(data))
+    }
+
+    fn read_u8(&mut self) -> io::Result<u8> {
+        let mut buf = [0; 1];
+        self.stream.read_exact(&mut buf)?;
+        Ok(buf[0])
+    }
+
+    fn read_u64(&mut self) -> io::Result<u64> {
+        let mut buf = [0; 8];
+        self.stream.read_exact(&mut buf)?;
+        Ok(u64::from_le_bytes(buf))
+    }
+}
+
+impl SerialDevice for LinkCable {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn start_transfer(&mut self, data: u8) -> io::Result<()> {
+        self.transfer_start = Some(self.cycles);
+
+        let mut message = vec![MSG_TRANSFER];
+        message.extend(self.cycles.to_le_bytes());
+        message.push(data);
+        self.stream.write_all(&message)
+    }
+
+    /// Waits for the byte the peer shifted out during our transfer.
+    fn exchange(&mut self, _data: u8) -> io::Result<u8> {
+        // The peer may need our clock to reach the end of the transfer
+        self.sync()?;
+
+        // The port finishes on a DIV edge, which can come a bit before the end the peer
+        // uses. Our clock stands still until the reply is in, so count from that end.
+        let end = self.transfer_start.take().map_or(self.cycles, |start| {
+            self.cycles.max(start + TRANSFER_CYCLES)
+        });
+
+        loop {
+            if let Some(reply) = self.reply.take() {
+                return Ok(reply);
+            }
+
+            // Both sides started a transfer with the internal clock and the peer may be
+            // stuck in its own `exchange`, so answer its transfer instead of blocking.
+            // Neither side waits for the external clock, both read 0xFF.
+            self.answer(end, 0xFF, false)?;
+            self.receive()?;
+        }
+    }
+
+    /// Keeps both sides in lock step and answers transfers the peer clocked.
+    fn tick(&mut self, cycles: u64, sb: u8, waiting: bool) -> io::Result<Option<u8>> {
+        self.cycles += cycles;
+
+        if self.cycles - self.last_sync >= SYNC_INTERVAL {
+            self.sync()?;
+        }
+
+        while self.cycles > self.peer_cycles + MAX_LEAD {
+            if self.last_sync != self.cycles {
+                self.sync()?;
+            }
+            self.receive()?;
+        }
+
+        self.answer(self.cycles, sb, waiting)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        net::{TcpListener, TcpStream},
+        sync::{Arc, Barrier},
+        thread,
+    };
+
+    use super::{LinkCable, TRANSFER_CYCLES};
+    use crate::mmu::serial_device::SerialDevice;
+
+    /// Serial clock cycles per `tick`, one M-cycle.
+    const STEP: u64 = 4;
+
+    #[derive(Clone, Copy)]
+    enum Clock {
+        /// Starts a transfer at this cycle
+        Internal(u64),
+        /// Waits for the peer to clock a transfer
+        External,
+        /// No transfer requested
+        Idle,
+    }
+
+    /// What one side of the cable does, shifting out `sb`.
+    #[derive(Clone, Copy)]
+    struct Port {
+        sb: u8,
+        clock: Clock,
+    }
+
+    /// Runs `port` for a few transfers worth of cycles and returns the byte shifted in.
+    fn run(stream: TcpStream, port: Port, done: Arc<Barrier>) -> io::Result<Option<u8>> {
+        let mut cable = LinkCable::new(stream)?;
+        let mut received = None;
+        let mut cycles = 0;
+
+        while cycles < 4 * TRANSFER_CYCLES {
+            let waiting = matches!(port.clock, Clock::External) && received.is_none();
+            if let Some(data) = cable.tick(STEP, port.sb, waiting)? {
+                received = Some(data);
+            }
+            cycles += STEP;
+
+            if let Clock::Internal(start) = port.clock {
+                if cycles == start {
+                    cable.start_transfer(port.sb)?;
+                } else if cycles == start + TRANSFER_CYCLES - 256 {
+                    // Like the serial port, which is done on the 8th falling edge of
+                    // DIV bit 8, up to 512 cycles before the nominal end
+                    received = Some(cable.exchange(port.sb)?);
+                }
+            }
+        }
+
+        // Closing the connection early would cut off the peer
+        done.wait();
+        Ok(received)
+    }
+
+    /// Connects two ports over a local socket, like two emulators would be.
+    fn link(a: Port, b: Port) -> (Option<u8>, Option<u8>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+
+        let done = Arc::new(Barrier::new(2));
+        let other = done.clone();
+        let b = thread::spawn(move || run(server, b, other));
+        let a = run(client, a, done);
+
+        (a.unwrap(), b.join().unwrap().unwrap())
+    }
+
+    #[test]
+    fn master_and_slave() {
+        let master = Port {
+            sb: 0x12,
+            clock: Clock::Internal(STEP),
+        };
+        let slave = Port {
+            sb: 0x34,
+            clock: Clock::External,
+        };
+
+        assert_eq!(link(master, slave), (Some(0x34), Some(0x12)));
+        assert_eq!(link(slave, master), (Some(0x12), Some(0x34)));
+    }
+
+    #[test]
+    fn two_masters() {
+        // Both ends clock at the same time, as Gen 1 / 2 Pokemon do during their handshake
+        let a = Port {
+            sb: 0x12,
+            clock: Clock::Internal(STEP),
+        };
+        let b = Port {
+            sb: 0x34,
+            clock: Clock::Internal(STEP),
+        };
+        assert_eq!(link(a, b), (Some(0xFF), Some(0xFF)));
+
+        // Or one slightly after the other
+        let b = Port {
+            sb: 0x34,
+            clock: Clock::Internal(STEP + 512),
+        };
+        assert_eq!(link(a, b), (Some(0xFF), Some(0xFF)));
+        assert_eq!(link(b, a), (Some(0xFF), Some(0xFF)));
+    }
+
+    #[test]
+    fn idle_port_reads_high_line() {
+        let master = Port {
+            sb: 0x12,
+            clock: Clock::Internal(STEP),
+        };
+        let idle = Port {
+            sb: 0x34,
+            clock: Clock::Idle,
+        };
+
+        assert_eq!(link(master, idle), (Some(0xFF), None));
+    }
+}
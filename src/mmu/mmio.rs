@@ -3,7 +3,14 @@
 /// Ideal for memory mapped registers.
 #[allow(clippy::upper_case_acronyms)]
 pub trait MMIO {
-    fn read(&mut self, address: u16) -> u8;
+    /// Reads `address` without side effects or advancing time, for debugging tools.
+    fn peek(&self, address: u16) -> u8;
+
+    /// Reads `address` like the CPU does, which is just a peek unless the access itself matters.
+    fn read(&mut self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
     fn write(&mut self, address: u16, value: u8);
 
     fn write_with_callback<F: FnMut()>(&mut self, address: u16, value: u8, _cb: F) {
@@ -1,5 +1,8 @@
 pub mod bus;
 pub mod hdma_transfer;
+pub mod link_cable;
 pub mod mmio;
+pub mod printer;
 pub mod serial;
+pub mod serial_device;
 pub mod timer;
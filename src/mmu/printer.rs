@@ -0,0 +1,448 @@
+use std::{io, sync::mpsc::Sender};
+
+use image::{GrayImage, Luma};
+
+use crate::mmu::serial_device::SerialDevice;
+
+/// Width of the paper in pixels, 20 tiles.
+const PAPER_WIDTH: usize = 160;
+
+/// Bytes of 2bpp tile data that make up one line of pixels.
+const BYTES_PER_LINE: usize = PAPER_WIDTH / 8 * 2;
+
+/// One data packet holds two rows of 20 tiles.
+const BAND_SIZE: usize = BYTES_PER_LINE * 16;
+
+/// The printer buffers at most 9 bands (= 144 lines) before it has to print.
+const BUFFER_SIZE: usize = 9 * BAND_SIZE;
+
+/// Blank lines fed per unit of the margin values of the print command.
+const LINES_PER_MARGIN: usize = 16;
+
+/// Status inquiries the printer reports being busy for after a print command.
+const BUSY_INQUIRIES: u8 = 4;
+
+/// Gray levels of the thermal paper, white to black.
+const SHADES: [u8; 4] = [0xFF, 0xAA, 0x55, 0x00];
+
+// Packet commands, anything else (like 0x0F) only inquires the status
+const INIT: u8 = 0x01;
+const PRINT: u8 = 0x02;
+const DATA: u8 = 0x04;
+
+// Status bits
+const CHECKSUM_ERROR: u8 = 1 << 0;
+const PRINTING: u8 = 1 << 1;
+const IMAGE_DATA_FULL: u8 = 1 << 2;
+const UNPROCESSED_DATA: u8 = 1 << 3;
+
+/// Which byte of a packet is expected next.
+#[derive(Clone, Copy, PartialEq)]
+enum PacketState {
+    MagicHigh,
+    MagicLow,
+    Command,
+    Compression,
+    LengthLow,
+    LengthHigh,
+    Data,
+    ChecksumLow,
+    ChecksumHigh,
+    KeepAlive,
+    Status,
+}
+
+/// Game Boy Printer attached to the serial port.
+///
+/// The Game Boy always drives the clock and sends packets of the form
+/// `0x88 0x33 command compression length(2) data checksum(2) 0x00 0x00`.
+/// The printer answers `0x81` to the first trailing zero and its status to the second.
+///
+/// Printed strips are glued together on a sheet of paper, which is finished once
+/// a print command feeds paper after the image. Finished sheets are sent to
+/// whoever holds the receiving end of `pages`.
+pub struct Printer {
+    state: PacketState,
+
+    command: u8,
+    compressed: bool,
+    length: u16,
+    packet: Vec<u8>,
+    checksum: u16,
+    received_checksum: u16,
+
+    status: u8,
+    busy: u8,
+
+    /// Decompressed 2bpp tile data waiting to be printed
+    buffer: Vec<u8>,
+    /// Shades of the current sheet, one byte per pixel
+    paper: Vec<u8>,
+    pages: Sender<GrayImage>,
+}
+
+impl Printer {
+    pub const NAME: &'static str = "Game Boy Printer";
+
+    pub fn new(pages: Sender<GrayImage>) -> Self {
+        Self {
+            state: PacketState::MagicHigh,
+
+            command: 0,
+            compressed: false,
+            length: 0,
+            packet: Vec::new(),
+            checksum: 0,
+            received_checksum: 0,
+
+            status: 0,
+            busy: 0,
+
+            buffer: Vec::with_capacity(BUFFER_SIZE),
+            paper: Vec::new(),
+            pages,
+        }
+    }
+
+    /// Receives one byte from the Game Boy and returns the byte shifted back.
+    fn receive(&mut self, byte: u8) -> u8 {
+        use PacketState::*;
+
+        let mut response = 0x00;
+
+        self.state = match self.state {
+            MagicHigh if byte == 0x88 => MagicLow,
+            MagicHigh => MagicHigh,
+            MagicLow if byte == 0x33 => Command,
+            MagicLow => MagicHigh,
+            Command => {
+                self.command = byte;
+                self.checksum = byte as u16;
+                Compression
+            }
+            Compression => {
+                self.compressed = byte & 1 != 0;
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                LengthLow
+            }
+            LengthLow => {
+                self.length = byte as u16;
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                LengthHigh
+            }
+            LengthHigh => {
+                self.length |= (byte as u16) << 8;
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                self.packet.clear();
+
+                if self.length == 0 {
+                    ChecksumLow
+                } else {
+                    Data
+                }
+            }
+            Data => {
+                self.packet.push(byte);
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+
+                if self.packet.len() == self.length as usize {
+                    ChecksumLow
+                } else {
+                    Data
+                }
+            }
+            ChecksumLow => {
+                self.received_checksum = byte as u16;
+                ChecksumHigh
+            }
+            ChecksumHigh => {
+                self.received_checksum |= (byte as u16) << 8;
+                KeepAlive
+            }
+            KeepAlive => {
+                response = 0x81;
+                self.process_packet();
+                Status
+            }
+            Status => {
+                response = self.status();
+                MagicHigh
+            }
+        };
+
+        response
+    }
+
+    fn status(&mut self) -> u8 {
+        if self.busy > 0 {
+            self.busy -= 1;
+            if self.busy == 0 {
+                self.status &= !PRINTING;
+            }
+        }
+
+        self.status
+    }
+
+    fn process_packet(&mut self) {
+        if self.checksum != self.received_checksum {
+            self.status |= CHECKSUM_ERROR;
+            return;
+        }
+        self.status &= !CHECKSUM_ERROR;
+
+        match self.command {
+            INIT => {
+                self.buffer.clear();
+                self.status = 0;
+                self.busy = 0;
+            }
+            DATA => {
+                let packet = std::mem::take(&mut self.packet);
+                if self.compressed {
+                    self.decompress(&packet);
+                } else {
+                    self.buffer.extend_from_slice(&packet);
+                }
+                self.buffer.truncate(BUFFER_SIZE);
+
+                if !self.buffer.is_empty() {
+                    self.status |= UNPROCESSED_DATA;
+                }
+                if self.buffer.len() == BUFFER_SIZE {
+                    self.status |= IMAGE_DATA_FULL;
+                }
+            }
+            PRINT if self.packet.len() == 4 => {
+                let margins = self.packet[1];
+                let palette = self.packet[2];
+                self.print(margins >> 4, margins & 0x0F, palette);
+            }
+            // A status inquiry (or unknown command) only reads the status
+            _ => {}
+        }
+    }
+
+    /// Run-length encoding: a control byte with bit 7 set repeats the next byte
+    /// `(control & 0x7F) + 2` times, otherwise `control + 1` literal bytes follow.
+    fn decompress(&mut self, packet: &[u8]) {
+        let mut bytes = packet.iter();
+
+        while let Some(&control) = bytes.next() {
+            if control & 0x80 != 0 {
+                let count = (control & 0x7F) as usize + 2;
+                if let Some(&byte) = bytes.next() {
+                    self.buffer.resize(self.buffer.len() + count, byte);
+                }
+            } else {
+                self.buffer
+                    .extend(bytes.by_ref().take(control as usize + 1));
+            }
+        }
+    }
+
+    /// Prints the buffered tiles as a strip, `palette` maps the color numbers to shades.
+    fn print(&mut self, margin_before: u8, margin_after: u8, palette: u8) {
+        // Many games send a palette of 0 which the printer treats as the default one
+        let palette = if palette == 0 { 0xE4 } else { palette };
+
+        self.feed(margin_before);
+
+        // Only whole rows of tiles can be printed
+        let lines = self.buffer.len() / (BYTES_PER_LINE * 8) * 8;
+        for y in 0..lines {
+            let tile_row = y / 8;
+            let line = y % 8;
+
+            for x in 0..PAPER_WIDTH {
+                let tile = tile_row * 20 + x / 8;
+                let offset = tile * 16 + line * 2;
+                let bit = 7 - (x % 8);
+
+                let low = (self.buffer[offset] >> bit) & 1;
+                let high = (self.buffer[offset + 1] >> bit) & 1;
+                let color = (high << 1) | low;
+
+                let shade = (palette >> (color * 2)) & 0b11;
+                self.paper.push(SHADES[shade as usize]);
+            }
+        }
+
+        self.feed(margin_after);
+        if margin_after > 0 {
+            self.cut();
+        }
+
+        self.buffer.clear();
+        self.status = (self.status & !(UNPROCESSED_DATA | IMAGE_DATA_FULL)) | PRINTING;
+        self.busy = BUSY_INQUIRIES;
+    }
+
+    fn feed(&mut self, margin: u8) {
+        let lines = margin as usize * LINES_PER_MARGIN;
+        self.paper
+            .resize(self.paper.len() + lines * PAPER_WIDTH, SHADES[0]);
+    }
+
+    /// Finishes the current sheet of paper.
+    fn cut(&mut self) {
+        let paper = std::mem::take(&mut self.paper);
+        let height = paper.len() / PAPER_WIDTH;
+
+        let page = GrayImage::from_fn(PAPER_WIDTH as u32, height as u32, |x, y| {
+            Luma([paper[y as usize * PAPER_WIDTH + x as usize]])
+        });
+        // Nobody is interested in the picture if the receiver is gone
+        let _ = self.pages.send(page);
+    }
+}
+
+impl SerialDevice for Printer {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn exchange(&mut self, data: u8) -> io::Result<u8> {
+        Ok(self.receive(data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::{self, Receiver};
+
+    use image::GrayImage;
+
+    use super::{
+        Printer, CHECKSUM_ERROR, DATA, INIT, PAPER_WIDTH, PRINT, PRINTING, UNPROCESSED_DATA,
+    };
+    use crate::mmu::serial_device::SerialDevice;
+
+    const INQUIRY: u8 = 0x0F;
+
+    fn printer() -> (Printer, Receiver<GrayImage>) {
+        let (pages, receiver) = mpsc::channel();
+        (Printer::new(pages), receiver)
+    }
+
+    /// Sends a whole packet, returns what the printer answered to the two trailing zeroes.
+    fn send(
+        printer: &mut Printer,
+        command: u8,
+        compressed: bool,
+        data: &[u8],
+        checksum_offset: u16,
+    ) -> [u8; 2] {
+        let length = (data.len() as u16).to_le_bytes();
+        let mut packet = vec![command, compressed as u8, length[0], length[1]];
+        packet.extend_from_slice(data);
+
+        let checksum = packet
+            .iter()
+            .fold(0u16, |sum, &b| sum.wrapping_add(b as u16))
+            .wrapping_add(checksum_offset);
+
+        let mut bytes = vec![0x88, 0x33];
+        bytes.extend(packet);
+        bytes.extend(checksum.to_le_bytes());
+
+        for byte in bytes {
+            assert_eq!(printer.exchange(byte).unwrap(), 0x00);
+        }
+
+        [0x00; 2].map(|zero| printer.exchange(zero).unwrap())
+    }
+
+    /// Two rows of tiles, black on top of white, run-length encoded.
+    fn compressed_band() -> Vec<u8> {
+        let mut data = Vec::new();
+
+        // 320 bytes of 0xFF as runs of 129, 129 and 62
+        data.extend([0xFF, 0xFF, 0xFF, 0xFF, 0x80 | 60, 0xFF]);
+        // 320 bytes of 0x00 as literals of 128, 128 and 64
+        for length in [128, 128, 64] {
+            data.push(length as u8 - 1);
+            data.extend(vec![0x00; length]);
+        }
+
+        data
+    }
+
+    #[test]
+    fn prints_compressed_band() {
+        let (mut printer, pages) = printer();
+
+        assert_eq!(send(&mut printer, INIT, false, &[], 0), [0x81, 0x00]);
+        assert_eq!(
+            send(&mut printer, DATA, true, &compressed_band(), 0),
+            [0x81, UNPROCESSED_DATA]
+        );
+        assert_eq!(printer.buffer.len(), 640);
+
+        // 1 sheet, no margin before and one after, default palette, exposure
+        assert_eq!(
+            send(&mut printer, PRINT, false, &[0x01, 0x01, 0xE4, 0x40], 0),
+            [0x81, PRINTING]
+        );
+
+        // Busy for a few inquiries, then done
+        let statuses: Vec<u8> = (0..4)
+            .map(|_| send(&mut printer, INQUIRY, false, &[], 0)[1])
+            .collect();
+        assert_eq!(statuses, [PRINTING, PRINTING, 0x00, 0x00]);
+
+        let page = pages.try_recv().unwrap();
+        assert_eq!(page.dimensions(), (PAPER_WIDTH as u32, 16 + 16));
+        for (_, y, pixel) in page.enumerate_pixels() {
+            let expected = if y < 8 { 0x00 } else { 0xFF };
+            assert_eq!(pixel.0[0], expected, "line {y}");
+        }
+        assert!(pages.try_recv().is_err());
+    }
+
+    #[test]
+    fn reports_checksum_errors() {
+        let (mut printer, _pages) = printer();
+
+        assert_eq!(
+            send(&mut printer, DATA, false, &[0xAA; 16], 1),
+            [0x81, CHECKSUM_ERROR]
+        );
+        assert!(printer.buffer.is_empty());
+
+        // The next good packet clears the error
+        assert_eq!(send(&mut printer, INQUIRY, false, &[], 0), [0x81, 0x00]);
+    }
+
+    #[test]
+    fn skips_garbage_before_magic() {
+        let (mut printer, _pages) = printer();
+
+        for byte in [0x00, 0x33, 0x88, 0x00] {
+            assert_eq!(printer.exchange(byte).unwrap(), 0x00);
+        }
+        assert_eq!(send(&mut printer, INIT, false, &[], 0), [0x81, 0x00]);
+    }
+
+    #[test]
+    fn print_without_margin_keeps_sheet_open() {
+        let (mut printer, pages) = printer();
+
+        send(&mut printer, DATA, true, &compressed_band(), 0);
+        send(&mut printer, PRINT, false, &[0x01, 0x00, 0xE4, 0x40], 0);
+        assert!(pages.try_recv().is_err());
+
+        // The next strip is glued below, the margin after it finishes the sheet
+        send(&mut printer, DATA, true, &compressed_band(), 0);
+        send(&mut printer, PRINT, false, &[0x01, 0x01, 0x1B, 0x40], 0);
+
+        let page = pages.try_recv().unwrap();
+        assert_eq!(page.height(), 16 + 16 + 16);
+        let column: Vec<u8> = (0..page.height())
+            .step_by(8)
+            .map(|y| page.get_pixel(0, y).0[0])
+            .collect();
+        // The second strip has its palette inverted, then comes the margin
+        assert_eq!(column, [0x00, 0xFF, 0xFF, 0x00, 0xFF, 0xFF]);
+    }
+}
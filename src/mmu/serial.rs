@@ -1,5 +1,11 @@
+use std::io;
+
 use crate::cpu::interrupts::{Interrupt, InterruptHandler};
-use crate::mmu::mmio::MMIO;
+use crate::mmu::{
+    mmio::MMIO,
+    serial_device::{Disconnected, SerialDevice},
+};
+use crate::save_state::{Snapshot, StateError, StateReader, StateWriter};
 
 pub struct Serial {
     sb: u8,
@@ -7,6 +13,13 @@ pub struct Serial {
 
     counter: u8,
     and_result_falling_edge: bool,
+    /// Byte that is being shifted out
+    transfer_byte: u8,
+
+    /// Whatever is plugged into the link port
+    device: Box<dyn SerialDevice>,
+    /// Set when the device was unplugged because of an error
+    pub device_error: Option<io::Error>,
 }
 
 impl Default for Serial {
@@ -17,12 +30,16 @@ impl Default for Serial {
 
             counter: 1,
             and_result_falling_edge: false,
+            transfer_byte: 0x00,
+
+            device: Box::new(Disconnected),
+            device_error: None,
         }
     }
 }
 
 impl MMIO for Serial {
-    fn read(&mut self, address: u16) -> u8 {
+    fn peek(&self, address: u16) -> u8 {
         match address {
             0xFF01 => self.sb,
             0xFF02 => self.sc,
@@ -33,7 +50,17 @@ impl MMIO for Serial {
     fn write(&mut self, address: u16, value: u8) {
         match address {
             0xFF01 => self.sb = value,
-            0xFF02 => self.sc = value | 0b0111_1110,
+            0xFF02 => {
+                self.sc = value | 0b0111_1110;
+
+                if self.is_transfer_requested() && self.is_internal_clock() {
+                    self.transfer_byte = self.sb;
+
+                    if let Err(e) = self.device.start_transfer(self.sb) {
+                        self.disconnect(e);
+                    }
+                }
+            }
             _ => unreachable!(),
         }
     }
@@ -41,10 +68,25 @@ impl MMIO for Serial {
 
 impl Serial {
     pub fn tick(&mut self, interrupt_handler: &mut InterruptHandler, cycles_passed: u16, div: u16) {
+        let waiting = self.is_transfer_requested() && !self.is_internal_clock();
+
+        // The device drives the external clock
+        match self.device.tick(cycles_passed as u64 * 4, self.sb, waiting) {
+            Ok(Some(data)) => {
+                self.sb = data;
+                self.sc &= !(1 << 7);
+
+                interrupt_handler.request_interrupt(Interrupt::Serial);
+            }
+            Ok(None) => {}
+            Err(e) => self.disconnect(e),
+        }
+
         for _ in 0..(cycles_passed * 4) {
             if self.and_result_falling_edge && !self.get_serial_falling_edge(div) {
                 if self.counter <= 8 {
-                    self.sb = (self.sb << self.counter) | (0xFF >> (8 - self.counter));
+                    self.sb = self.sb.checked_shl(self.counter as u32).unwrap_or(0)
+                        | (0xFF >> (8 - self.counter));
                     self.counter += 1;
                 }
 
@@ -52,6 +94,11 @@ impl Serial {
                     self.counter = 1;
                     self.sc = 0x01;
 
+                    match self.device.exchange(self.transfer_byte) {
+                        Ok(data) => self.sb = data,
+                        Err(e) => self.disconnect(e),
+                    }
+
                     interrupt_handler.request_interrupt(Interrupt::Serial);
                 }
             }
@@ -60,6 +107,21 @@ impl Serial {
         }
     }
 
+    pub fn device(&self) -> &dyn SerialDevice {
+        self.device.as_ref()
+    }
+
+    /// Plugs `device` into the link port and returns the previous one.
+    pub fn replace_device(&mut self, device: Box<dyn SerialDevice>) -> Box<dyn SerialDevice> {
+        std::mem::replace(&mut self.device, device)
+    }
+
+    /// Unplugs a device that failed, the port is left disconnected.
+    fn disconnect(&mut self, error: io::Error) {
+        self.device = Box::new(Disconnected);
+        self.device_error = Some(error);
+    }
+
     /// Serial timer is based on DIV, bit 8: 8kHz, and falling edge (see schematic)
     fn get_serial_falling_edge(&self, div: u16) -> bool {
         ((div & (1 << 8)) != 0) & self.is_internal_clock() & self.is_transfer_requested()
@@ -69,8 +131,112 @@ impl Serial {
         self.sc & (1 << 7) != 0
     }
 
-    // bit 0 is internal or external clock -- external clock is effectively disable for emulation
+    // bit 0 is internal or external clock -- external clock is only driven by the connected device
     fn is_internal_clock(&self) -> bool {
         self.sc & 1 != 0
     }
 }
+
+impl Snapshot for Serial {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.sb);
+        writer.write_u8(self.sc);
+        writer.write_u8(self.transfer_byte);
+        writer.write_u8(self.counter);
+        writer.write_bool(self.and_result_falling_edge);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.sb = reader.read_u8()?;
+        self.sc = reader.read_u8()?;
+        self.transfer_byte = reader.read_u8()?;
+        self.counter = reader.read_u8()?;
+        self.and_result_falling_edge = reader.read_bool()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, io, rc::Rc};
+
+    use super::Serial;
+    use crate::{
+        cpu::interrupts::{Interrupt, InterruptHandler},
+        mmu::{mmio::MMIO, serial_device::SerialDevice},
+        save_state::{Snapshot, StateReader, StateWriter},
+    };
+
+    /// Records what the Game Boy sends and always answers 0x5A.
+    struct Recorder(Rc<RefCell<Vec<u8>>>);
+
+    impl SerialDevice for Recorder {
+        fn name(&self) -> &str {
+            "Recorder"
+        }
+
+        fn exchange(&mut self, data: u8) -> io::Result<u8> {
+            self.0.borrow_mut().push(data);
+            Ok(0x5A)
+        }
+    }
+
+    fn serial() -> (Serial, Rc<RefCell<Vec<u8>>>) {
+        let sent = Rc::new(RefCell::new(Vec::new()));
+        let mut serial = Serial::default();
+        serial.replace_device(Box::new(Recorder(sent.clone())));
+
+        (serial, sent)
+    }
+
+    /// Ticks M-cycle by M-cycle with DIV running, for at most `cycles` M-cycles
+    /// or until the transfer is done.
+    fn run(serial: &mut Serial, div: &mut u16, cycles: u32) -> InterruptHandler {
+        let mut interrupts = InterruptHandler::default();
+        for _ in 0..cycles {
+            if serial.peek(0xFF02) & 0x80 == 0 {
+                break;
+            }
+
+            *div = div.wrapping_add(4);
+            serial.tick(&mut interrupts, 1, *div);
+        }
+
+        interrupts
+    }
+
+    #[test]
+    fn internal_clock_transfer() {
+        let (mut serial, sent) = serial();
+        serial.write(0xFF01, 0x42);
+        serial.write(0xFF02, 0x81);
+
+        // 8 bits at 8192 Hz
+        let interrupts = run(&mut serial, &mut 0, 8 * 128 + 128);
+
+        assert_eq!(*sent.borrow(), [0x42]);
+        assert_eq!(serial.peek(0xFF01), 0x5A);
+        assert_eq!(serial.peek(0xFF02) & 0x80, 0);
+        assert!(interrupts.is_interrupt_requested(Interrupt::Serial));
+    }
+
+    #[test]
+    fn transfer_survives_save_state() {
+        let (mut serial, _) = serial();
+        serial.write(0xFF01, 0x42);
+        serial.write(0xFF02, 0x81);
+
+        let mut div = 0;
+        run(&mut serial, &mut div, 4 * 128);
+        let mut writer = StateWriter::new();
+        serial.save_state(&mut writer);
+        let state = writer.into_inner();
+
+        let (mut loaded, sent) = self::serial();
+        loaded.load_state(&mut StateReader::new(&state)).unwrap();
+        run(&mut loaded, &mut div, 8 * 128);
+
+        assert_eq!(*sent.borrow(), [0x42]);
+    }
+}
@@ -0,0 +1,90 @@
+use std::io::{self, Write};
+
+/// Peripheral plugged into the link port.
+///
+/// Every transfer exchanges one byte: the Game Boy shifts out `SB` while the
+/// device shifts its answer in. With the internal clock the Game Boy drives the
+/// transfer and the device only answers, with the external clock the device
+/// drives it from `tick`.
+///
+/// A device that returns an error is unplugged and the port is left disconnected.
+pub trait SerialDevice {
+    /// Shown in the frontend, also tells the built-in devices apart.
+    fn name(&self) -> &str;
+
+    /// The Game Boy started a transfer with its internal clock, shifting out `data`.
+    fn start_transfer(&mut self, _data: u8) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// A transfer with the internal clock completed, `data` is the byte the Game Boy
+    /// shifted out. Returns the byte the device shifted in.
+    fn exchange(&mut self, data: u8) -> io::Result<u8>;
+
+    /// Advances the device by `cycles` serial clock (DIV) cycles.
+    ///
+    /// `sb` is the byte the Game Boy would shift out and `waiting` whether it has a
+    /// transfer with the external clock pending. Returns the byte shifted in if the
+    /// device clocked a transfer to completion.
+    fn tick(&mut self, _cycles: u64, _sb: u8, _waiting: bool) -> io::Result<Option<u8>> {
+        Ok(None)
+    }
+}
+
+/// Nothing plugged in, the data line stays high.
+#[derive(Default)]
+pub struct Disconnected;
+
+impl Disconnected {
+    pub const NAME: &'static str = "Disconnected";
+}
+
+impl SerialDevice for Disconnected {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn exchange(&mut self, _data: u8) -> io::Result<u8> {
+        Ok(0xFF)
+    }
+}
+
+/// Serial out wired to serial in, every byte sent comes right back.
+#[derive(Default)]
+pub struct Loopback;
+
+impl Loopback {
+    pub const NAME: &'static str = "Loopback";
+}
+
+impl SerialDevice for Loopback {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn exchange(&mut self, data: u8) -> io::Result<u8> {
+        Ok(data)
+    }
+}
+
+/// Prints every byte sent as a character, e.g. the results of Blargg's test ROMs.
+#[derive(Default)]
+pub struct StdoutLogger;
+
+impl StdoutLogger {
+    pub const NAME: &'static str = "Stdout logger";
+}
+
+impl SerialDevice for StdoutLogger {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn exchange(&mut self, data: u8) -> io::Result<u8> {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(&[data])?;
+        stdout.flush()?;
+
+        Ok(0xFF)
+    }
+}
@@ -1,4 +1,7 @@
-use crate::mmu::mmio::MMIO;
+use crate::{
+    mmu::mmio::MMIO,
+    save_state::{Snapshot, StateError, StateReader, StateWriter},
+};
 
 pub struct Timers {
     pub div: u16,
@@ -12,7 +15,7 @@ pub struct Timers {
 }
 
 impl MMIO for Timers {
-    fn read(&mut self, address: u16) -> u8 {
+    fn peek(&self, address: u16) -> u8 {
         match address {
             0xFF04 => (self.div >> 8) as u8,
             0xFF05 => self.tima,
@@ -138,3 +141,27 @@ impl Timers {
         (self.tac & 0b100) != 0
     }
 }
+
+impl Snapshot for Timers {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.div);
+        writer.write_u8(self.tima);
+        writer.write_u8(self.tma);
+        writer.write_u8(self.tac);
+        writer.write_bool(self.irq);
+        writer.write_bool(self.and_result_falling_edge);
+        writer.write_bool(self.reload);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.div = reader.read_u16()?;
+        self.tima = reader.read_u8()?;
+        self.tma = reader.read_u8()?;
+        self.tac = reader.read_u8()?;
+        self.irq = reader.read_bool()?;
+        self.and_result_falling_edge = reader.read_bool()?;
+        self.reload = reader.read_bool()?;
+
+        Ok(())
+    }
+}
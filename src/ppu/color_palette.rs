@@ -1,6 +1,8 @@
 use eframe::epaint::Color32;
 use std::sync::atomic::{AtomicBool, Ordering};
 
+use crate::save_state::{Snapshot, StateError, StateReader, StateWriter};
+
 /// Washes out the colors while converting from rgb555.
 ///
 /// Global variables bad yes but this is ok. :clueless:
@@ -12,7 +14,7 @@ pub static COLOR_CORRECTION: AtomicBool = AtomicBool::new(false);
 ///
 /// `OBP` shall be used for both obp0 and obp1 as long as the correct palette is passed.
 #[allow(clippy::upper_case_acronyms)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq)]
 pub enum Palette {
     BGP(u8),
     OBP(u8),
@@ -90,6 +92,21 @@ pub(super) fn convert_to_color(value: u8, palette: Palette, cgb: bool, cram: &[u
     }
 }
 
+/// DMG compatibility mode: `palette` (BGP, OBP0 or OBP1) maps the color value to one of the
+/// four colors of CRAM palette `number`.
+pub(super) fn convert_to_compat_color(
+    value: u8,
+    palette: u8,
+    number: u8,
+    cram: &[u8],
+) -> ScreenColor {
+    let shade = (palette >> (value * 2)) & 0b11;
+    let index = (number * 8 + shade * 2) as usize;
+
+    let color_bytes = u16::from_le_bytes([cram[index], cram[index + 1]]);
+    ScreenColor::FullColor(rgb555_to_color(color_bytes), value)
+}
+
 fn color_from_value(value: u8, index: u8) -> ScreenColor {
     match value {
         0b00 => ScreenColor::White(index),
@@ -101,7 +118,7 @@ fn color_from_value(value: u8, index: u8) -> ScreenColor {
 }
 
 // https://saveweb.github.io/near.sh/articles/video/color-emulation.html
-fn rgb555_to_color(rgb: u16) -> Color32 {
+pub fn rgb555_to_color(rgb: u16) -> Color32 {
     let red = (rgb & 0x1F) as u8;
     let green = ((rgb >> 5) & 0x1F) as u8;
     let blue = ((rgb >> 10) & 0x1F) as u8;
@@ -124,3 +141,37 @@ fn rgb555_to_color(rgb: u16) -> Color32 {
         )
     }
 }
+
+impl Snapshot for ScreenColor {
+    fn save_state(&self, writer: &mut StateWriter) {
+        match *self {
+            ScreenColor::White(i) => writer.write_bytes(&[0, i]),
+            ScreenColor::LightGray(i) => writer.write_bytes(&[1, i]),
+            ScreenColor::Gray(i) => writer.write_bytes(&[2, i]),
+            ScreenColor::Black(i) => writer.write_bytes(&[3, i]),
+            ScreenColor::FullColor(c, i) => {
+                writer.write_bytes(&[4, i]);
+                writer.write_bytes(&c.to_array());
+            }
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        let tag = reader.read_u8()?;
+        let index = reader.read_u8()?;
+
+        *self = match tag {
+            0 => ScreenColor::White(index),
+            1 => ScreenColor::LightGray(index),
+            2 => ScreenColor::Gray(index),
+            3 => ScreenColor::Black(index),
+            4 => {
+                let [r, g, b, a]: [u8; 4] = reader.read_bytes(4)?.try_into().unwrap();
+                ScreenColor::FullColor(Color32::from_rgba_premultiplied(r, g, b, a), index)
+            }
+            _ => return Err(StateError::InvalidValue("screen color")),
+        };
+
+        Ok(())
+    }
+}
@@ -0,0 +1,206 @@
+/// Colors of all palettes as RGB555, four per palette.
+///
+/// Some combinations start in the middle of a palette, so they are
+/// addressed by the index of their first color.
+#[rustfmt::skip]
+const COLORS: [u16; 120] = [
+    0x7FFF, 0x32BF, 0x00D0, 0x0000, // 0
+    0x639F, 0x4279, 0x15B0, 0x04CB, // 1
+    0x7FFF, 0x6E31, 0x454A, 0x0000, // 2
+    0x7FFF, 0x1BEF, 0x0200, 0x0000, // 3
+    0x7FFF, 0x421F, 0x1CF2, 0x0000, // 4
+    0x7FFF, 0x5294, 0x294A, 0x0000, // 5
+    0x7FFF, 0x03FF, 0x012F, 0x0000, // 6
+    0x7FFF, 0x03EF, 0x01D6, 0x0000, // 7
+    0x7FFF, 0x42B5, 0x3DC8, 0x0000, // 8
+    0x7E74, 0x03FF, 0x0180, 0x0000, // 9
+    0x67FF, 0x77AC, 0x1A13, 0x2D6B, // 10
+    0x7ED6, 0x4BFF, 0x2175, 0x0000, // 11
+    0x53FF, 0x4A5F, 0x7E52, 0x0000, // 12
+    0x4FFF, 0x7ED2, 0x3A4C, 0x1CE0, // 13
+    0x03ED, 0x7FFF, 0x255F, 0x0000, // 14
+    0x036A, 0x021F, 0x03FF, 0x7FFF, // 15
+    0x7FFF, 0x01DF, 0x0112, 0x0000, // 16
+    0x231F, 0x035F, 0x00F2, 0x0009, // 17
+    0x7FFF, 0x03EA, 0x011F, 0x0000, // 18
+    0x299F, 0x001A, 0x000C, 0x0000, // 19
+    0x7FFF, 0x027F, 0x001F, 0x0000, // 20
+    0x7FFF, 0x03E0, 0x0206, 0x0120, // 21
+    0x7FFF, 0x7EEB, 0x001F, 0x7C00, // 22
+    0x7FFF, 0x3FFF, 0x7E00, 0x001F, // 23
+    0x7FFF, 0x03FF, 0x001F, 0x0000, // 24
+    0x03FF, 0x001F, 0x000C, 0x0000, // 25
+    0x7FFF, 0x033F, 0x0193, 0x0000, // 26
+    0x0000, 0x4200, 0x037F, 0x7FFF, // 27
+    0x7FFF, 0x7E8C, 0x7C00, 0x0000, // 28
+    0x7FFF, 0x1BEF, 0x6180, 0x0000, // 29
+];
+
+/// First colors of OBJ0, OBJ1 and BG for every combination.
+#[rustfmt::skip]
+const COMBINATIONS: [[usize; 3]; 51] = [
+    palettes(4, 4, 29), palettes(18, 18, 18), palettes(20, 20, 20), palettes(24, 24, 24),
+    palettes(9, 9, 9), palettes(0, 0, 0), palettes(27, 27, 27), palettes(5, 5, 5),
+    palettes(12, 12, 12), palettes(26, 26, 26), palettes(16, 8, 8), palettes(4, 28, 28),
+    palettes(4, 2, 2), palettes(3, 4, 4), palettes(4, 29, 29), palettes(28, 4, 28),
+    palettes(2, 17, 2), palettes(16, 16, 8), palettes(4, 4, 7), palettes(4, 4, 18),
+    palettes(4, 4, 20), palettes(19, 19, 9), [4 * 4 - 1, 4 * 4 - 1, 11 * 4], palettes(17, 17, 2),
+    palettes(4, 4, 2), palettes(4, 4, 3), palettes(28, 28, 0), palettes(3, 3, 0),
+    palettes(0, 0, 1), palettes(18, 22, 18), palettes(20, 22, 20), palettes(24, 22, 24),
+    palettes(16, 22, 8), palettes(17, 4, 13), [28 * 4 - 1, 0, 14 * 4], [28 * 4 - 1, 4 * 4, 15 * 4],
+    [19 * 4, 23 * 4 - 1, 9 * 4], palettes(16, 28, 10), palettes(4, 23, 28), palettes(17, 22, 2),
+    palettes(4, 0, 2), palettes(4, 28, 3), palettes(28, 3, 0), palettes(3, 28, 4),
+    palettes(21, 28, 4), palettes(3, 28, 0), palettes(25, 3, 28), palettes(0, 28, 8),
+    palettes(4, 3, 28), palettes(28, 3, 6), palettes(4, 28, 29),
+];
+
+/// Sums of the title bytes of the games the boot ROM knows.
+///
+/// Titles from `FIRST_DUPLICATE` on share their sum with another one,
+/// they are told apart by the 4th letter of the title.
+#[rustfmt::skip]
+const TITLE_CHECKSUMS: [u8; 94] = [
+    0x00, 0x88, 0x16, 0x36, 0xD1, 0xDB, 0xF2, 0x3C, 0x8C, 0x92, 0x3D, 0x5C, 0x58, 0xC9, 0x3E, 0x70,
+    0x1D, 0x59, 0x69, 0x19, 0x35, 0xA8, 0x14, 0xAA, 0x75, 0x95, 0x99, 0x34, 0x6F, 0x15, 0xFF, 0x97,
+    0x4B, 0x90, 0x17, 0x10, 0x39, 0xF7, 0xF6, 0xA2, 0x49, 0x4E, 0x43, 0x68, 0xE0, 0x8B, 0xF0, 0xCE,
+    0x0C, 0x29, 0xE8, 0xB7, 0x86, 0x9A, 0x52, 0x01, 0x9D, 0x71, 0x9C, 0xBD, 0x5D, 0x6D, 0x67, 0x3F,
+    0x6B,
+    // Duplicates
+    0xB3, 0x46, 0x28, 0xA5, 0xC6, 0xD3, 0x27, 0x61, 0x18, 0x66, 0x6A, 0xBF, 0x0D, 0xF4,
+    0xB3, 0x46, 0x28, 0xA5, 0xC6, 0xD3, 0x27, 0x61, 0x18, 0x66, 0x6A, 0xBF, 0x0D, 0xF4, 0xB3,
+];
+
+const FIRST_DUPLICATE: usize = 65;
+
+/// 4th title letter of the duplicates, in the same order.
+const DUPLICATE_LETTERS: &[u8; 29] = b"BEFAARBEKEK R-URAR INAILICE R";
+
+/// Combination of every entry of `TITLE_CHECKSUMS`.
+#[rustfmt::skip]
+const TITLE_COMBINATIONS: [u8; 94] = [
+    0, 4, 5, 35, 34, 3, 31, 15, 10, 5, 19, 36, 7, 37, 30, 44,
+    21, 32, 31, 20, 5, 33, 13, 14, 5, 29, 5, 18, 9, 3, 2, 26,
+    25, 25, 41, 42, 26, 45, 42, 45, 36, 38, 26, 42, 30, 41, 34, 34,
+    5, 42, 6, 5, 33, 25, 42, 42, 40, 2, 16, 25, 42, 42, 5, 0,
+    39,
+    // Duplicates
+    36, 22, 25, 6, 32, 12, 36, 11, 39, 18, 39, 24, 31, 50,
+    17, 46, 6, 27, 0, 47, 41, 41, 0, 0, 19, 34, 23, 18, 29,
+];
+
+/// Combinations picked by holding a direction and optionally A or B
+/// while the CGB logo is shown.
+pub const BUTTON_COMBINATIONS: [(&str, usize); 12] = [
+    ("Up", 5),
+    ("Up + A", 43),
+    ("Up + B", 28),
+    ("Left", 48),
+    ("Left + A", 40),
+    ("Left + B", 7),
+    ("Down", 8),
+    ("Down + A", 3),
+    ("Down + B", 49),
+    ("Right", 1),
+    ("Right + A", 0),
+    ("Right + B", 6),
+];
+
+/// RGB555 colors of the BG, OBJ0 and OBJ1 palette, lightest shade first.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct CompatPalettes {
+    pub bg: [u16; 4],
+    pub obj0: [u16; 4],
+    pub obj1: [u16; 4],
+}
+
+impl CompatPalettes {
+    /// Palettes of `combination`, an index into the boot ROM's table.
+    ///
+    /// Unknown combinations get the default one, `Right + A`.
+    pub fn new(combination: usize) -> Self {
+        let [obj0, obj1, bg] = COMBINATIONS.get(combination).unwrap_or(&COMBINATIONS[0]);
+        let palette = |start: &usize| COLORS[*start..*start + 4].try_into().unwrap();
+
+        Self {
+            bg: palette(bg),
+            obj0: palette(obj0),
+            obj1: palette(obj1),
+        }
+    }
+}
+
+/// Combination the CGB boot ROM picks for the game with this `header`.
+///
+/// Only games published by Nintendo are looked up by the checksum of their
+/// title, every other game and unknown titles get the default combination.
+pub fn title_combination(header: &[u8]) -> usize {
+    let nintendo = match header[0x014B] {
+        0x01 => true,
+        0x33 => &header[0x0144..=0x0145] == b"01",
+        _ => false,
+    };
+    if !nintendo {
+        return 0;
+    }
+
+    let title = &header[0x0134..=0x0143];
+    let checksum = title.iter().fold(0u8, |acc, b| acc.wrapping_add(*b));
+
+    TITLE_CHECKSUMS
+        .iter()
+        .enumerate()
+        .position(|(i, &sum)| {
+            sum == checksum
+                && (i < FIRST_DUPLICATE || DUPLICATE_LETTERS[i - FIRST_DUPLICATE] == title[3])
+        })
+        .map_or(0, |i| TITLE_COMBINATIONS[i] as usize)
+}
+
+const fn palettes(obj0: usize, obj1: usize, bg: usize) -> [usize; 3] {
+    [obj0 * 4, obj1 * 4, bg * 4]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::title_combination;
+
+    /// Header with `title` and the old licensee code `licensee`.
+    fn header(title: &str, licensee: u8) -> Vec<u8> {
+        let mut header = vec![0; 0x150];
+        header[0x0134..0x0134 + title.len()].copy_from_slice(title.as_bytes());
+        header[0x014B] = licensee;
+        header
+    }
+
+    #[test]
+    fn nintendo_titles_by_checksum() {
+        assert_eq!(title_combination(&header("POKEMON RED", 0x01)), 13);
+        assert_eq!(title_combination(&header("TETRIS", 0x01)), 3);
+
+        // New licensee code "01" is Nintendo as well
+        let mut pokemon = header("POKEMON RED", 0x33);
+        pokemon[0x0144..=0x0145].copy_from_slice(b"01");
+        assert_eq!(title_combination(&pokemon), 13);
+    }
+
+    #[test]
+    fn duplicate_checksums_by_4th_letter() {
+        // Both sum up to 0x46
+        assert_eq!(title_combination(&header("SUPER MARIOLAND", 0x01)), 22);
+        assert_eq!(title_combination(&header("METROID2", 0x01)), 46);
+
+        // Same sum, but no title with this 4th letter
+        assert_eq!(title_combination(&header("METORID2", 0x01)), 0);
+    }
+
+    #[test]
+    fn other_licensees_get_default() {
+        assert_eq!(title_combination(&header("POKEMON RED", 0x08)), 0);
+
+        let mut capcom = header("POKEMON RED", 0x33);
+        capcom[0x0144..=0x0145].copy_from_slice(b"08");
+        assert_eq!(title_combination(&capcom), 0);
+
+        assert_eq!(title_combination(&header("UNKNOWN GAME", 0x01)), 0);
+    }
+}
@@ -5,13 +5,16 @@ use crate::{
     mmu::{hdma_transfer::Hdma, mmio::MMIO},
     ppu::{
         color_palette::*,
+        compat_palettes::CompatPalettes,
         ppu_regs::PPURegisters,
         sprite::Sprite,
         tile_attributes::{BgOamPrio, TileAttribute},
     },
+    save_state::{Snapshot, StateError, StateReader, StateWriter},
 };
 
 pub mod color_palette;
+pub mod compat_palettes;
 pub mod ppu_regs;
 pub mod sprite;
 pub mod tile_attributes;
@@ -75,10 +78,12 @@ pub struct PPU {
 
     internal_window_line: u8,
     pub cgb: bool,
+    /// DMG game on a CGB: renders like a DMG, the palette registers pick colors from CRAM
+    dmg_compat: bool,
 }
 
 impl MMIO for PPU {
-    fn read(&mut self, address: u16) -> u8 {
+    fn peek(&self, address: u16) -> u8 {
         match address {
             0xFF40 => self.regs.lcdc,
             0xFF41 => self.regs.stat,
@@ -233,6 +238,7 @@ impl PPU {
 
             internal_window_line: 0,
             cgb: false,
+            dmg_compat: false,
         }
     }
 
@@ -365,10 +371,50 @@ impl PPU {
         self.cgb = true;
     }
 
+    /// From here on the PPU behaves like a DMG one, except for its colors.
+    /// BG uses CRAM palette 0, OBJ palettes 0 and 1 stand in for OBP0 and OBP1.
+    pub fn enable_dmg_compat(&mut self) {
+        self.cgb = false;
+        self.dmg_compat = true;
+    }
+
+    pub fn is_dmg_compat(&self) -> bool {
+        self.dmg_compat
+    }
+
+    /// Writes the palettes used in DMG compatibility mode to CRAM,
+    /// like the CGB boot ROM does before it hands over to a DMG game.
+    pub fn set_compat_palettes(&mut self, palettes: &CompatPalettes) {
+        let (obj0, obj1) = self.obj_cram.split_at_mut(8);
+        let cram = [
+            (&mut self.bg_cram[..8], palettes.bg),
+            (obj0, palettes.obj0),
+            (&mut obj1[..8], palettes.obj1),
+        ];
+
+        for (cram, colors) in cram {
+            for (bytes, color) in cram.chunks_exact_mut(2).zip(colors) {
+                bytes.copy_from_slice(&color.to_le_bytes());
+            }
+        }
+    }
+
+    /// Register state at power on, before a boot ROM ran.
+    pub fn power_on(&mut self) {
+        self.regs.lcdc = 0x00;
+        self.regs.stat = 0x80;
+        self.regs.bgp = 0x00;
+        self.turn_lcd_off();
+    }
+
     // --------------------------
     //          DMA
     // --------------------------
 
+    pub fn regs(&self) -> &PPURegisters {
+        &self.regs
+    }
+
     pub fn get_dma_state(&self) -> DMATransferState {
         self.dma_state
     }
@@ -398,16 +444,16 @@ impl PPU {
 
     // -------- DEBUGGING STUFF --------
 
-    /// Dumps 256x256 BG map for the vram viewer
-    pub fn dump_bg_map(&mut self, vram: &[[u8; 0x2000]]) {
+    /// Dumps the 256x256 tile map at `map` ($9800 or $9C00) for the vram viewer,
+    /// with the CGB attributes of every tile applied
+    pub fn dump_tile_map(&mut self, vram: &[[u8; 0x2000]], map: u16) {
         let mut current_line: Vec<(ScreenColor, BgOamPrio)> = Vec::with_capacity(256);
 
         for y in 0..=255 {
             let unsigned_addressing = self.regs.lcdc & 0b10000 != 0;
-            let bg_tile_map_area =
-                if self.regs.lcdc & 0b1000 == 0 { 0x9800 - 0x8000 } else { 0x9C00 - 0x8000 };
+            let tile_map_area = (map - 0x8000) as usize;
 
-            let tile_map_start = bg_tile_map_area + (((y / 8) as usize) * 0x20);
+            let tile_map_start = tile_map_area + (((y / 8) as usize) * 0x20);
 
             for index in tile_map_start..=(tile_map_start + 0x1F) {
                 let tile_row = self.get_tile_row(vram, unsigned_addressing, index, y);
@@ -422,6 +468,106 @@ impl PPU {
         }
     }
 
+    /// Dumps all 384 tiles of both VRAM banks into a 256x192 image for the vram viewer,
+    /// 16 tiles per row with bank 1 to the right of bank 0
+    pub fn dump_tiles(&self, vram: &[[u8; 0x2000]], palette: Palette) -> Vec<ScreenColor> {
+        let mut pixels = vec![ScreenColor::White(0); 256 * 192];
+
+        for bank in 0..2 {
+            for tile in 0..384 {
+                let tile_x = bank * 16 + tile % 16;
+                let tile_y = tile / 16;
+
+                for y in 0..8 {
+                    let row = self.debug_tile_row(vram, bank, tile, y, palette, false);
+                    let start = (tile_y * 8 + y) * 256 + tile_x * 8;
+                    pixels[start..start + 8].copy_from_slice(&row);
+                }
+            }
+        }
+
+        pixels
+    }
+
+    /// Dumps a single sprite with its flips, bank and palette, 8 pixels wide and 8 or 16 high
+    /// depending on LCDC bit 2
+    pub fn dump_sprite(&self, vram: &[[u8; 0x2000]], sprite: &Sprite) -> Vec<ScreenColor> {
+        let height = if self.regs.is_sprite_8x8() { 8 } else { 16 };
+        let tile = if height == 8 { sprite.tile_index } else { sprite.tile_index & 0xFE };
+
+        let bank = if self.cgb { sprite.vbk() as usize } else { 0 };
+        let palette = Palette::OBP(if self.cgb {
+            sprite.get_cgb_obp_num()
+        } else {
+            sprite.get_dmg_obp_num()
+        });
+
+        (0..height)
+            .flat_map(|y| {
+                let y = if sprite.is_y_flipped() { height - 1 - y } else { y };
+                let tile = tile as usize + y / 8;
+                self.debug_tile_row(vram, bank, tile, y % 8, palette, sprite.is_x_flipped())
+            })
+            .collect()
+    }
+
+    /// BG palettes in CGB mode, stored as RGB555
+    pub fn bg_cram(&self) -> &[u8; 64] {
+        &self.bg_cram
+    }
+
+    /// OBJ palettes in CGB mode, stored as RGB555
+    pub fn obj_cram(&self) -> &[u8; 64] {
+        &self.obj_cram
+    }
+
+    /// Pixels of row `y` of tile 0-383 in `bank`
+    fn debug_tile_row(
+        &self,
+        vram: &[[u8; 0x2000]],
+        bank: usize,
+        tile: usize,
+        y: usize,
+        palette: Palette,
+        x_flip: bool,
+    ) -> [ScreenColor; 8] {
+        let first_byte = vram[bank][tile * 16 + 2 * y];
+        let second_byte = vram[bank][tile * 16 + 2 * y + 1];
+
+        let mut row = [ScreenColor::White(0); 8];
+        for i in 0..8 {
+            let lsb = (first_byte >> i) & 1;
+            let msb = (second_byte >> i) & 1;
+            let x = if x_flip { i } else { 7 - i };
+
+            row[x] = self.debug_color(msb << 1 | lsb, palette);
+        }
+
+        row
+    }
+
+    /// Colors for the debug views, with palette numbers instead of register values:
+    /// 0-7 for CRAM in CGB mode, otherwise BGP or OBP0 and OBP1.
+    ///
+    /// Unlike on screen, color 0 of the OBJ palettes is shown too.
+    fn debug_color(&self, value: u8, palette: Palette) -> ScreenColor {
+        let obp = |number: u8| if number & 1 == 0 { self.regs.opb0 } else { self.regs.opb1 };
+
+        match palette {
+            Palette::BGP(_) if self.cgb => convert_to_color(value, palette, true, &self.bg_cram),
+            Palette::OBP(_) if self.cgb => convert_to_color(value, palette, true, &self.obj_cram),
+            Palette::BGP(_) if self.dmg_compat => {
+                convert_to_compat_color(value, self.regs.bgp, 0, &self.bg_cram)
+            }
+            Palette::OBP(number) if self.dmg_compat => {
+                convert_to_compat_color(value, obp(number), number & 1, &self.obj_cram)
+            }
+            Palette::BGP(_) => convert_to_color(value, Palette::BGP(self.regs.bgp), false, &[]),
+            // OBP has no color for 0, the shades are mapped the same way as BGP
+            Palette::OBP(number) => convert_to_color(value, Palette::BGP(obp(number)), false, &[]),
+        }
+    }
+
     // -------- ACTUAL RENDERING --------
 
     fn update_current_line(&mut self, vram: &[[u8; 0x2000]]) {
@@ -441,7 +587,7 @@ impl PPU {
             0x9C00 - 0x8000
         };
 
-        let adjusted_y = self.regs.ly + self.regs.scy;
+        let adjusted_y = self.regs.ly.wrapping_add(self.regs.scy);
         let tile_map_start = bg_tile_map_area + (((adjusted_y / 8) as usize) * 0x20);
 
         for index in tile_map_start..=(tile_map_start + 0x1F) {
@@ -614,10 +760,13 @@ impl PPU {
             let msb = (second_byte & (1 << i)) >> i;
             let h_index = if tile_attribute.h_flip && self.cgb { i } else { 7 - i };
 
-            current_line[h_index] = (
-                convert_to_color(msb << 1 | lsb, Palette::BGP(bgp), self.cgb, &self.bg_cram),
-                tile_attribute.bg_to_oam,
-            );
+            let color = if self.dmg_compat {
+                convert_to_compat_color(msb << 1 | lsb, bgp, 0, &self.bg_cram)
+            } else {
+                convert_to_color(msb << 1 | lsb, Palette::BGP(bgp), self.cgb, &self.bg_cram)
+            };
+
+            current_line[h_index] = (color, tile_attribute.bg_to_oam);
         }
 
         current_line
@@ -655,7 +804,7 @@ impl PPU {
             }
         } else {
             if sprite.is_obj_prio() {
-                convert_to_color(index, palette, false, &self.obj_cram)
+                self.dmg_obj_color(sprite, index, palette)
             } else {
                 if matches!(
                     current_color,
@@ -663,8 +812,9 @@ impl PPU {
                         | ScreenColor::LightGray(0)
                         | ScreenColor::Gray(0)
                         | ScreenColor::Black(0)
+                        | ScreenColor::FullColor(_, 0)
                 ) {
-                    convert_to_color(index, palette, false, &self.obj_cram)
+                    self.dmg_obj_color(sprite, index, palette)
                 } else {
                     current_color
                 }
@@ -672,6 +822,15 @@ impl PPU {
         }
     }
 
+    fn dmg_obj_color(&self, sprite: &Sprite, index: u8, palette: Palette) -> ScreenColor {
+        match palette {
+            Palette::OBP(obp) if self.dmg_compat => {
+                convert_to_compat_color(index, obp, sprite.get_dmg_obp_num(), &self.obj_cram)
+            }
+            _ => convert_to_color(index, palette, false, &self.obj_cram),
+        }
+    }
+
     fn draw_current_line(&mut self) {
         let y = self.regs.ly as usize;
 
@@ -715,3 +874,84 @@ impl PPU {
         !prev_stat && current_stat
     }
 }
+
+impl Snapshot for PPU {
+    fn save_state(&self, writer: &mut StateWriter) {
+        for color in self.frame_buffer.iter().chain(self.ui_frame_buffer.iter()) {
+            color.save_state(writer);
+        }
+
+        writer.write_bytes(&self.bg_cram);
+        writer.write_u8(self.bgpi);
+        writer.write_bytes(&self.obj_cram);
+        writer.write_u8(self.obpi);
+
+        // `current_line` is always drawn and cleared within the same dot, sprites are not.
+        writer.write_u8(self.current_sprites.len() as u8);
+        for sprite in &self.current_sprites {
+            sprite.save_state(writer);
+        }
+
+        self.regs.save_state(writer);
+        writer.write_i16(self.dots);
+
+        writer.write_u8(self.current_mode as u8);
+        writer.write_bool(self.stat_block);
+        writer.write_u8(match self.dma_state {
+            DMATransferState::Pending => 0,
+            DMATransferState::Transferring => 1,
+            DMATransferState::Disabled => 2,
+        });
+
+        writer.write_u8(self.internal_window_line);
+        writer.write_bool(self.cgb);
+        writer.write_bool(self.dmg_compat);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        for color in self
+            .frame_buffer
+            .iter_mut()
+            .chain(self.ui_frame_buffer.iter_mut())
+        {
+            color.load_state(reader)?;
+        }
+
+        reader.read_into(&mut self.bg_cram)?;
+        self.bgpi = reader.read_u8()?;
+        reader.read_into(&mut self.obj_cram)?;
+        self.obpi = reader.read_u8()?;
+
+        self.current_line.clear();
+        self.current_sprites.clear();
+        for _ in 0..reader.read_u8()? {
+            let mut sprite = Sprite::new(0, 0, 0, 0);
+            sprite.load_state(reader)?;
+            self.current_sprites.push(sprite);
+        }
+
+        self.regs.load_state(reader)?;
+        self.dots = reader.read_i16()?;
+
+        self.current_mode = match reader.read_u8()? {
+            0 => Mode::HBlank,
+            1 => Mode::VBlank,
+            2 => Mode::Mode2,
+            3 => Mode::Mode3,
+            _ => return Err(StateError::InvalidValue("PPU mode")),
+        };
+        self.stat_block = reader.read_bool()?;
+        self.dma_state = match reader.read_u8()? {
+            0 => DMATransferState::Pending,
+            1 => DMATransferState::Transferring,
+            2 => DMATransferState::Disabled,
+            _ => return Err(StateError::InvalidValue("DMA state")),
+        };
+
+        self.internal_window_line = reader.read_u8()?;
+        self.cgb = reader.read_bool()?;
+        self.dmg_compat = reader.read_bool()?;
+
+        Ok(())
+    }
+}
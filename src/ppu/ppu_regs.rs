@@ -1,3 +1,5 @@
+use crate::save_state::{Snapshot, StateError, StateReader, StateWriter};
+
 #[derive(Debug)]
 pub struct PPURegisters {
     pub lcdc: u8,
@@ -61,4 +63,43 @@ impl PPURegisters {
     pub fn is_sprite_8x8(&self) -> bool {
         self.lcdc & 0x4 == 0
     }
+
+    /// $9800 or $9C00, depending on LCDC bit 3
+    pub fn bg_tile_map(&self) -> u16 {
+        if self.lcdc & 0x8 == 0 {
+            0x9800
+        } else {
+            0x9C00
+        }
+    }
+
+    /// $9800 or $9C00, depending on LCDC bit 6
+    pub fn window_tile_map(&self) -> u16 {
+        if self.lcdc & 0x40 == 0 {
+            0x9800
+        } else {
+            0x9C00
+        }
+    }
+}
+
+impl Snapshot for PPURegisters {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&[
+            self.lcdc, self.stat, self.scy, self.scx, self.ly, self.lyc, self.wy, self.wx,
+            self.bgp, self.opb0, self.opb1, self.dma,
+        ]);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        let mut regs = [0u8; 12];
+        reader.read_into(&mut regs)?;
+
+        [
+            self.lcdc, self.stat, self.scy, self.scx, self.ly, self.lyc, self.wy, self.wx,
+            self.bgp, self.opb0, self.opb1, self.dma,
+        ] = regs;
+
+        Ok(())
+    }
 }
@@ -1,3 +1,5 @@
+use crate::save_state::{Snapshot, StateError, StateReader, StateWriter};
+
 #[derive(Debug, Clone, Copy)]
 pub struct Sprite {
     pub y_pos: u8,
@@ -38,6 +40,13 @@ pub fn get_current_sprites_per_line(
     sprites
 }
 
+/// All 40 sprites in OAM order, for the vram viewer.
+pub fn get_all_sprites(oam: &[u8]) -> Vec<Sprite> {
+    oam.chunks(4)
+        .map(|attributes| Sprite::new(attributes[0], attributes[1], attributes[2], attributes[3]))
+        .collect()
+}
+
 impl Sprite {
     pub fn new(y_pos: u8, x_pos: u8, tile_index: u8, attr: u8) -> Self {
         Self {
@@ -76,3 +85,18 @@ impl Sprite {
         self.attr & 0x80 == 0
     }
 }
+
+impl Snapshot for Sprite {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&[self.y_pos, self.x_pos, self.tile_index, self.attr]);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.y_pos = reader.read_u8()?;
+        self.x_pos = reader.read_u8()?;
+        self.tile_index = reader.read_u8()?;
+        self.attr = reader.read_u8()?;
+
+        Ok(())
+    }
+}
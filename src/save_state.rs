@@ -0,0 +1,151 @@
+use std::fmt::Display;
+
+/// Magic bytes at the start of every save state.
+pub const STATE_MAGIC: &[u8; 4] = b"KVBS";
+/// Bumped whenever the layout of the serialized machine changes.
+pub const STATE_VERSION: u16 = 5;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateError {
+    InvalidMagic,
+    UnsupportedVersion(u16),
+    RomMismatch,
+    UnexpectedEof,
+    InvalidValue(&'static str),
+}
+
+impl Display for StateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StateError::InvalidMagic => write!(f, "File is not a Kevboy save state"),
+            StateError::UnsupportedVersion(v) => write!(
+                f,
+                "Save state version {v} is not supported (expected {STATE_VERSION})"
+            ),
+            StateError::RomMismatch => write!(f, "Save state belongs to a different ROM"),
+            StateError::UnexpectedEof => write!(f, "Save state is truncated"),
+            StateError::InvalidValue(what) => write!(f, "Save state contains an invalid {what}"),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+/// Implemented by every component that is part of a save state.
+///
+/// Fields are written and read back in the same order, so `load_state`
+/// has to mirror `save_state` exactly.
+pub trait Snapshot {
+    fn save_state(&self, writer: &mut StateWriter);
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError>;
+}
+
+/// Little endian byte sink for save states.
+#[derive(Default)]
+pub struct StateWriter {
+    buffer: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.buffer.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.buffer.push(value as u8);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.buffer.extend(value.to_le_bytes());
+    }
+
+    pub fn write_i16(&mut self, value: i16) {
+        self.buffer.extend(value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.buffer.extend(value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.buffer.extend(value.to_le_bytes());
+    }
+
+    pub fn write_f32(&mut self, value: f32) {
+        self.write_u32(value.to_bits());
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.buffer
+    }
+}
+
+/// Reads back what `StateWriter` produced, fails on truncated input.
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, StateError> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, StateError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub fn read_i16(&mut self) -> Result<i16, StateError> {
+        Ok(i16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, StateError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, StateError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub fn read_f32(&mut self) -> Result<f32, StateError> {
+        Ok(f32::from_bits(self.read_u32()?))
+    }
+
+    /// Fills `dest` completely with the next bytes.
+    pub fn read_into(&mut self, dest: &mut [u8]) -> Result<(), StateError> {
+        dest.copy_from_slice(self.take(dest.len())?);
+        Ok(())
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        self.take(len)
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        let end = self.position + len;
+        if end > self.data.len() {
+            return Err(StateError::UnexpectedEof);
+        }
+
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+
+        Ok(bytes)
+    }
+}
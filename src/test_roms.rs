@@ -0,0 +1,282 @@
+use std::{
+    cell::RefCell,
+    collections::hash_map::DefaultHasher,
+    fs,
+    hash::{Hash, Hasher},
+    io,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use image::RgbImage;
+
+use crate::{
+    emulator::Emulator,
+    mmu::{mmio::MMIO, serial_device::SerialDevice},
+    ppu::{color_palette::ScreenColor, LCD_HEIGHT, LCD_WIDTH},
+};
+
+/// Directory with the test ROMs, laid out like a release of
+/// https://github.com/c-sp/gameboy-test-roms. The tests are ignored by default, run them
+/// with `cargo test --release -- --ignored`.
+const ROMS_VAR: &str = "KEVBOY_TEST_ROMS";
+
+/// M-cycles of a frame in normal speed.
+const FRAME_CYCLES: u64 = 17_556;
+
+/// Registers B, C, D, E, H and L when a mooneye test passed, failed tests set all to 0x42.
+const MOONEYE_PASS: [u8; 6] = [3, 5, 8, 13, 21, 34];
+
+/// DMG acceptance tests that pass, relative to `mooneye-test-suite/acceptance`.
+///
+/// The same ones the README lists, any failure of them is a regression.
+const MOONEYE_PASSING: &[&str] = &[
+    "timer/div_write.gb",
+    "timer/tim00.gb",
+    "timer/tim00_div_trigger.gb",
+    "timer/tim01.gb",
+    "timer/tim01_div_trigger.gb",
+    "timer/tim10.gb",
+    "timer/tim10_div_trigger.gb",
+    "timer/tim11.gb",
+    "timer/tim11_div_trigger.gb",
+    "timer/tima_reload.gb",
+    "timer/tima_write_reloading.gb",
+    "timer/tma_write_reloading.gb",
+];
+
+fn rom_dir() -> PathBuf {
+    std::env::var_os(ROMS_VAR)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| panic!("{ROMS_VAR} has to point to the test ROMs"))
+}
+
+fn load(path: &Path) -> Emulator {
+    let rom = fs::read(path).unwrap_or_else(|e| panic!("{}: {e}", path.display()));
+
+    let mut emulator = Emulator::new();
+    emulator
+        .load_rom(&rom, None)
+        .unwrap_or_else(|e| panic!("{}: {e}", path.display()));
+
+    emulator
+}
+
+/// Steps until `done` holds before an instruction, returns false if that takes
+/// longer than `frames` frames.
+fn run(emulator: &mut Emulator, frames: u64, mut done: impl FnMut(&mut Emulator) -> bool) -> bool {
+    // In half M-cycles of normal speed, double speed M-cycles only take half as long
+    let mut elapsed = 0;
+
+    while elapsed < frames * FRAME_CYCLES * 2 {
+        if done(emulator) {
+            return true;
+        }
+
+        let factor = if emulator.bus.double_speed { 1 } else { 2 };
+        elapsed += emulator.step() as u64 * factor;
+    }
+
+    false
+}
+
+/// Collects every byte the game sends over the link port.
+struct SerialCapture(Rc<RefCell<Vec<u8>>>);
+
+impl SerialDevice for SerialCapture {
+    fn name(&self) -> &str {
+        "Serial capture"
+    }
+
+    fn exchange(&mut self, data: u8) -> io::Result<u8> {
+        self.0.borrow_mut().push(data);
+        Ok(0xFF)
+    }
+}
+
+/// Runs a Blargg test until it prints its result to the serial port.
+fn blargg(path: &str, frames: u64) {
+    let dir = rom_dir();
+
+    let mut emulator = load(&dir.join(path));
+    let output = Rc::new(RefCell::new(Vec::new()));
+    emulator
+        .bus
+        .serial
+        .replace_device(Box::new(SerialCapture(output.clone())));
+
+    let mut checked = 0;
+    run(&mut emulator, frames, |_| {
+        let output = output.borrow();
+        if output.len() == checked {
+            return false;
+        }
+        checked = output.len();
+
+        // The result ends with the line it is on, e.g. "Failed #2"
+        let text = String::from_utf8_lossy(&output);
+        ["Passed", "Failed"].iter().any(|result| {
+            text.split_once(result)
+                .is_some_and(|(_, rest)| rest.contains('\n'))
+        })
+    });
+
+    let output = String::from_utf8_lossy(&output.borrow()).into_owned();
+    assert!(
+        output.contains("Passed") && !output.contains("Failed"),
+        "{path}:\n{output}"
+    );
+}
+
+/// Runs a mooneye test until it executes `LD B, B` and returns whether the registers hold the signature.
+fn mooneye(path: &Path) -> bool {
+    let mut emulator = load(path);
+    let finished = run(&mut emulator, 20 * 60, |emulator| {
+        emulator.bus.peek(emulator.cpu.registers.PC) == 0x40
+    });
+
+    let r = &emulator.cpu.registers;
+    finished && [r.B, r.C, r.D, r.E, r.H, r.L] == MOONEYE_PASS
+}
+
+/// Mooneye tests name the models they are meant for after the last `-`, e.g. `-dmgABCmgb`
+/// or `-GS` (DMG, MGB, SGB). Tests without one run on every model.
+fn runs_on_dmg(path: &Path) -> bool {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+
+    match stem.rsplit_once('-') {
+        Some((_, models)) => {
+            models.contains("dmgABC")
+                || models.chars().all(|c| c.is_ascii_uppercase()) && models.contains('G')
+        }
+        None => true,
+    }
+}
+
+/// All `.gb` files below `dir`, sorted by path.
+fn roms_in(dir: &Path) -> Vec<PathBuf> {
+    let mut roms = Vec::new();
+
+    for entry in fs::read_dir(dir).unwrap_or_else(|e| panic!("{}: {e}", dir.display())) {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            roms.extend(roms_in(&path));
+        } else if path.extension().is_some_and(|e| e == "gb") {
+            roms.push(path);
+        }
+    }
+
+    roms.sort();
+    roms
+}
+
+/// The last frame as it is shown without a custom palette or color correction.
+///
+/// DMG shades are the ones the reference images of the acid2 tests use.
+fn screenshot(emulator: &Emulator) -> RgbImage {
+    let pixels = emulator
+        .bus
+        .ppu
+        .ui_frame_buffer
+        .iter()
+        .flat_map(|color| match *color {
+            ScreenColor::White(_) => [0xFF; 3],
+            ScreenColor::LightGray(_) => [0xAA; 3],
+            ScreenColor::Gray(_) => [0x55; 3],
+            ScreenColor::Black(_) => [0x00; 3],
+            ScreenColor::FullColor(c, _) => [c.r(), c.g(), c.b()],
+        })
+        .collect();
+
+    RgbImage::from_raw(LCD_WIDTH as u32, LCD_HEIGHT as u32, pixels).unwrap()
+}
+
+fn hash(image: &RgbImage) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    image.as_raw().hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Runs an acid2 test and compares the hash of its screen with the one of the reference image.
+///
+/// A screen that doesn't match is saved to the temporary directory.
+fn acid2(rom: &str, reference: &str) {
+    let dir = rom_dir();
+
+    let mut emulator = load(&dir.join(rom));
+    run(&mut emulator, 60, |_| false);
+
+    let actual = screenshot(&emulator);
+    let expected = image::open(dir.join(reference))
+        .unwrap_or_else(|e| panic!("{reference}: {e}"))
+        .to_rgb8();
+
+    if hash(&actual) != hash(&expected) {
+        let name = Path::new(rom).with_extension("png");
+        let path = std::env::temp_dir().join(name.file_name().unwrap());
+        actual.save(&path).unwrap();
+
+        panic!(
+            "{rom} doesn't match {reference}, the screen was saved to {}",
+            path.display()
+        );
+    }
+}
+
+#[test]
+#[ignore = "needs KEVBOY_TEST_ROMS"]
+fn blargg_cpu_instrs() {
+    blargg("blargg/cpu_instrs/cpu_instrs.gb", 60 * 60);
+}
+
+#[test]
+#[ignore = "needs KEVBOY_TEST_ROMS"]
+fn blargg_instr_timing() {
+    blargg("blargg/instr_timing/instr_timing.gb", 10 * 60);
+}
+
+#[test]
+#[ignore = "needs KEVBOY_TEST_ROMS"]
+fn blargg_mem_timing() {
+    blargg("blargg/mem_timing/mem_timing.gb", 10 * 60);
+}
+
+#[test]
+#[ignore = "needs KEVBOY_TEST_ROMS"]
+fn mooneye_acceptance() {
+    let dir = rom_dir().join("mooneye-test-suite/acceptance");
+
+    let failed: Vec<&str> = MOONEYE_PASSING
+        .iter()
+        .copied()
+        .filter(|rom| !mooneye(&dir.join(rom)))
+        .collect();
+
+    // Candidates for the list, only shown with --nocapture
+    for rom in roms_in(&dir).iter().filter(|rom| runs_on_dmg(rom)) {
+        let name = rom.strip_prefix(&dir).unwrap().display().to_string();
+        if !MOONEYE_PASSING.contains(&name.as_str()) && mooneye(rom) {
+            eprintln!("{name} passes as well");
+        }
+    }
+
+    assert!(
+        failed.is_empty(),
+        "{} of {} tests failed:\n{}",
+        failed.len(),
+        MOONEYE_PASSING.len(),
+        failed.join("\n")
+    );
+}
+
+#[test]
+#[ignore = "needs KEVBOY_TEST_ROMS"]
+fn dmg_acid2() {
+    acid2("dmg-acid2/dmg-acid2.gb", "dmg-acid2/dmg-acid2-dmg.png");
+}
+
+#[test]
+#[ignore = "needs KEVBOY_TEST_ROMS"]
+fn cgb_acid2() {
+    acid2("cgb-acid2/cgb-acid2.gbc", "cgb-acid2/cgb-acid2.png");
+}
@@ -0,0 +1,510 @@
+use egui::{Button, Color32, Grid, Label, RichText, Sense, TextEdit, Ui};
+
+use crate::{
+    cpu::{
+        debug::{
+            debugger::{parse_hex, Break, Breakpoint, Condition, Debugger},
+            instruction::Instruction,
+            watchpoint::{parse_range, ValueCondition, Watchpoint},
+        },
+        registers::Flag,
+    },
+    emulator::Emulator,
+    mmu::mmio::MMIO,
+};
+
+/// Instructions of the disassembly shown before and after PC.
+const LINES_BEFORE: usize = 6;
+const LINES_AFTER: usize = 14;
+
+/// Pausing, stepping, breakpoints and watchpoints on top of `Debugger`,
+/// with a live disassembly around PC.
+pub struct CpuDebugger {
+    pub open: bool,
+    pub debugger: Debugger,
+
+    /// Why emulation paused, `None` if paused from the outside
+    status: Option<String>,
+    address_input: String,
+    condition_input: String,
+    input_error: Option<String>,
+
+    watch_range_input: String,
+    watch_condition_input: String,
+    watch_read: bool,
+    watch_write: bool,
+    watch_error: Option<String>,
+}
+
+impl CpuDebugger {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            debugger: Debugger::new(),
+
+            status: None,
+            address_input: String::new(),
+            condition_input: String::new(),
+            input_error: None,
+
+            watch_range_input: String::new(),
+            watch_condition_input: String::new(),
+            watch_read: false,
+            watch_write: true,
+            watch_error: None,
+        }
+    }
+
+    /// Executes one instruction under the debugger, returns its cycles and whether
+    /// emulation has to pause. Breakpoints pause before the instruction, watchpoints
+    /// after it. The window is opened when it pauses.
+    pub fn step(&mut self, emulator: &mut Emulator) -> (u8, bool) {
+        if let Some(reason) = self.debugger.check(&emulator.cpu) {
+            self.pause_for(reason);
+            return (0, true);
+        }
+
+        let pc = emulator.cpu.registers.PC;
+        let cycles = emulator.step();
+
+        match self.debugger.check_watchpoints(&mut emulator.bus, pc) {
+            Some(reason) => {
+                self.pause_for(reason);
+                (cycles, true)
+            }
+            None => (cycles, false),
+        }
+    }
+
+    fn pause_for(&mut self, reason: Break) {
+        self.status = Some(reason.to_string());
+        self.open = true;
+    }
+
+    /// Continues after a pause without hitting the breakpoint at PC again.
+    pub fn resume(&mut self, pause: &mut bool) {
+        self.debugger.resume();
+        self.status = None;
+        *pause = false;
+    }
+
+    pub fn show(&mut self, ui: &mut Ui, emulator: &mut Emulator, pause: &mut bool) {
+        let loaded = !emulator.rom.is_empty();
+
+        ui.add_enabled_ui(loaded, |ui| self.show_controls(ui, emulator, pause));
+        ui.separator();
+        self.show_registers(ui, emulator);
+        ui.separator();
+        if loaded {
+            self.show_disassembly(ui, emulator, pause);
+            ui.separator();
+        }
+        self.show_breakpoints(ui);
+        ui.separator();
+        self.show_watchpoints(ui, &mut emulator.bus.watchpoints.list);
+    }
+
+    fn show_controls(&mut self, ui: &mut Ui, emulator: &mut Emulator, pause: &mut bool) {
+        let registers = &emulator.cpu.registers;
+        let (pc, sp) = (registers.PC, registers.SP);
+
+        ui.horizontal(|ui| {
+            if *pause {
+                if ui.button("▶ Continue").clicked() {
+                    self.resume(pause);
+                }
+            } else if ui.button("⏸ Pause").clicked() {
+                *pause = true;
+            }
+
+            ui.separator();
+
+            ui.add_enabled_ui(*pause, |ui| {
+                if ui
+                    .button("Step")
+                    .on_hover_text("Execute one instruction")
+                    .clicked()
+                {
+                    self.single_step(emulator);
+                }
+
+                if ui
+                    .button("Step over")
+                    .on_hover_text("Run a call until it returned")
+                    .clicked()
+                {
+                    let instruction = Instruction::decode(pc, |address| emulator.bus.peek(address));
+
+                    if instruction.is_call() {
+                        self.debugger.step_over(&instruction, sp);
+                        self.status = None;
+                        *pause = false;
+                    } else {
+                        self.single_step(emulator);
+                    }
+                }
+
+                if ui
+                    .button("Step out")
+                    .on_hover_text("Run until the current function returned")
+                    .clicked()
+                {
+                    self.debugger.step_out(sp);
+                    self.status = None;
+                    *pause = false;
+                }
+            });
+        });
+
+        let status = match (&self.status, *pause) {
+            (_, false) => "Running".to_string(),
+            (Some(status), true) => status.clone(),
+            (None, true) => format!("Paused at ${pc:04X}"),
+        };
+        ui.label(RichText::new(status).size(13.0));
+    }
+
+    fn single_step(&mut self, emulator: &mut Emulator) {
+        let pc = emulator.cpu.registers.PC;
+        emulator.cycle_count += emulator.step() as u16;
+
+        self.status = Some(
+            match self.debugger.check_watchpoints(&mut emulator.bus, pc) {
+                Some(reason) => reason.to_string(),
+                None => format!("Stepped to ${:04X}", emulator.cpu.registers.PC),
+            },
+        );
+    }
+
+    fn show_registers(&self, ui: &mut Ui, emulator: &Emulator) {
+        let cpu = &emulator.cpu;
+        let registers = &cpu.registers;
+
+        let name = |text: &str| {
+            RichText::new(text)
+                .strong()
+                .monospace()
+                .color(Color32::GRAY)
+        };
+        let value = |text: String| {
+            RichText::new(text)
+                .strong()
+                .monospace()
+                .color(Color32::GOLD)
+        };
+
+        Grid::new("debugger registers").show(ui, |ui| {
+            let pairs = [
+                ("AF", registers.get_af(), "BC", registers.get_bc()),
+                ("DE", registers.get_de(), "HL", registers.get_hl()),
+                ("SP", registers.SP, "PC", registers.PC),
+            ];
+
+            for (left, left_value, right, right_value) in pairs {
+                ui.label(name(left));
+                ui.label(value(format!("{left_value:04X}")));
+                ui.label(name(right));
+                ui.label(value(format!("{right_value:04X}")));
+                ui.end_row();
+            }
+
+            let flags = [
+                ('Z', Flag::Zero),
+                ('N', Flag::Substraction),
+                ('H', Flag::HalfCarry),
+                ('C', Flag::Carry),
+            ]
+            .iter()
+            .map(|&(c, flag)| if registers.get_flag(flag) { c } else { '-' })
+            .collect::<String>();
+
+            ui.label(name("Flags"));
+            ui.label(value(flags));
+            ui.label(name("IME"));
+            ui.label(value(format!("{}", cpu.ime as u8)));
+            ui.end_row();
+
+            if cpu.halt {
+                ui.label(name("HALT"));
+                ui.end_row();
+            }
+        });
+    }
+
+    fn show_disassembly(&mut self, ui: &mut Ui, emulator: &mut Emulator, pause: &mut bool) {
+        let pc = emulator.cpu.registers.PC;
+        let instructions = disassemble_around(emulator, pc);
+
+        Grid::new("disassembly").striped(true).show(ui, |ui| {
+            for instruction in instructions {
+                let address = instruction.address;
+                let current = address == pc;
+
+                let dot = RichText::new("●").color(if self.debugger.has_breakpoint(address) {
+                    Color32::RED
+                } else {
+                    Color32::DARK_GRAY
+                });
+                if ui
+                    .add(Button::new(dot).frame(false))
+                    .on_hover_text("Toggle breakpoint")
+                    .clicked()
+                {
+                    self.debugger.toggle_breakpoint(address);
+                }
+
+                let color = if current { Color32::GOLD } else { Color32::GRAY };
+                let marker = if current { "▶" } else { " " };
+                ui.label(
+                    RichText::new(format!("{marker} {address:04X}"))
+                        .monospace()
+                        .color(color),
+                );
+
+                let bytes = instruction
+                    .bytes
+                    .iter()
+                    .map(|b| format!("{b:02X} "))
+                    .collect::<String>();
+                ui.label(RichText::new(bytes).monospace().color(Color32::DARK_GRAY));
+
+                let text = RichText::new(instruction.to_string()).monospace();
+                let text = if current { text.color(Color32::GOLD) } else { text };
+                ui.add(Label::new(text).sense(Sense::click()))
+                    .on_hover_text("Right click for more")
+                    .context_menu(|ui| {
+                        if ui.button("Run to cursor").clicked() {
+                            self.debugger.run_to(address);
+                            self.status = None;
+                            *pause = false;
+                            ui.close_menu();
+                        }
+                        if ui.button("Toggle breakpoint").clicked() {
+                            self.debugger.toggle_breakpoint(address);
+                            ui.close_menu();
+                        }
+                    });
+
+                ui.end_row();
+            }
+        });
+    }
+
+    fn show_breakpoints(&mut self, ui: &mut Ui) {
+        ui.label(RichText::new("Breakpoints").strong());
+
+        ui.horizontal(|ui| {
+            ui.add(
+                TextEdit::singleline(&mut self.address_input)
+                    .desired_width(50.0)
+                    .hint_text("0150"),
+            );
+            ui.label("if");
+            ui.add(
+                TextEdit::singleline(&mut self.condition_input)
+                    .desired_width(90.0)
+                    .hint_text("A == 3F"),
+            )
+            .on_hover_text("Optional, compares a register with a hexadecimal value");
+
+            if ui.button("Add").clicked() {
+                self.add_breakpoint();
+            }
+        });
+
+        if let Some(error) = &self.input_error {
+            ui.colored_label(Color32::RED, error);
+        }
+
+        let mut remove = None;
+        Grid::new("breakpoints").striped(true).show(ui, |ui| {
+            for (i, breakpoint) in self.debugger.breakpoints.iter_mut().enumerate() {
+                let address = RichText::new(format!("{:04X}", breakpoint.address)).monospace();
+                ui.checkbox(&mut breakpoint.enabled, address);
+
+                if let Some(condition) = breakpoint.condition {
+                    ui.label(RichText::new(format!("if {condition}")).monospace());
+                } else {
+                    ui.label("");
+                }
+
+                if ui.small_button("🗑").clicked() {
+                    remove = Some(i);
+                }
+                ui.end_row();
+            }
+        });
+
+        if let Some(i) = remove {
+            self.debugger.breakpoints.remove(i);
+        }
+    }
+
+    fn show_watchpoints(&mut self, ui: &mut Ui, watchpoints: &mut Vec<Watchpoint>) {
+        ui.label(RichText::new("Watchpoints").strong());
+
+        ui.horizontal(|ui| {
+            ui.add(
+                TextEdit::singleline(&mut self.watch_range_input)
+                    .desired_width(80.0)
+                    .hint_text("C000-C0FF"),
+            );
+            ui.checkbox(&mut self.watch_read, "R");
+            ui.checkbox(&mut self.watch_write, "W");
+            ui.label("if");
+            ui.add(
+                TextEdit::singleline(&mut self.watch_condition_input)
+                    .desired_width(50.0)
+                    .hint_text("== 0"),
+            )
+            .on_hover_text("Optional, compares the value read or written with a hexadecimal value");
+
+            if ui.button("Add").clicked() {
+                self.add_watchpoint(watchpoints);
+            }
+        });
+
+        if let Some(error) = &self.watch_error {
+            ui.colored_label(Color32::RED, error);
+        }
+
+        let mut remove = None;
+        Grid::new("watchpoints").striped(true).show(ui, |ui| {
+            for (i, watchpoint) in watchpoints.iter_mut().enumerate() {
+                let (start, end) = (*watchpoint.range.start(), *watchpoint.range.end());
+                let range = if start == end {
+                    format!("{start:04X}")
+                } else {
+                    format!("{start:04X}-{end:04X}")
+                };
+                ui.checkbox(&mut watchpoint.enabled, RichText::new(range).monospace());
+
+                let access = match (watchpoint.read, watchpoint.write) {
+                    (true, true) => "RW",
+                    (true, false) => "R",
+                    _ => "W",
+                };
+                let condition = watchpoint
+                    .condition
+                    .map(|c| format!(" if {c}"))
+                    .unwrap_or_default();
+                ui.label(RichText::new(format!("{access}{condition}")).monospace());
+
+                if ui.small_button("🗑").clicked() {
+                    remove = Some(i);
+                }
+                ui.end_row();
+            }
+        });
+
+        if let Some(i) = remove {
+            watchpoints.remove(i);
+        }
+    }
+
+    fn add_watchpoint(&mut self, watchpoints: &mut Vec<Watchpoint>) {
+        let Some(range) = parse_range(&self.watch_range_input) else {
+            self.watch_error = Some(format!(
+                "\"{}\" is not a hexadecimal address or range",
+                self.watch_range_input.trim()
+            ));
+            return;
+        };
+
+        if !self.watch_read && !self.watch_write {
+            self.watch_error = Some("Watch reads, writes or both".to_string());
+            return;
+        }
+
+        let condition = match self.watch_condition_input.trim() {
+            "" => None,
+            condition => match condition.parse::<ValueCondition>() {
+                Ok(condition) => Some(condition),
+                Err(e) => {
+                    self.watch_error = Some(e.to_string());
+                    return;
+                }
+            },
+        };
+
+        watchpoints.push(Watchpoint {
+            range,
+            read: self.watch_read,
+            write: self.watch_write,
+            condition,
+            enabled: true,
+        });
+
+        self.watch_range_input.clear();
+        self.watch_condition_input.clear();
+        self.watch_error = None;
+    }
+
+    fn add_breakpoint(&mut self) {
+        let Some(address) = parse_hex(&self.address_input) else {
+            self.input_error = Some(format!(
+                "\"{}\" is not a hexadecimal address",
+                self.address_input.trim()
+            ));
+            return;
+        };
+
+        let condition = match self.condition_input.trim() {
+            "" => None,
+            condition => match condition.parse::<Condition>() {
+                Ok(condition) => Some(condition),
+                Err(e) => {
+                    self.input_error = Some(e.to_string());
+                    return;
+                }
+            },
+        };
+
+        self.debugger.breakpoints.push(Breakpoint {
+            address,
+            condition,
+            enabled: true,
+        });
+
+        self.address_input.clear();
+        self.condition_input.clear();
+        self.input_error = None;
+    }
+}
+
+/// Instructions around `pc`, read without ticking the bus.
+///
+/// Instructions have different lengths, so where the ones before PC start is
+/// a guess: the earliest start from which decoding lines up with PC wins.
+fn disassemble_around(emulator: &mut Emulator, pc: u16) -> Vec<Instruction> {
+    let mut read = |address| emulator.bus.peek(address);
+
+    let start = (1..=LINES_BEFORE as u16 * 3)
+        .rev()
+        .map(|distance| pc.wrapping_sub(distance))
+        .find(|&start| {
+            let mut address = start;
+            while address.wrapping_sub(start) < pc.wrapping_sub(start) {
+                address = Instruction::decode(address, &mut read).next_address();
+            }
+            address == pc
+        })
+        .unwrap_or(pc);
+
+    let mut before = Vec::new();
+    let mut address = start;
+    while address != pc {
+        let instruction = Instruction::decode(address, &mut read);
+        address = instruction.next_address();
+        before.push(instruction);
+    }
+
+    let mut instructions = before.split_off(before.len().saturating_sub(LINES_BEFORE));
+    for _ in 0..=LINES_AFTER {
+        let instruction = Instruction::decode(address, &mut read);
+        address = instruction.next_address();
+        instructions.push(instruction);
+    }
+
+    instructions
+}
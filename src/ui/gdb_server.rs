@@ -0,0 +1,76 @@
+use eframe::CreationContext;
+use egui::{DragValue, RichText, Ui};
+
+use crate::{
+    cpu::debug::{debugger::Debugger, gdb_stub::GdbStub},
+    emulator::Emulator,
+};
+
+/// Starts and stops the GDB server on a local port.
+///
+/// Connect with `target remote localhost:<port>`, emulation pauses while GDB is attached.
+pub struct GdbServer {
+    pub open: bool,
+    pub port: u16,
+
+    stub: Option<GdbStub>,
+    status: String,
+}
+
+impl GdbServer {
+    pub fn new(cc: &CreationContext) -> Self {
+        let port = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, "gdb_port"))
+            .unwrap_or(2345);
+
+        Self {
+            open: false,
+            port,
+
+            stub: None,
+            status: "Not running".to_string(),
+        }
+    }
+
+    /// Serves the connected client, called every frame.
+    pub fn poll(&mut self, emulator: &mut Emulator, debugger: &mut Debugger, pause: &mut bool) {
+        if let Some(stub) = &mut self.stub {
+            if let Err(e) = stub.poll(emulator, debugger, pause) {
+                self.status = format!("Connection lost: {e}");
+            }
+        }
+    }
+
+    pub fn show(&mut self, ui: &mut Ui) {
+        ui.vertical_centered(|ui| {
+            ui.horizontal(|ui| {
+                ui.label("Port:");
+                ui.add_enabled(self.stub.is_none(), DragValue::new(&mut self.port));
+
+                if self.stub.is_some() {
+                    if ui.button("Stop").clicked() {
+                        self.stub = None;
+                        self.status = "Not running".to_string();
+                    }
+                } else if ui.button("Start").clicked() {
+                    match GdbStub::bind(self.port) {
+                        Ok(stub) => {
+                            self.stub = Some(stub);
+                            self.status = format!("Listening on localhost:{}", self.port);
+                        }
+                        Err(e) => self.status = format!("Starting failed: {e}"),
+                    }
+                }
+            });
+
+            ui.add_space(5.0);
+            ui.separator();
+            let status = match &self.stub {
+                Some(stub) if stub.is_connected() => "GDB attached".to_string(),
+                _ => self.status.clone(),
+            };
+            ui.label(RichText::new(status).size(13.0));
+        });
+    }
+}
@@ -0,0 +1,144 @@
+use std::{
+    io::ErrorKind,
+    net::{TcpListener, TcpStream},
+};
+
+use eframe::CreationContext;
+use egui::{RichText, TextEdit, Ui};
+
+use crate::mmu::{link_cable::LinkCable, serial::Serial, serial_device::Disconnected};
+
+/// Connects two instances with a link cable over TCP.
+///
+/// One instance hosts on an address, the other one connects to it.
+/// Which Game Boy drives the clock is decided by the games, not by who hosts.
+pub struct LinkSettings {
+    pub open: bool,
+    pub address: String,
+
+    listener: Option<TcpListener>,
+    peer: Option<String>,
+    status: String,
+}
+
+impl LinkSettings {
+    pub fn new(cc: &CreationContext) -> Self {
+        let address = cc
+            .storage
+            .and_then(|storage| eframe::get_value::<String>(storage, "link_address"))
+            .unwrap_or_else(|| "127.0.0.1:7654".to_string());
+
+        Self {
+            open: false,
+            address,
+
+            listener: None,
+            peer: None,
+            status: "Not connected".to_string(),
+        }
+    }
+
+    /// Accepts a waiting peer and reports a device that failed, called every frame.
+    pub fn poll(&mut self, serial: &mut Serial) {
+        if let Some(listener) = &self.listener {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    self.listener = None;
+                    self.attach(serial, stream);
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => {
+                    self.listener = None;
+                    self.status = format!("Hosting failed: {e}");
+                }
+            }
+        }
+
+        if let Some(e) = serial.device_error.take() {
+            self.status = format!("Disconnected: {e}");
+
+            rfd::MessageDialog::new()
+                .set_title("The link port device was unplugged!")
+                .set_description(&e.to_string())
+                .show();
+        }
+    }
+
+    pub fn show(&mut self, ui: &mut Ui, serial: &mut Serial) {
+        let connected = serial.device().name() == LinkCable::NAME;
+
+        ui.vertical_centered(|ui| {
+            ui.horizontal(|ui| {
+                ui.label("Address:");
+                ui.add_enabled(
+                    !connected && self.listener.is_none(),
+                    TextEdit::singleline(&mut self.address),
+                );
+            });
+            ui.add_space(5.0);
+
+            ui.horizontal(|ui| {
+                if connected {
+                    if ui.button("Disconnect").clicked() {
+                        serial.replace_device(Box::new(Disconnected));
+                        self.status = "Not connected".to_string();
+                    }
+                } else if self.listener.is_some() {
+                    if ui.button("Cancel").clicked() {
+                        self.listener = None;
+                        self.status = "Not connected".to_string();
+                    }
+                } else {
+                    if ui.button("Host").clicked() {
+                        self.host();
+                    }
+                    if ui.button("Connect").clicked() {
+                        match TcpStream::connect(&self.address) {
+                            Ok(stream) => self.attach(serial, stream),
+                            Err(e) => self.status = format!("Connecting failed: {e}"),
+                        }
+                    }
+                }
+            });
+
+            ui.add_space(5.0);
+            ui.separator();
+            // Another device may have been plugged in from the menu in the meantime
+            let status = match (connected, &self.peer) {
+                (true, Some(peer)) => format!("Connected to {peer}"),
+                _ => self.status.clone(),
+            };
+            ui.label(RichText::new(status).size(13.0));
+        });
+    }
+
+    fn host(&mut self) {
+        let listener = TcpListener::bind(&self.address).and_then(|listener| {
+            listener.set_nonblocking(true)?;
+            Ok(listener)
+        });
+
+        match listener {
+            Ok(listener) => {
+                self.listener = Some(listener);
+                self.status = format!("Waiting for a connection on {} . . .", self.address);
+            }
+            Err(e) => self.status = format!("Hosting failed: {e}"),
+        }
+    }
+
+    fn attach(&mut self, serial: &mut Serial, stream: TcpStream) {
+        let link = stream
+            .set_nonblocking(false)
+            .and_then(|_| LinkCable::new(stream));
+
+        match link {
+            Ok(link) => {
+                self.peer = Some(link.peer_address().unwrap_or_default());
+                self.status = "Not connected".to_string();
+                serial.replace_device(Box::new(link));
+            }
+            Err(e) => self.status = format!("Connecting failed: {e}"),
+        }
+    }
+}
@@ -1,86 +1,320 @@
 use eframe::{
-    egui::{Grid, RichText, ScrollArea, Separator, TextStyle, Ui},
+    egui::{
+        ComboBox, Grid, Key, Label, RichText, ScrollArea, Sense, Separator, TextEdit, TextStyle, Ui,
+    },
     epaint::Color32,
 };
 
+use crate::{emulator::Emulator, mmu::mmio::MMIO};
+
+/// Frames a byte stays highlighted after it changed.
+const HIGHLIGHT_FRAMES: u8 = 30;
+
+/// Part of memory the viewer shows, banked memory is shown one bank at a time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Region {
+    /// Everything the CPU sees, with the banks that are mapped right now
+    Bus,
+    /// The ROM of the cartridge with all banks, edits patch it
+    Rom,
+    Vram(usize),
+    Wram(usize),
+    Sram(usize),
+    Oam,
+    Hram,
+}
+
+impl Region {
+    fn name(self) -> String {
+        match self {
+            Region::Bus => "Bus".to_string(),
+            Region::Rom => "ROM".to_string(),
+            Region::Vram(bank) => format!("VRAM bank {bank}"),
+            Region::Wram(bank) => format!("WRAM bank {bank}"),
+            Region::Sram(bank) => format!("SRAM bank {bank}"),
+            Region::Oam => "OAM".to_string(),
+            Region::Hram => "HRAM".to_string(),
+        }
+    }
+
+    /// Address of the first byte on the bus, the ROM is shown by offset instead
+    fn start(self) -> usize {
+        match self {
+            Region::Bus | Region::Rom => 0,
+            Region::Vram(_) => 0x8000,
+            Region::Sram(_) => 0xA000,
+            Region::Wram(0) => 0xC000,
+            Region::Wram(_) => 0xD000,
+            Region::Oam => 0xFE00,
+            Region::Hram => 0xFF80,
+        }
+    }
+
+    fn bank(self) -> Option<usize> {
+        match self {
+            Region::Vram(bank) | Region::Wram(bank) | Region::Sram(bank) => Some(bank),
+            _ => None,
+        }
+    }
+
+    /// Current contents, read without side effects.
+    fn read(self, emulator: &Emulator) -> Vec<u8> {
+        let bus = &emulator.bus;
+
+        match self {
+            Region::Bus => (0..=0xFFFF).map(|address| bus.peek(address)).collect(),
+            Region::Rom => bus.cartridge.rom().to_vec(),
+            Region::Vram(bank) => bus.vram[bank].to_vec(),
+            Region::Wram(bank) => bus.wram[bank].to_vec(),
+            Region::Sram(bank) => bus
+                .cartridge
+                .sram()
+                .chunks(0x2000)
+                .nth(bank)
+                .unwrap_or_default()
+                .to_vec(),
+            Region::Oam => bus.oam.to_vec(),
+            Region::Hram => bus.hram[..0x7F].to_vec(),
+        }
+    }
+
+    fn write(self, emulator: &mut Emulator, offset: usize, value: u8) {
+        let bus = &mut emulator.bus;
+
+        match self {
+            Region::Bus => bus.poke(offset as u16, value),
+            Region::Rom => bus.cartridge.rom_mut()[offset] = value,
+            Region::Vram(bank) => bus.vram[bank][offset] = value,
+            Region::Wram(bank) => bus.wram[bank][offset] = value,
+            Region::Sram(bank) => bus.cartridge.sram_mut()[bank * 0x2000 + offset] = value,
+            Region::Oam => bus.oam[offset] = value,
+            Region::Hram => bus.hram[offset] = value,
+        }
+    }
+}
+
+/// Live view of the memory of the emulator, refreshed every frame.
+///
+/// Bytes that changed are highlighted for a moment, clicking one edits it.
 pub struct MemoryViewer {
     pub open: bool,
+    region: Region,
     memory: Vec<u8>,
+    /// Frames left to highlight each byte
+    changed: Vec<u8>,
     show_ascii: bool,
+
+    /// Offset of the byte being edited and its input
+    editing: Option<(usize, String)>,
+    /// Offset of the byte that was jumped to
+    selected: Option<usize>,
+    jump_input: String,
+    jump_error: Option<String>,
+    scroll_to_row: Option<usize>,
 }
 
 impl MemoryViewer {
     pub fn new() -> Self {
         Self {
             open: false,
+            region: Region::Bus,
             memory: Vec::new(),
-            show_ascii: false,
-        }
-    }
+            changed: Vec::new(),
+            show_ascii: true,
 
-    pub fn new_with_memory(memory: &[u8], show_ascii: bool) -> Self {
-        Self {
-            open: false,
-            memory: memory.to_vec(),
-            show_ascii,
+            editing: None,
+            selected: None,
+            jump_input: String::new(),
+            jump_error: None,
+            scroll_to_row: None,
         }
     }
 
-    pub fn show(&mut self, ui: &mut Ui) {
-        let mem_chunks: Vec<_> = self.memory.chunks(16).collect();
+    /// Forgets what was shown before, so a new ROM isn't compared with the old one.
+    pub fn reset(&mut self) {
+        self.memory.clear();
+        self.changed.clear();
+        self.editing = None;
+    }
 
-        ui.checkbox(&mut self.show_ascii, "Show ASCII");
+    pub fn show(&mut self, ui: &mut Ui, emulator: &mut Emulator) {
+        self.show_controls(ui, emulator);
         ui.separator();
 
-        ScrollArea::new([false, true]).show_rows(
-            ui,
-            ui.text_style_height(&TextStyle::Monospace),
-            self.memory.len() / 16,
-            |ui, range| {
-                Grid::new("memory viewer").striped(true).show(ui, |ui| {
-                    for line in range {
-                        let chunk = mem_chunks[line];
-
-                        ui.label(
-                            RichText::new(format!("{:#06X}:", line * 16)).color(Color32::GOLD),
-                        );
-
-                        ui.label(
-                            RichText::new(
-                                chunk
-                                    .iter()
-                                    .map(|b| format!("{b:02X?} "))
-                                    .collect::<String>(),
-                            )
-                            .monospace(),
-                        );
-
-                        if self.show_ascii {
-                            ui.horizontal(|ui| {
-                                ui.add(Separator::default().vertical().spacing(3.0));
-
-                                ui.label(
-                                    RichText::new(
-                                        chunk
-                                            .iter()
-                                            .map(|c| {
-                                                if (32..=127).contains(c) {
-                                                    *c as char
-                                                } else {
-                                                    '.'
-                                                }
-                                            })
-                                            .collect::<String>(),
-                                    )
-                                    .monospace(),
-                                );
-                            });
-                        }
+        self.refresh(emulator);
+
+        let row_height = ui.text_style_height(&TextStyle::Monospace);
+        let mut scroll_area = ScrollArea::new([false, true]).auto_shrink([false, false]);
+        if let Some(row) = self.scroll_to_row.take() {
+            let offset = row as f32 * (row_height + ui.spacing().item_spacing.y);
+            scroll_area = scroll_area.vertical_scroll_offset(offset);
+        }
+
+        let rows = self.memory.len().div_ceil(16);
+        scroll_area.show_rows(ui, row_height, rows, |ui, range| {
+            Grid::new("memory viewer").striped(true).show(ui, |ui| {
+                for line in range {
+                    self.show_line(ui, emulator, line);
+                    ui.end_row();
+                }
+            });
+        });
+    }
+
+    fn show_controls(&mut self, ui: &mut Ui, emulator: &mut Emulator) {
+        let sram_banks = emulator.bus.cartridge.sram().len().div_ceil(0x2000);
+        let regions = [Region::Bus, Region::Rom]
+            .into_iter()
+            .chain((0..2).map(Region::Vram))
+            .chain((0..8).map(Region::Wram))
+            .chain((0..sram_banks).map(Region::Sram))
+            .chain([Region::Oam, Region::Hram]);
 
-                        ui.end_row();
+        ui.horizontal(|ui| {
+            let previous = self.region;
+            ComboBox::from_id_source("memory region")
+                .selected_text(self.region.name())
+                .show_ui(ui, |ui| {
+                    for region in regions {
+                        ui.selectable_value(&mut self.region, region, region.name());
                     }
                 });
-            },
-        );
+
+            if self.region != previous {
+                self.reset();
+                self.selected = None;
+                self.scroll_to_row = Some(0);
+            }
+
+            ui.separator();
+
+            let response = ui.add(
+                TextEdit::singleline(&mut self.jump_input)
+                    .desired_width(60.0)
+                    .hint_text("Address"),
+            );
+            let entered = response.lost_focus() && ui.input(|i| i.key_pressed(Key::Enter));
+            if ui.button("Go").clicked() || entered {
+                self.jump();
+            }
+
+            ui.separator();
+            ui.checkbox(&mut self.show_ascii, "Show ASCII");
+        });
+
+        if let Some(error) = &self.jump_error {
+            ui.colored_label(Color32::RED, error);
+        }
+    }
+
+    /// Scrolls to the address in the input, on the bus or in the bank that is shown.
+    fn jump(&mut self) {
+        let input = self.jump_input.trim().trim_start_matches('$');
+        let start = self.region.start();
+
+        let offset = usize::from_str_radix(input, 16)
+            .ok()
+            .and_then(|address| address.checked_sub(start))
+            .filter(|&offset| offset < self.memory.len());
+
+        match offset {
+            Some(offset) => {
+                self.selected = Some(offset);
+                self.scroll_to_row = Some(offset / 16);
+                self.jump_error = None;
+            }
+            None => {
+                let end = start + self.memory.len().saturating_sub(1);
+                self.jump_error = Some(format!(
+                    "\"{input}\" is not an address in {start:04X}-{end:04X}"
+                ));
+            }
+        }
+    }
+
+    /// Reads the region again and highlights what changed since the last frame.
+    fn refresh(&mut self, emulator: &Emulator) {
+        // The ROM only changes when it is patched, copying it every frame isn't worth it
+        if self.region == Region::Rom && !self.memory.is_empty() {
+            return;
+        }
+
+        let memory = self.region.read(emulator);
+        if memory.len() == self.memory.len() {
+            for ((old, new), changed) in self.memory.iter().zip(&memory).zip(&mut self.changed) {
+                *changed = if old != new { HIGHLIGHT_FRAMES } else { changed.saturating_sub(1) };
+            }
+        } else {
+            self.changed = vec![0; memory.len()];
+        }
+
+        self.memory = memory;
+    }
+
+    fn show_line(&mut self, ui: &mut Ui, emulator: &mut Emulator, line: usize) {
+        let start = line * 16;
+        let end = (start + 16).min(self.memory.len());
+
+        let address = self.region.start() + start;
+        let label = match self.region.bank() {
+            Some(bank) => format!("{bank:X}:{address:04X}"),
+            None if self.region == Region::Rom => format!("{address:06X}"),
+            None => format!("{address:04X}"),
+        };
+        ui.label(RichText::new(label).monospace().color(Color32::GOLD));
+
+        ui.horizontal(|ui| {
+            ui.spacing_mut().item_spacing.x = 4.0;
+
+            for offset in start..end {
+                if let Some((_, input)) = self.editing.as_mut().filter(|(o, _)| *o == offset) {
+                    let response = ui.add(
+                        TextEdit::singleline(input)
+                            .desired_width(16.0)
+                            .char_limit(2)
+                            .font(TextStyle::Monospace),
+                    );
+                    response.request_focus();
+
+                    if response.lost_focus() {
+                        let value = u8::from_str_radix(input.trim(), 16).ok();
+                        if ui.input(|i| i.key_pressed(Key::Enter)) {
+                            if let Some(value) = value {
+                                self.region.write(emulator, offset, value);
+                                self.memory[offset] = value;
+                            }
+                        }
+                        self.editing = None;
+                    }
+                    continue;
+                }
+
+                let mut text = RichText::new(format!("{:02X}", self.memory[offset])).monospace();
+                if self.changed[offset] > 0 {
+                    text = text.color(Color32::LIGHT_RED);
+                }
+                if self.selected == Some(offset) {
+                    text = text.background_color(Color32::DARK_BLUE);
+                }
+
+                let response = ui.add(Label::new(text).sense(Sense::click()));
+                if response.clicked() {
+                    self.editing = Some((offset, format!("{:02X}", self.memory[offset])));
+                    self.selected = Some(offset);
+                }
+            }
+        });
+
+        if self.show_ascii {
+            ui.horizontal(|ui| {
+                ui.add(Separator::default().vertical().spacing(3.0));
+
+                let ascii: String = self.memory[start..end]
+                    .iter()
+                    .map(|c| if (32..=127).contains(c) { *c as char } else { '.' })
+                    .collect();
+                ui.label(RichText::new(ascii).monospace());
+            });
+        }
     }
 }
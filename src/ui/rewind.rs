@@ -0,0 +1,216 @@
+use std::collections::VecDeque;
+
+use eframe::CreationContext;
+
+/// Frames per second the Game Boy runs at (roughly).
+const FRAMES_PER_SECOND: usize = 60;
+
+/// A snapshot stored as the XOR difference to the next newer one,
+/// with runs of zeroes (= unchanged bytes) run-length encoded.
+struct Delta {
+    len: usize,
+    data: Vec<u8>,
+}
+
+/// Ring buffer of per-frame save states used for rewinding.
+///
+/// Only the newest state is kept as is, every older state is stored
+/// as a compressed delta to its successor. Stepping back undoes one delta,
+/// dropping the oldest frame never invalidates the remaining ones.
+pub struct Rewind {
+    pub enabled: bool,
+    /// History length in seconds
+    pub seconds: usize,
+
+    newest: Option<Vec<u8>>,
+    deltas: VecDeque<Delta>,
+}
+
+impl Default for Rewind {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            seconds: 10,
+
+            newest: None,
+            deltas: VecDeque::new(),
+        }
+    }
+}
+
+impl Rewind {
+    pub fn new(cc: &CreationContext) -> Self {
+        let (enabled, seconds) = cc
+            .storage
+            .and_then(|storage| eframe::get_value::<(bool, usize)>(storage, "rewind"))
+            .unwrap_or((true, 10));
+
+        Self {
+            enabled,
+            seconds,
+            ..Default::default()
+        }
+    }
+
+    /// Records the state of the frame that was just emulated.
+    pub fn push(&mut self, state: Vec<u8>) {
+        if let Some(newest) = self.newest.take() {
+            self.deltas.push_back(Delta::new(&newest, &state));
+        }
+        self.newest = Some(state);
+
+        while self.deltas.len() > self.seconds * FRAMES_PER_SECOND {
+            self.deltas.pop_front();
+        }
+    }
+
+    /// Steps back one frame and returns the state of the previous frame.
+    ///
+    /// Returns `None` once the start of the history is reached.
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let delta = self.deltas.pop_back()?;
+        let previous = delta.apply(self.newest.as_ref()?);
+
+        self.newest = Some(previous.clone());
+        Some(previous)
+    }
+
+    pub fn clear(&mut self) {
+        self.newest = None;
+        self.deltas.clear();
+    }
+}
+
+impl Delta {
+    fn new(old: &[u8], new: &[u8]) -> Self {
+        let mut data = Vec::new();
+        let mut zeroes = 0u8;
+
+        for i in 0..old.len().max(new.len()) {
+            let byte = old.get(i).unwrap_or(&0) ^ new.get(i).unwrap_or(&0);
+
+            if byte == 0 {
+                zeroes += 1;
+                if zeroes == u8::MAX {
+                    data.extend([0, zeroes]);
+                    zeroes = 0;
+                }
+            } else {
+                if zeroes > 0 {
+                    data.extend([0, zeroes]);
+                    zeroes = 0;
+                }
+                data.push(byte);
+            }
+        }
+
+        if zeroes > 0 {
+            data.extend([0, zeroes]);
+        }
+
+        Self {
+            len: old.len(),
+            data,
+        }
+    }
+
+    /// Recreates the older state from its successor.
+    fn apply(&self, new: &[u8]) -> Vec<u8> {
+        let mut xor = Vec::with_capacity(self.len.max(new.len()));
+        let mut bytes = self.data.iter();
+
+        while let Some(&byte) = bytes.next() {
+            if byte == 0 {
+                let zeroes = *bytes.next().unwrap();
+                xor.resize(xor.len() + zeroes as usize, 0);
+            } else {
+                xor.push(byte);
+            }
+        }
+
+        xor.iter()
+            .enumerate()
+            .take(self.len)
+            .map(|(i, byte)| byte ^ new.get(i).unwrap_or(&0))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Delta, Rewind, FRAMES_PER_SECOND};
+
+    fn round_trip(old: &[u8], new: &[u8]) {
+        assert_eq!(Delta::new(old, new).apply(new), old);
+    }
+
+    #[test]
+    fn delta_unchanged() {
+        let state = vec![0x42; 1000];
+        let delta = Delta::new(&state, &state);
+
+        // 1000 zeroes are 3 full runs of 255 and one of 235
+        assert_eq!(delta.data, [0, 255, 0, 255, 0, 255, 0, 235]);
+        round_trip(&state, &state);
+    }
+
+    #[test]
+    fn delta_fully_changed() {
+        let old: Vec<u8> = (0..=255).collect();
+        let new: Vec<u8> = old.iter().map(|b| !b).collect();
+
+        assert_eq!(Delta::new(&old, &new).data.len(), old.len());
+        round_trip(&old, &new);
+    }
+
+    #[test]
+    fn delta_mixed_and_different_lengths() {
+        let old: Vec<u8> = (0..600).map(|i| (i / 7) as u8).collect();
+        let mut new = old.clone();
+        new[0] = 0xFF;
+        new[300..310].fill(0);
+        new[599] ^= 1;
+
+        round_trip(&old, &new);
+        // States can grow or shrink between frames, e.g. after loading another game
+        round_trip(&old, &new[..400]);
+        round_trip(&old[..400], &new);
+        round_trip(&[], &new);
+        round_trip(&old, &[]);
+    }
+
+    #[test]
+    fn history_steps_back_in_order() {
+        let mut rewind = Rewind::default();
+        for frame in 0..5u8 {
+            rewind.push(vec![frame; 16]);
+        }
+
+        for frame in (0..4).rev() {
+            assert_eq!(rewind.pop(), Some(vec![frame; 16]));
+        }
+        assert_eq!(rewind.pop(), None);
+    }
+
+    #[test]
+    fn capacity_evicts_oldest_frame() {
+        let mut rewind = Rewind {
+            seconds: 1,
+            ..Default::default()
+        };
+
+        let frames = FRAMES_PER_SECOND + 2;
+        for frame in 0..frames {
+            rewind.push((frame as u16).to_le_bytes().repeat(8));
+        }
+        assert_eq!(rewind.deltas.len(), FRAMES_PER_SECOND);
+
+        let mut oldest = None;
+        while let Some(state) = rewind.pop() {
+            oldest = Some(state);
+        }
+
+        // Frame 0 was dropped, frame 1 is as far back as it goes
+        assert_eq!(oldest, Some(1u16.to_le_bytes().repeat(8)));
+    }
+}
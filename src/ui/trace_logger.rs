@@ -0,0 +1,139 @@
+use egui::{Color32, Grid, TextEdit, Ui};
+
+use crate::{
+    cpu::debug::{
+        debugger::parse_hex,
+        trace::{TraceFilter, Tracer},
+        watchpoint::parse_range,
+    },
+    emulator::Emulator,
+};
+
+/// Starts and stops the trace log of the emulator, with its filters.
+pub struct TraceLogger {
+    bank_input: String,
+    pc_input: String,
+    frames_input: String,
+    input_error: Option<String>,
+}
+
+impl TraceLogger {
+    pub fn new() -> Self {
+        Self {
+            bank_input: String::new(),
+            pc_input: String::new(),
+            frames_input: String::new(),
+            input_error: None,
+        }
+    }
+
+    pub fn show(&mut self, ui: &mut Ui, emulator: &mut Emulator) {
+        if let Some(tracer) = &emulator.tracer {
+            let state = if tracer.is_finished() { "Finished" } else { "Tracing" };
+            ui.label(format!("{state}, {} lines written", tracer.lines()));
+
+            if ui.button("Stop trace").clicked() {
+                if let Err(e) = emulator.tracer.take().unwrap().finish() {
+                    rfd::MessageDialog::new()
+                        .set_title("Writing the trace failed!")
+                        .set_description(&e.to_string())
+                        .show();
+                }
+                ui.close_menu();
+            }
+
+            return;
+        }
+
+        Grid::new("trace_filter").num_columns(2).show(ui, |ui| {
+            ui.label("ROM bank");
+            ui.add(
+                TextEdit::singleline(&mut self.bank_input)
+                    .desired_width(80.0)
+                    .hint_text("any"),
+            )
+            .on_hover_text("Only code running from this bank, in hexadecimal");
+            ui.end_row();
+
+            ui.label("PC");
+            ui.add(
+                TextEdit::singleline(&mut self.pc_input)
+                    .desired_width(80.0)
+                    .hint_text("0150-3FFF"),
+            );
+            ui.end_row();
+
+            ui.label("Frames");
+            ui.add(
+                TextEdit::singleline(&mut self.frames_input)
+                    .desired_width(80.0)
+                    .hint_text("all"),
+            )
+            .on_hover_text("Stops the trace after this many frames");
+            ui.end_row();
+        });
+
+        if let Some(error) = &self.input_error {
+            ui.colored_label(Color32::RED, error);
+        }
+
+        if ui.button("Start trace . . .").clicked() {
+            match self.filter() {
+                Ok(filter) => {
+                    self.input_error = None;
+                    self.start(emulator, filter);
+                    ui.close_menu();
+                }
+                Err(e) => self.input_error = Some(e),
+            }
+        }
+    }
+
+    fn start(&mut self, emulator: &mut Emulator, filter: TraceFilter) {
+        let file = rfd::FileDialog::new()
+            .add_filter("Trace log", &["log", "txt"])
+            .save_file();
+
+        if let Some(path) = file {
+            match Tracer::create(&path, filter) {
+                Ok(tracer) => emulator.tracer = Some(tracer),
+                Err(e) => {
+                    rfd::MessageDialog::new()
+                        .set_title("Creating the trace file failed!")
+                        .set_description(&e.to_string())
+                        .show();
+                }
+            }
+        }
+    }
+
+    /// Parses the inputs, empty ones don't filter anything.
+    fn filter(&self) -> Result<TraceFilter, String> {
+        let input = |input: &str| Some(input.trim().to_string()).filter(|s| !s.is_empty());
+
+        let bank = input(&self.bank_input)
+            .map(|bank| {
+                parse_hex(&bank)
+                    .map(usize::from)
+                    .ok_or_else(|| format!("\"{bank}\" is not a hexadecimal bank"))
+            })
+            .transpose()?;
+
+        let pc = input(&self.pc_input)
+            .map(|pc| {
+                parse_range(&pc)
+                    .ok_or_else(|| format!("\"{pc}\" is not a hexadecimal address or range"))
+            })
+            .transpose()?;
+
+        let frames = input(&self.frames_input)
+            .map(|frames| {
+                frames
+                    .parse()
+                    .map_err(|_| format!("\"{frames}\" is not a number of frames"))
+            })
+            .transpose()?;
+
+        Ok(TraceFilter { bank, pc, frames })
+    }
+}
@@ -1,8 +1,11 @@
 use std::{
     fs::{self, File},
     io::Write,
-    path::PathBuf,
-    sync::atomic::Ordering,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::Ordering,
+        mpsc::{self, Receiver},
+    },
 };
 
 use eframe::{
@@ -13,32 +16,64 @@ use eframe::{
     epaint::{Color32, ColorImage},
     App, CreationContext, Frame, Storage,
 };
-use egui::{Grid, Rgba, ScrollArea, SelectableLabel, SidePanel, TextureHandle, Vec2};
+use egui::{Grid, Rgba, ScrollArea, SelectableLabel, SidePanel, Slider, TextureHandle, Vec2};
 use egui_extras::RetainedImage;
+use gilrs::Axis;
 use hashlink::LinkedHashSet;
+use image::{GrayImage, ImageResult};
 
 use crate::{
-    cpu::registers::Flag,
+    apu::audio_output::{AudioOutput, NullOutput, RodioOutput},
+    cartridge::camera_source::{FrameSequence, ImageSource, StillImage, TestPattern},
+    cpu::{
+        debug::{disassembler::Disassembly, symbols::Symbols},
+        registers::Flag,
+    },
     emulator::Emulator,
+    input::rumble::Rumble,
+    mmu::{
+        link_cable::LinkCable,
+        printer::Printer,
+        serial_device::{Disconnected, Loopback, StdoutLogger},
+    },
     ppu::{
         color_palette::{Chocolate, Green, Monochrome, ScreenColor, COLOR_CORRECTION},
+        compat_palettes::BUTTON_COMBINATIONS,
         LCD_HEIGHT, LCD_WIDTH,
     },
 };
 
 use self::{
     control_panel::ControlPanel,
+    cpu_debugger::CpuDebugger,
     frame_history::FrameHistory,
+    gdb_server::GdbServer,
+    link_settings::LinkSettings,
     memory_viewer::MemoryViewer,
     palette_picker::{Palette, PalettePicker},
+    rewind::Rewind,
     sound_settings::SoundSettings,
+    trace_logger::TraceLogger,
+    vram_viewer::VramViewer,
 };
 
 pub mod control_panel;
+pub mod cpu_debugger;
 pub mod frame_history;
+pub mod gdb_server;
+pub mod link_settings;
 pub mod memory_viewer;
 pub mod palette_picker;
+pub mod rewind;
 pub mod sound_settings;
+pub mod trace_logger;
+pub mod vram_viewer;
+
+/// Hold to step backwards through the rewind history.
+const REWIND_KEY: Key = Key::Backspace;
+
+/// Keys for the numbered save state slots, `Shift` saves and the plain key loads.
+const STATE_SLOT_KEYS: [Key; 4] = [Key::F1, Key::F2, Key::F3, Key::F4];
 
 /// Shortcut for adding phosphor icons infront of text.
 macro_rules! icon_text {
@@ -58,12 +93,28 @@ pub struct Kevboy {
     frame_buffer: Vec<Color32>,
 
     mem_viewer: MemoryViewer,
+    cpu_debugger: CpuDebugger,
+    vram_viewer: VramViewer,
+    trace_logger: TraceLogger,
+    gdb_server: GdbServer,
     control_panel: ControlPanel,
     palette_picker: PalettePicker,
     sound_settings: SoundSettings,
+    link_settings: LinkSettings,
+    rewind: Rewind,
+    rumble: Rumble,
     github_img: RetainedImage,
 
     recent_roms: LinkedHashSet<PathBuf>,
+    rom_path: Option<PathBuf>,
+    /// Pictures for the Pocket Camera, a test pattern is used if empty
+    camera_images: Vec<PathBuf>,
+    /// DMG and CGB boot ROM files, run before the game if set
+    boot_roms: (Option<PathBuf>, Option<PathBuf>),
+    /// Labels for the disassembler, `<rom>.sym` is loaded with the ROM
+    symbols: Symbols,
+    /// Pictures of the Game Boy Printer, if plugged in
+    prints: Option<Receiver<GrayImage>>,
     is_vram_window_open: bool,
 
     playback_button_width: f32,
@@ -83,17 +134,30 @@ impl Kevboy {
     /// `CreationContext` is needed for its `storage`, so that we can
     /// store some local settings like controls, colors, etc.
     pub fn new(cc: &CreationContext) -> Self {
-        Self {
-            emulator: Emulator::new(),
+        // Keep running without sound if there is no audio device
+        let audio_output: Box<dyn AudioOutput> = match RodioOutput::new() {
+            Some(rodio) => Box::new(rodio),
+            None => Box::new(NullOutput),
+        };
+
+        let mut kevboy = Self {
+            emulator: Emulator::with_audio_output(audio_output),
             history: FrameHistory::default(),
 
             texture: None,
             frame_buffer: [Green::WHITE].repeat(LCD_WIDTH * LCD_HEIGHT),
 
             mem_viewer: MemoryViewer::new(),
+            cpu_debugger: CpuDebugger::new(),
+            vram_viewer: VramViewer::new(),
+            trace_logger: TraceLogger::new(),
+            gdb_server: GdbServer::new(cc),
             control_panel: ControlPanel::new(cc),
             palette_picker: PalettePicker::new(cc),
             sound_settings: SoundSettings::new(cc),
+            link_settings: LinkSettings::new(cc),
+            rewind: Rewind::new(cc),
+            rumble: Rumble::default(),
             github_img: RetainedImage::from_svg_bytes(
                 "gh",
                 include_bytes!("../../icon/github-mark-white.svg"),
@@ -101,6 +165,11 @@ impl Kevboy {
             .unwrap(),
 
             recent_roms: eframe::get_value(cc.storage.unwrap(), "recent_roms").unwrap_or_default(),
+            rom_path: None,
+            camera_images: Vec::new(),
+            boot_roms: (None, None),
+            symbols: Symbols::default(),
+            prints: None,
             is_vram_window_open: false,
 
             playback_button_width: 0.0,
@@ -111,16 +180,26 @@ impl Kevboy {
             integer_scaling: (false, 0),
             blend: false,
             color_correction: false,
+        };
+
+        let (dmg, cgb): (Option<PathBuf>, Option<PathBuf>) =
+            eframe::get_value(cc.storage.unwrap(), "boot_roms").unwrap_or_default();
+        for path in [dmg, cgb].iter().flatten() {
+            kevboy.load_boot_rom(path);
         }
+
+        let (colorize, palette): (bool, Option<usize>) =
+            eframe::get_value(cc.storage.unwrap(), "dmg_colorization").unwrap_or_default();
+        kevboy.emulator.colorize_dmg = colorize;
+        kevboy.emulator.set_dmg_palette(palette);
+
+        kevboy
     }
 
     /// For starting the emulator from the command line
-    pub fn with_rom(rom: &[u8], cc: &CreationContext) -> Self {
-        let mut emulator = Emulator::new();
+    pub fn with_rom(rom_path: PathBuf, cc: &CreationContext) -> Self {
         let mut kevboy = Self::new(cc);
-
-        emulator.load_rom(rom);
-        kevboy.emulator = emulator;
+        kevboy.open_rom(&rom_path);
 
         kevboy
     }
@@ -130,16 +209,38 @@ impl App for Kevboy {
     /// Called on shutdown and regular intervals, uses local filesystem or local storage (web)
     ///
     /// We save colors, controls and recently opened ROMs.
+    /// Battery backed cartridge RAM is written back to the `.sav` next to the ROM.
     fn save(&mut self, _storage: &mut dyn Storage) {
+        self.store_battery_save();
+
         eframe::set_value(_storage, "colors", &self.palette_picker.colors);
         eframe::set_value(_storage, "dir_controls", &self.control_panel.direction_keys);
         eframe::set_value(_storage, "action_controls", &self.control_panel.action_keys);
         eframe::set_value(_storage, "recent_roms", &self.recent_roms);
+        eframe::set_value(_storage, "boot_roms", &self.boot_roms);
+        eframe::set_value(
+            _storage,
+            "dmg_colorization",
+            &(self.emulator.colorize_dmg, self.emulator.dmg_palette()),
+        );
+        eframe::set_value(_storage, "link_address", &self.link_settings.address);
+        eframe::set_value(_storage, "gdb_port", &self.gdb_server.port);
+        eframe::set_value(
+            _storage,
+            "rewind",
+            &(self.rewind.enabled, self.rewind.seconds),
+        );
     }
 
     /// UI declarations and functionality, called every frame and also runs the emulator
     fn update(&mut self, ctx: &Context, frame: &mut Frame) {
         self.history.update(ctx, frame);
+        self.link_settings.poll(&mut self.emulator.bus.serial);
+        self.gdb_server.poll(
+            &mut self.emulator,
+            &mut self.cpu_debugger.debugger,
+            &mut self.pause,
+        );
 
         if let Some(tex) = &mut self.texture {
             tex.set(
@@ -158,29 +259,33 @@ impl App for Kevboy {
         }
 
         // Load rom file when dropped on top of the GUI
-        ctx.input(|c| {
-            let dropped_files = &c.raw.dropped_files;
-            let first_rom = dropped_files
+        let dropped_rom = ctx.input(|c| {
+            c.raw
+                .dropped_files
                 .iter()
-                .find(|file| {
-                    file.path.as_ref().is_some_and(|p| {
-                        let extension = p.extension().unwrap();
-                        extension == "gb" || extension == "gbc"
-                    })
-                })
-                .cloned();
-
-            if let Some(file) = first_rom {
-                let rom = fs::read(file.path.as_ref().unwrap()).unwrap();
+                .filter_map(|file| file.path.clone())
+                .find(|p| p.extension().is_some_and(|e| e == "gb" || e == "gbc"))
+        });
 
+        if let Some(path) = dropped_rom {
+            if self.open_rom(&path) {
                 frame.set_window_title(&format!(
                     "Kevboy - {:#?}",
-                    file.path.unwrap().file_name().unwrap().to_str().unwrap()
+                    path.file_name().unwrap().to_str().unwrap()
                 ));
-                self.emulator.load_rom(&rom);
-                self.mem_viewer = MemoryViewer::new_with_memory(&rom, true);
             }
-        });
+        }
+
+        // Numbered save state slots, Shift + F-key saves and the F-key alone loads
+        if !self.emulator.rom.is_empty() {
+            for (slot, key) in STATE_SLOT_KEYS.iter().enumerate() {
+                if ctx.input_mut(|i| i.consume_key(Modifiers::SHIFT, *key)) {
+                    self.save_state_slot(slot + 1);
+                } else if ctx.input_mut(|i| i.consume_key(Modifiers::NONE, *key)) {
+                    self.load_state_slot(slot + 1);
+                }
+            }
+        }
 
         // ----------------------------------
         //      Start of UI declarations
@@ -197,8 +302,7 @@ impl App for Kevboy {
                             .add_filter("Game Boy ROM", &["gb", "bin", "gbc"])
                             .pick_file();
 
-                        if let Some(path) = file {
-                            let rom = fs::read(path.clone()).expect("ROM wasn't loaded correctly!");
+                        if let Some(path) = file.filter(|p| self.open_rom(p)) {
                             // Limit recent roms list to 10 (gets too cluttered otherwise)
                             if self.recent_roms.insert(path.clone()) && self.recent_roms.len() >= 10  {
                                 self.recent_roms.pop_front();
@@ -210,8 +314,6 @@ impl App for Kevboy {
                             }
 
                             frame.set_window_title(&format!("Kevboy - {:#?}", path.file_name().unwrap().to_str().unwrap()));
-                            self.emulator.load_rom(&rom);
-                            self.mem_viewer = MemoryViewer::new_with_memory(&rom, true);
                         }
 
                         ui.close_menu();
@@ -223,12 +325,10 @@ impl App for Kevboy {
                     ui.menu_button(icon_text!(FILES, "Open recent ROMs"), |ui| {
                         for rom_path in self.recent_roms.clone().iter().rev() {
                             if ui.button(rom_path.file_name().unwrap().to_str().unwrap()).clicked() {
-                                let rom = fs::read(rom_path).expect("ROM wasn't loaded correctly!");
-                                self.recent_roms.to_back(rom_path);
-
-                                frame.set_window_title(&format!("Kevboy - {:#?}", rom_path.file_name().unwrap().to_str().unwrap()));
-                                self.emulator.load_rom(&rom);
-                                self.mem_viewer = MemoryViewer::new_with_memory(&rom, true);
+                                if self.open_rom(rom_path) {
+                                    self.recent_roms.to_back(rom_path);
+                                    frame.set_window_title(&format!("Kevboy - {:#?}", rom_path.file_name().unwrap().to_str().unwrap()));
+                                }
 
                                 ui.close_menu();
                             }
@@ -257,11 +357,24 @@ impl App for Kevboy {
                             .pick_file();
 
                         if let Some(path) = file {
-                            let save_file = fs::read(path).expect("Save file wasn't loaded correctly!");
-
-                            // restart ROM so that the save can be applied before it's too late
-                            self.emulator.load_rom(&self.emulator.rom.clone());
-                            self.emulator.bus.cartridge.load_sram(&save_file);
+                            match fs::read(path) {
+                                Ok(save_file) => {
+                                    // restart ROM so that the save can be applied before it's too late
+                                    self.emulator
+                                        .load_rom(&self.emulator.rom.clone(), self.rom_path.as_deref())
+                                        .expect("Reloading the running ROM failed!");
+                                    self.apply_camera_source();
+                                    self.emulator.bus.cartridge.load_sram(&save_file);
+                                    // Rewinding would bring back the RAM from before the import
+                                    self.rewind.clear();
+                                }
+                                Err(e) => {
+                                    rfd::MessageDialog::new()
+                                        .set_title("Loading the save file failed!")
+                                        .set_description(&e.to_string())
+                                        .show();
+                                }
+                            }
                         }
                     }
 
@@ -293,6 +406,63 @@ impl App for Kevboy {
                             }
                         }
                     }
+
+                    ui.separator();
+
+                    // Save states capture the whole machine. Numbered slots are stored next to the ROM,
+                    // "Export"/"Import" go through a file dialog instead.
+                    ui.add_enabled_ui(!self.emulator.rom.is_empty(), |ui| {
+                        ui.menu_button(icon_text!(FLOPPY_DISK, "Save State"), |ui| {
+                            for (slot, key) in STATE_SLOT_KEYS.iter().enumerate() {
+                                if ui
+                                    .add(Button::new(format!("Slot {}", slot + 1)).shortcut_text(
+                                        ctx.format_shortcut(&KeyboardShortcut::new(Modifiers::SHIFT, *key)),
+                                    ))
+                                    .clicked()
+                                {
+                                    self.save_state_slot(slot + 1);
+                                    ui.close_menu();
+                                }
+                            }
+
+                            ui.separator();
+                            if ui.button("Export . . .").clicked() {
+                                let file = rfd::FileDialog::new().add_filter("Save state", &["state"]).save_file();
+                                if let Some(path) = file {
+                                    self.save_state_to(&path);
+                                }
+
+                                ui.close_menu();
+                            }
+                        });
+
+                        ui.menu_button(icon_text!(CLOCK_COUNTER_CLOCKWISE, "Load State"), |ui| {
+                            for (slot, key) in STATE_SLOT_KEYS.iter().enumerate() {
+                                let exists = self.state_slot_path(slot + 1).is_some_and(|p| p.exists());
+                                if ui
+                                    .add_enabled(
+                                        exists,
+                                        Button::new(format!("Slot {}", slot + 1))
+                                            .shortcut_text(ctx.format_shortcut(&KeyboardShortcut::new(Modifiers::NONE, *key))),
+                                    )
+                                    .clicked()
+                                {
+                                    self.load_state_slot(slot + 1);
+                                    ui.close_menu();
+                                }
+                            }
+
+                            ui.separator();
+                            if ui.button("Import . . .").clicked() {
+                                let file = rfd::FileDialog::new().add_filter("Save state", &["state"]).pick_file();
+                                if let Some(path) = file {
+                                    self.load_state_from(&path);
+                                }
+
+                                ui.close_menu();
+                            }
+                        });
+                    });
                 });
 
                 // Options for changing controls and color palettes.
@@ -313,6 +483,24 @@ impl App for Kevboy {
                         }
                     });
 
+                    // Colors a CGB gives DMG games, by title like its boot ROM or picked by hand
+                    ui.menu_button(icon_text!(PAINT_BUCKET, "Colorize DMG games"), |ui| {
+                        ui.checkbox(&mut self.emulator.colorize_dmg, "Run on a CGB").on_hover_text("Takes effect when the next game is loaded");
+                        ui.separator();
+
+                        let palette = self.emulator.dmg_palette();
+                        if ui.radio(palette.is_none(), "By title").clicked() {
+                            self.emulator.set_dmg_palette(None);
+                            ui.close_menu();
+                        }
+                        for (buttons, combination) in BUTTON_COMBINATIONS {
+                            if ui.radio(palette == Some(combination), buttons).clicked() {
+                                self.emulator.set_dmg_palette(Some(combination));
+                                ui.close_menu();
+                            }
+                        }
+                    });
+
                     ui.menu_button(icon_text!(MAGNIFYING_GLASS_PLUS, "Scaling"), |ui| {
                         let (force, scale) = &mut self.integer_scaling;
 
@@ -328,6 +516,94 @@ impl App for Kevboy {
                         });
                     });
 
+                    ui.menu_button(icon_text!(REWIND, "Rewind"), |ui| {
+                        ui.checkbox(&mut self.rewind.enabled, "Enable rewind")
+                            .on_hover_text(format!("Hold {} to rewind", REWIND_KEY.name()));
+                        ui.add_enabled(self.rewind.enabled, Slider::new(&mut self.rewind.seconds, 1..=60).suffix(" s"))
+                            .on_hover_text("How far back the rewind history goes");
+
+                        if !self.rewind.enabled {
+                            self.rewind.clear();
+                        }
+                    });
+
+                    // Picture source for the Game Boy Camera, one image is shown as is,
+                    // several are cycled through with every picture the game takes.
+                    ui.menu_button(icon_text!(CAMERA, "Camera"), |ui| {
+                        if ui.radio(self.camera_images.is_empty(), "Test pattern").clicked() {
+                            self.camera_images.clear();
+                            self.apply_camera_source();
+                            ui.close_menu();
+                        }
+                        if ui.radio(self.camera_images.len() == 1, "Image . . .").clicked() {
+                            let file = rfd::FileDialog::new().add_filter("Image", &["png", "jpg", "jpeg", "bmp"]).pick_file();
+                            if let Some(path) = file {
+                                self.camera_images = vec![path];
+                                self.apply_camera_source();
+                            }
+                            ui.close_menu();
+                        }
+                        if ui.radio(self.camera_images.len() > 1, "Image sequence . . .").clicked() {
+                            let files = rfd::FileDialog::new().add_filter("Image", &["png", "jpg", "jpeg", "bmp"]).pick_files();
+                            if let Some(mut paths) = files {
+                                paths.sort();
+                                self.camera_images = paths;
+                                self.apply_camera_source();
+                            }
+                            ui.close_menu();
+                        }
+                    });
+
+                    // Peripheral plugged into the serial port, the link cable has its own window
+                    ui.menu_button(icon_text!(PLUGS, "Link port"), |ui| {
+                        let device = self.emulator.bus.serial.device().name().to_string();
+
+                        if ui.radio(device == Disconnected::NAME, "Nothing").clicked() {
+                            self.emulator.bus.serial.replace_device(Box::new(Disconnected));
+                            ui.close_menu();
+                        }
+                        if ui.radio(device == Printer::NAME, Printer::NAME).on_hover_text("Prints are saved as PNG files next to the ROM").clicked() {
+                            let (sender, receiver) = mpsc::channel();
+                            self.prints = Some(receiver);
+                            self.emulator.bus.serial.replace_device(Box::new(Printer::new(sender)));
+                            ui.close_menu();
+                        }
+                        if ui.radio(device == Loopback::NAME, Loopback::NAME).clicked() {
+                            self.emulator.bus.serial.replace_device(Box::new(Loopback));
+                            ui.close_menu();
+                        }
+                        if ui.radio(device == StdoutLogger::NAME, StdoutLogger::NAME).clicked() {
+                            self.emulator.bus.serial.replace_device(Box::new(StdoutLogger));
+                            ui.close_menu();
+                        }
+                        if ui.radio(device == LinkCable::NAME, "Link cable . . .").clicked() {
+                            self.link_settings.open = true;
+                            ui.close_menu();
+                        }
+                    });
+
+                    // Boot ROMs are picked by size and run before the next game that is loaded
+                    ui.menu_button(icon_text!(POWER, "Boot ROM"), |ui| {
+                        for (model, path) in [("DMG", &self.boot_roms.0), ("CGB", &self.boot_roms.1)] {
+                            let name = path.as_ref().and_then(|p| p.file_name()).map(|n| n.to_string_lossy());
+                            ui.label(format!("{model}: {}", name.unwrap_or("none".into())));
+                        }
+                        ui.separator();
+
+                        if ui.button("Load boot ROM . . .").clicked() {
+                            let file = rfd::FileDialog::new().add_filter("Boot ROM", &["bin", "rom", "gb", "gbc"]).pick_file();
+                            if let Some(path) = file {
+                                self.load_boot_rom(&path);
+                            }
+                            ui.close_menu();
+                        }
+                        if ui.button("Remove boot ROMs").clicked() {
+                            self.boot_roms = (None, None);
+                            self.emulator.remove_boot_roms();
+                            ui.close_menu();
+                        }
+                    });
+
                     ui.separator();
                     ui.toggle_value(&mut self.blend, icon_text!(CARDS, "Frame blending")).on_hover_text("Slow on the web version!");
                     if ui.toggle_value(&mut self.color_correction, icon_text!(PAINT_BRUSH_HOUSEHOLD, "Color correction")).clicked() {
@@ -351,6 +627,31 @@ impl App for Kevboy {
                     if ui.button(icon_text!(FRAME_CORNERS, "Open VRAM viewer")).clicked() {
                         self.is_vram_window_open = !self.is_vram_window_open;
                     }
+                    if ui.button(icon_text!(BUG, "CPU debugger")).clicked() {
+                        self.cpu_debugger.open = !self.cpu_debugger.open;
+                    }
+                    ui.menu_button(icon_text!(FILE_TEXT, "Trace log"), |ui| {
+                        self.trace_logger.show(ui, &mut self.emulator);
+                    });
+                    if ui.button(icon_text!(TERMINAL_WINDOW, "GDB server . . .")).clicked() {
+                        self.gdb_server.open = !self.gdb_server.open;
+                    }
+                    ui.separator();
+
+                    if ui.button(icon_text!(TAG, "Import symbols . . .")).clicked() {
+                        let file = rfd::FileDialog::new().add_filter("Symbol file", &["sym"]).pick_file();
+                        if let Some(path) = file {
+                            self.import_symbols(&path);
+                        }
+                        ui.close_menu();
+                    }
+                    if ui.add_enabled(!self.emulator.rom.is_empty(), Button::new(icon_text!(CODE, "Disassemble ROM . . ."))).clicked() {
+                        let file = rfd::FileDialog::new().add_filter("Assembly", &["asm"]).save_file();
+                        if let Some(path) = file {
+                            self.disassemble_rom(&path);
+                        }
+                        ui.close_menu();
+                    }
                 });
             });
         });
@@ -457,9 +758,9 @@ impl App for Kevboy {
                         It is not the most accurate emulator out there but it fares relatively well thanks to sub-instruction timing \
                         for example.");
 
-                        ui.horizontal(|ui| {
+                        ui.horizontal_wrapped(|ui| {
                             ui.label(RichText::new("Supported MBCs:").strong());
-                            ui.label(RichText::new("MBC0, MBC1, MBC2, MBC3, MBC5").monospace());
+                            ui.label(RichText::new("MBC0, MBC1, MBC2, MBC3, MBC5, MBC6, MBC7, MMM01, HuC1, HuC3, Pocket Camera").monospace());
                         });
 
                         ui.horizontal(|ui| {
@@ -486,6 +787,7 @@ impl App for Kevboy {
                                     .on_hover_text("Stop the emulation and reset the emulator state")
                                     .clicked()
                                 {
+                                    self.store_battery_save();
                                     self.emulator.reset();
                                     self.frame_buffer.fill(Green::WHITE);
                                 }
@@ -494,7 +796,11 @@ impl App for Kevboy {
                                     .on_hover_text("Pause / Resume the emulation")
                                     .clicked()
                                 {
-                                    self.pause = !self.pause;
+                                    if self.pause {
+                                        self.cpu_debugger.resume(&mut self.pause);
+                                    } else {
+                                        self.pause = true;
+                                    }
                                 }
 
                                 if ui.add_sized([25.0, 25.0], SelectableLabel::new(self.fast_forward, RichText::new("⏩").size(15.0)))
@@ -566,6 +872,30 @@ impl App for Kevboy {
             self.sound_settings.open &= sound_settings;
         }
 
+        // Host or join a link cable connection to another instance
+        if self.link_settings.open {
+            let mut link_settings_open = self.link_settings.open;
+            Window::new("🔌 Link cable")
+                .open(&mut link_settings_open)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    self.link_settings.show(ui, &mut self.emulator.bus.serial);
+                });
+            self.link_settings.open &= link_settings_open;
+        }
+
+        // Let GDB attach to debug the running game
+        if self.gdb_server.open {
+            let mut gdb_server_open = self.gdb_server.open;
+            Window::new("🐛 GDB server")
+                .open(&mut gdb_server_open)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    self.gdb_server.show(ui);
+                });
+            self.gdb_server.open &= gdb_server_open;
+        }
+
         // Change and customize the color palette of the Game Boy
         if self.palette_picker.open {
             let mut palette_window_open = self.palette_picker.open;
@@ -583,56 +913,30 @@ impl App for Kevboy {
             Window::new("💾 Memory")
                 .open(&mut mem_viewer_open)
                 .show(ctx, |ui| {
-                    self.mem_viewer.show(ui);
+                    self.mem_viewer.show(ui, &mut self.emulator);
                 });
             self.mem_viewer.open = mem_viewer_open;
         }
 
-        if self.is_vram_window_open {
-            Window::new("🖼 BG Map")
-                .open(&mut self.is_vram_window_open)
-                .show(ctx, |ui| {
-                    self.emulator.bus.ppu.dump_bg_map(&self.emulator.bus.vram);
-
-                    let pixels: Vec<Color32> = self
-                        .emulator
-                        .bus
-                        .ppu
-                        .raw_frame
-                        .iter()
-                        .map(|c| match *c {
-                            ScreenColor::White(_) => self.palette_picker.colors["White"],
-                            ScreenColor::LightGray(_) => self.palette_picker.colors["Light Gray"],
-                            ScreenColor::Gray(_) => self.palette_picker.colors["Gray"],
-                            ScreenColor::Black(_) => self.palette_picker.colors["Black"],
-                            ScreenColor::FullColor(c, _) => c,
-                        })
-                        .collect();
-
-                    let image = RetainedImage::from_color_image(
-                        "vram",
-                        ColorImage {
-                            size: [256, 256],
-                            pixels,
-                        },
-                    )
-                    .with_options(TextureOptions::NEAREST);
-
-                    image.show_size(ui, ui.available_size());
-                });
-
-            Window::new("💾 VRAM:0")
-                .open(&mut self.is_vram_window_open)
+        // Pause, step and set breakpoints, opens by itself when a breakpoint is hit
+        if self.cpu_debugger.open {
+            let mut cpu_debugger_open = self.cpu_debugger.open;
+            Window::new("🐞 Debugger")
+                .open(&mut cpu_debugger_open)
                 .show(ctx, |ui| {
-                    let mut mem = MemoryViewer::new_with_memory(&self.emulator.bus.vram[0], false);
-                    mem.show(ui);
+                    self.cpu_debugger
+                        .show(ui, &mut self.emulator, &mut self.pause);
                 });
+            self.cpu_debugger.open &= cpu_debugger_open;
+        }
 
-            Window::new("💾 VRAM:1")
+        if self.is_vram_window_open {
+            Window::new("🖼 VRAM viewer")
                 .open(&mut self.is_vram_window_open)
                 .show(ctx, |ui| {
-                    let mut mem = MemoryViewer::new_with_memory(&self.emulator.bus.vram[1], false);
-                    mem.show(ui);
+                    let shades = ["White", "Light Gray", "Gray", "Black"]
+                        .map(|shade| self.palette_picker.colors[shade]);
+                    self.vram_viewer.show(ui, &mut self.emulator, shades);
                 });
         }
 
@@ -643,6 +947,8 @@ impl App for Kevboy {
         if !self.emulator.rom.is_empty() && !self.pause {
             self.run(ctx);
             ctx.request_repaint();
+        } else {
+            self.rumble.stop(&mut self.control_panel.gilrs);
         }
     }
 }
@@ -651,30 +957,78 @@ impl App for Kevboy {
 impl Kevboy {
     fn run(&mut self, ctx: &Context) {
         let double_factor = if self.emulator.bus.double_speed { 2 } else { 1 };
-
-        // Poll keyboard and gamepad input once per frame.
-        self.emulator.bus.joypad.tick(
-            ctx,
-            &mut self.emulator.bus.interrupt_handler,
-            &self.control_panel.action_keys,
-            &self.control_panel.direction_keys,
-            &mut self.control_panel.gilrs,
-        );
-
-        while self.emulator.cycle_count < 17_556 * double_factor {
-            for _ in 0..(4 * self.fast_forward as u8) {
-                self.emulator.step();
+        // Backspace in a text field deletes a character instead of rewinding
+        let rewinding = self.rewind.enabled
+            && !ctx.wants_keyboard_input()
+            && ctx.input(|i| i.key_down(REWIND_KEY));
+
+        if rewinding {
+            // Step back one frame instead of emulating one. The restored state contains the
+            // `ui_frame_buffer` that was shown back then and the APU drops its queued samples,
+            // so picture and sound continue from the same point once the key is released.
+            if let Some(state) = self.rewind.pop() {
+                if self.emulator.load_state(&state).is_err() {
+                    self.rewind.clear();
+                }
             }
+        } else {
+            // Poll keyboard and gamepad input once per frame.
+            self.emulator.bus.joypad.tick(
+                ctx,
+                &mut self.emulator.bus.interrupt_handler,
+                &self.control_panel.action_keys,
+                &self.control_panel.direction_keys,
+                &mut self.control_panel.gilrs,
+            );
 
-            self.emulator.cycle_count += self.emulator.step() as u16;
+            // Tilt sensor of MBC7 cartridges, arrow keys or the right stick
+            let (mut tilt_x, mut tilt_y) = ctx.input(|i| {
+                let axis = |neg, pos| i.key_down(pos) as u8 as f32 - i.key_down(neg) as u8 as f32;
+                (
+                    axis(Key::ArrowLeft, Key::ArrowRight),
+                    axis(Key::ArrowUp, Key::ArrowDown),
+                )
+            });
+            for (_, gamepad) in self.control_panel.gilrs.gamepads() {
+                tilt_x += gamepad.value(Axis::RightStickX);
+                tilt_y -= gamepad.value(Axis::RightStickY);
+            }
+            self.emulator
+                .bus
+                .cartridge
+                .set_tilt(tilt_x.clamp(-1.0, 1.0), tilt_y.clamp(-1.0, 1.0));
+
+            // The debugger may pause before or after any instruction, even mid-frame
+            'frame: while self.emulator.cycle_count < 17_556 * double_factor {
+                for _ in 0..(4 * self.fast_forward as u8) {
+                    if self.cpu_debugger.step(&mut self.emulator).1 {
+                        self.pause = true;
+                        break 'frame;
+                    }
+                }
+
+                let (cycles, pause) = self.cpu_debugger.step(&mut self.emulator);
+                self.emulator.cycle_count += cycles as u16;
+                if pause {
+                    self.pause = true;
+                    break;
+                }
+            }
         }
 
         self.emulator
             .bus
             .apu
-            .sink
+            .output
             .set_volume(self.sound_settings.volume / 100.0);
 
+        // Games without a rumble motor keep it stopped
+        let rumble_duty = self.emulator.bus.cartridge.take_rumble_duty();
+        self.rumble
+            .update(&mut self.control_panel.gilrs, rumble_duty.unwrap_or(0.0));
+
+        self.store_prints();
+
         // Normal frame buffer for frontend, gets swapped for double buffering
         let frame_buffer = self
             .emulator
@@ -699,8 +1053,222 @@ impl Kevboy {
             self.frame_buffer = frame_buffer;
         }
 
+        // The frame the debugger paused in is finished once emulation continues
+        if self.pause {
+            return;
+        }
+
         self.emulator.cycle_count = 0;
         self.emulator.bus.joypad.reset_pressed_keys();
+
+        if self.rewind.enabled && !rewinding {
+            self.rewind.push(self.emulator.save_state());
+        }
+    }
+
+    /// Loads the ROM at `path` into the emulator and the memory viewer.
+    ///
+    /// Shows a dialog and keeps the current game running if the file
+    /// can't be read or isn't a valid ROM, returns whether it was loaded.
+    fn open_rom(&mut self, path: &Path) -> bool {
+        self.store_battery_save();
+
+        let result = fs::read(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|rom| Ok(self.emulator.load_rom(&rom, Some(path))?));
+
+        match result {
+            Ok(()) => {
+                self.mem_viewer.reset();
+                self.rewind.clear();
+                self.rom_path = Some(path.to_path_buf());
+                self.symbols = fs::read_to_string(path.with_extension("sym"))
+                    .ok()
+                    .and_then(|symbols| symbols.parse().ok())
+                    .unwrap_or_default();
+                self.apply_camera_source();
+                true
+            }
+            Err(e) => {
+                rfd::MessageDialog::new()
+                    .set_title("Loading the ROM failed!")
+                    .set_description(&e.to_string())
+                    .show();
+                false
+            }
+        }
+    }
+
+    /// Hands the selected pictures to the camera, falls back to the test pattern on error.
+    fn apply_camera_source(&mut self) {
+        if !self.emulator.bus.cartridge.is_camera() {
+            return;
+        }
+
+        let source: ImageResult<Box<dyn ImageSource>> = match self.camera_images.as_slice() {
+            [] => Ok(Box::<TestPattern>::default()),
+            [path] => StillImage::open(path).map(|s| Box::new(s) as _),
+            paths => FrameSequence::open(paths).map(|s| Box::new(s) as _),
+        };
+
+        match source {
+            Ok(source) => self.emulator.bus.cartridge.set_camera_source(source),
+            Err(e) => {
+                self.camera_images.clear();
+                self.emulator
+                    .bus
+                    .cartridge
+                    .set_camera_source(Box::<TestPattern>::default());
+
+                rfd::MessageDialog::new()
+                    .set_title("Loading the camera image failed!")
+                    .set_description(&e.to_string())
+                    .show();
+            }
+        }
+    }
+
+    // ------------ BOOT ROMS ---------------
+
+    /// Hands the boot ROM at `path` to the emulator, shows a dialog if it can't be used.
+    fn load_boot_rom(&mut self, path: &Path) {
+        let result = fs::read(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|boot_rom| {
+                let cgb = boot_rom.len() > 0x100;
+                self.emulator.set_boot_rom(boot_rom)?;
+                Ok(cgb)
+            });
+
+        match result {
+            Ok(false) => self.boot_roms.0 = Some(path.to_path_buf()),
+            Ok(true) => self.boot_roms.1 = Some(path.to_path_buf()),
+            Err(e) => {
+                rfd::MessageDialog::new()
+                    .set_title("Loading the boot ROM failed!")
+                    .set_description(&e.to_string())
+                    .show();
+            }
+        }
+    }
+
+    // ------------ DISASSEMBLY ---------------
+
+    fn import_symbols(&mut self, path: &Path) {
+        let result = fs::read_to_string(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|symbols| Ok(symbols.parse::<Symbols>()?));
+
+        match result {
+            Ok(symbols) => self.symbols = symbols,
+            Err(e) => {
+                rfd::MessageDialog::new()
+                    .set_title("Importing the symbols failed!")
+                    .set_description(&e.to_string())
+                    .show();
+            }
+        }
+    }
+
+    /// Writes the listing to `path` and all labels to a `.sym` file next to it.
+    fn disassemble_rom(&self, path: &Path) {
+        let disassembly = Disassembly::new(&self.emulator.rom, &self.symbols);
+        let result = fs::write(path, disassembly.to_string()).and_then(|_| {
+            fs::write(
+                path.with_extension("sym"),
+                disassembly.symbols().to_string(),
+            )
+        });
+
+        if let Err(e) = result {
+            rfd::MessageDialog::new()
+                .set_title("Writing the disassembly failed!")
+                .set_description(&e.to_string())
+                .show();
+        }
+    }
+
+    // ------------ PRINTER ---------------
+
+    /// Writes every finished sheet of the printer to `game-print1.png`, `game-print2.png`, ...
+    fn store_prints(&self) {
+        let Some(prints) = &self.prints else {
+            return;
+        };
+
+        for page in prints.try_iter() {
+            let rom_path = self.rom_path.as_deref().unwrap_or(Path::new("kevboy"));
+            let stem = rom_path.file_stem().unwrap_or_default().to_string_lossy();
+
+            let path = (1..)
+                .map(|n| rom_path.with_file_name(format!("{stem}-print{n}.png")))
+                .find(|path| !path.exists())
+                .unwrap();
+
+            if let Err(e) = page.save(&path) {
+                rfd::MessageDialog::new()
+                    .set_title("Saving the printed picture failed!")
+                    .set_description(&e.to_string())
+                    .show();
+            }
+        }
+    }
+
+    // ------------ BATTERY SAVES ---------------
+
+    fn store_battery_save(&mut self) {
+        if let Err(e) = self.emulator.save_sram() {
+            rfd::MessageDialog::new()
+                .set_title("Writing the save file failed!")
+                .set_description(&e.to_string())
+                .show();
+        }
+    }
+
+    // ------------ SAVE STATES ---------------
+
+    /// Slot files live next to the ROM: `game.gb` -> `game.ss1`.
+    fn state_slot_path(&self, slot: usize) -> Option<PathBuf> {
+        self.rom_path
+            .as_ref()
+            .map(|p| p.with_extension(format!("ss{slot}")))
+    }
+
+    fn save_state_slot(&mut self, slot: usize) {
+        if let Some(path) = self.state_slot_path(slot) {
+            self.save_state_to(&path);
+        }
+    }
+
+    fn load_state_slot(&mut self, slot: usize) {
+        if let Some(path) = self.state_slot_path(slot).filter(|p| p.exists()) {
+            self.load_state_from(&path);
+        }
+    }
+
+    fn save_state_to(&self, path: &Path) {
+        if let Err(e) = fs::write(path, self.emulator.save_state()) {
+            rfd::MessageDialog::new()
+                .set_title("Save state failed!")
+                .set_description(&e.to_string())
+                .show();
+        }
+    }
+
+    fn load_state_from(&mut self, path: &Path) {
+        let result = fs::read(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|state| Ok(self.emulator.load_state(&state)?));
+
+        // History belongs to the timeline that was just replaced
+        self.rewind.clear();
+
+        if let Err(e) = result {
+            rfd::MessageDialog::new()
+                .set_title("Load state failed!")
+                .set_description(&e.to_string())
+                .show();
+        }
     }
 
     // TODO: rewrite as shader, slow on web
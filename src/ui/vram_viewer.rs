@@ -0,0 +1,365 @@
+use egui::{
+    epaint::{Color32, ColorImage},
+    pos2, vec2, ComboBox, Grid, Image, Rect, ScrollArea, Sense, Stroke, TextureHandle,
+    TextureOptions, Ui,
+};
+
+use crate::{
+    emulator::Emulator,
+    ppu::{
+        color_palette::{rgb555_to_color, Palette, ScreenColor},
+        sprite::get_all_sprites,
+        tile_attributes::{BgOamPrio, TileAttribute},
+    },
+};
+
+#[derive(PartialEq)]
+enum Tab {
+    BgMap,
+    Tiles,
+    Oam,
+    Palettes,
+}
+
+/// Shows what is in VRAM, OAM and CRAM: the BG map, all tiles, the sprites and the CGB palettes.
+pub struct VramViewer {
+    tab: Tab,
+    /// Tile map shown in the BG map tab, $9800 or $9C00
+    tile_map: u16,
+    /// Draw the screen and the window over the tile map
+    overlays: bool,
+    /// Palette the tile data is colored with, see `PPU::dump_tiles`
+    tile_palette: Palette,
+
+    bg_map: Option<TextureHandle>,
+    tiles: Option<TextureHandle>,
+    /// All 40 sprites next to each other, 8x16 pixels each
+    sprites: Option<TextureHandle>,
+}
+
+impl VramViewer {
+    pub fn new() -> Self {
+        Self {
+            tab: Tab::BgMap,
+            tile_map: 0x9800,
+            overlays: true,
+            tile_palette: Palette::BGP(0),
+
+            bg_map: None,
+            tiles: None,
+            sprites: None,
+        }
+    }
+
+    /// `shades` are the colors of the DMG palette, from white to black.
+    pub fn show(&mut self, ui: &mut Ui, emulator: &mut Emulator, shades: [Color32; 4]) {
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.tab, Tab::BgMap, "BG map");
+            ui.selectable_value(&mut self.tab, Tab::Tiles, "Tiles");
+            ui.selectable_value(&mut self.tab, Tab::Oam, "OAM");
+            ui.selectable_value(&mut self.tab, Tab::Palettes, "Palettes");
+        });
+        ui.separator();
+
+        match self.tab {
+            Tab::BgMap => self.show_bg_map(ui, emulator, shades),
+            Tab::Tiles => self.show_tiles(ui, emulator, shades),
+            Tab::Oam => self.show_oam(ui, emulator, shades),
+            Tab::Palettes => show_palettes(ui, emulator),
+        }
+    }
+
+    fn show_bg_map(&mut self, ui: &mut Ui, emulator: &mut Emulator, shades: [Color32; 4]) {
+        let bus = &mut emulator.bus;
+        let regs = bus.ppu.regs();
+        let (scx, scy, wx, wy) = (regs.scx, regs.scy, regs.wx, regs.wy);
+        let (bg_map, window_map) = (regs.bg_tile_map(), regs.window_tile_map());
+        let window = regs.is_window_enabled() && regs.is_window_visible();
+        let unsigned_addressing = regs.lcdc & 0x10 != 0;
+
+        ui.horizontal(|ui| {
+            for map in [0x9800, 0x9C00] {
+                let layers: Vec<&str> = [(bg_map, "BG"), (window_map, "window")]
+                    .into_iter()
+                    .filter(|(layer_map, _)| *layer_map == map)
+                    .map(|(_, layer)| layer)
+                    .collect();
+
+                let text = if layers.is_empty() {
+                    format!("${map:04X}")
+                } else {
+                    format!("${map:04X} ({})", layers.join(", "))
+                };
+                ui.selectable_value(&mut self.tile_map, map, text);
+            }
+
+            ui.separator();
+            ui.checkbox(&mut self.overlays, "Show screen and window");
+        });
+
+        bus.ppu.dump_tile_map(&bus.vram, self.tile_map);
+
+        let image = color_image([256, 256], &bus.ppu.raw_frame, shades);
+        let texture = upload(ui, &mut self.bg_map, "bg_map", image);
+
+        let side = ui.available_width().min(ui.available_height()).max(256.0);
+        let response = ui.add(Image::new(texture, vec2(side, side)).sense(Sense::hover()));
+        let scale = side / 256.0;
+
+        if self.overlays {
+            let painter = ui.painter_at(response.rect);
+
+            // Both layers wrap around at the edges of the map
+            let outline = |x: f32, y: f32, width: f32, height: f32, color: Color32| {
+                for (dx, dy) in [(0.0, 0.0), (-256.0, 0.0), (0.0, -256.0), (-256.0, -256.0)] {
+                    let min = response.rect.min + vec2(x + dx, y + dy) * scale;
+                    let rect = Rect::from_min_size(min, vec2(width, height) * scale);
+                    painter.rect_stroke(rect, 0.0, Stroke::new(2.0, color));
+                }
+            };
+
+            let (scx, scy) = (scx as f32, scy as f32);
+            // The window starts at WX - 7 on screen and always shows its map from the top left
+            let (window_x, window_y) = (wx as f32 - 7.0, wy as f32);
+            let window_size = (160.0 - window_x, 144.0 - window_y);
+
+            if self.tile_map == bg_map {
+                outline(scx, scy, 160.0, 144.0, Color32::RED);
+
+                if window {
+                    let (x, y) = (scx + window_x.max(0.0), scy + window_y);
+                    outline(
+                        x,
+                        y,
+                        window_size.0.min(160.0),
+                        window_size.1,
+                        Color32::LIGHT_BLUE,
+                    );
+                }
+            }
+
+            if self.tile_map == window_map && window {
+                let (width, height) = (window_size.0.min(160.0), window_size.1);
+                outline(-window_x.min(0.0), 0.0, width, height, Color32::LIGHT_BLUE);
+            }
+        }
+
+        if let Some(position) = response.hover_pos() {
+            let offset = (position - response.rect.min) / (8.0 * scale);
+            let (column, row) = (offset.x as u16, offset.y as u16);
+
+            if column < 32 && row < 32 {
+                let address = self.tile_map + row * 32 + column;
+                let index = bus.vram[0][address as usize - 0x8000];
+
+                // With signed addressing, tile 0 is at $9000 and 128-255 are at $8800-$8FFF
+                let data = if unsigned_addressing {
+                    0x8000 + index as u16 * 16
+                } else {
+                    0x9000u16.wrapping_add_signed(index as i8 as i16 * 16)
+                };
+
+                let mut text = format!("Map ${address:04X}\nTile ${index:02X} at ${data:04X}");
+                if bus.ppu.cgb {
+                    let attributes = TileAttribute::from(bus.vram[1][address as usize - 0x8000]);
+                    text += &format!(
+                        "\nBank {}, palette {}",
+                        attributes.vram_bank, attributes.bgp
+                    );
+                    if attributes.h_flip {
+                        text += ", X flip";
+                    }
+                    if attributes.v_flip {
+                        text += ", Y flip";
+                    }
+                    if attributes.bg_to_oam == BgOamPrio::BGPrio {
+                        text += ", over sprites";
+                    }
+                }
+
+                response.on_hover_text(text);
+            }
+        }
+    }
+
+    fn show_tiles(&mut self, ui: &mut Ui, emulator: &Emulator, shades: [Color32; 4]) {
+        let ppu = &emulator.bus.ppu;
+        let cgb = ppu.cgb;
+        let (bg_palettes, obj_palettes) = if cgb { (8, 8) } else { (1, 2) };
+
+        // The palette may not exist anymore after switching to a DMG game
+        self.tile_palette = match self.tile_palette {
+            Palette::BGP(number) if number >= bg_palettes => Palette::BGP(0),
+            Palette::OBP(number) if number >= obj_palettes => Palette::OBP(0),
+            palette => palette,
+        };
+
+        let name = |palette: Palette| match palette {
+            Palette::BGP(number) if cgb => format!("BG {number}"),
+            Palette::OBP(number) if cgb => format!("OBJ {number}"),
+            Palette::BGP(_) => "BGP".to_string(),
+            Palette::OBP(number) => format!("OBP{number}"),
+        };
+
+        ComboBox::from_label("Palette")
+            .selected_text(name(self.tile_palette))
+            .show_ui(ui, |ui| {
+                let palettes = (0..bg_palettes)
+                    .map(Palette::BGP)
+                    .chain((0..obj_palettes).map(Palette::OBP));
+
+                for palette in palettes {
+                    ui.selectable_value(&mut self.tile_palette, palette, name(palette));
+                }
+            });
+
+        let pixels = ppu.dump_tiles(&emulator.bus.vram, self.tile_palette);
+        let image = color_image([256, 192], &pixels, shades);
+        let texture = upload(ui, &mut self.tiles, "tiles", image);
+
+        let response = ui.add(Image::new(texture, vec2(512.0, 384.0)).sense(Sense::hover()));
+        if let Some(position) = response.hover_pos() {
+            let offset = (position - response.rect.min) / 16.0;
+            let (column, row) = (offset.x as usize, offset.y as usize);
+
+            if column < 32 && row < 24 {
+                let (bank, tile) = (column / 16, row * 16 + column % 16);
+                response.on_hover_text(format!(
+                    "Tile ${tile:03X} in bank {bank} at ${:04X}",
+                    0x8000 + tile * 16
+                ));
+            }
+        }
+    }
+
+    fn show_oam(&mut self, ui: &mut Ui, emulator: &Emulator, shades: [Color32; 4]) {
+        let bus = &emulator.bus;
+        let cgb = bus.ppu.cgb;
+        let sprites = get_all_sprites(&bus.oam);
+
+        let mut atlas = vec![ScreenColor::White(0); 40 * 8 * 16];
+        let mut height = 8;
+        for (i, sprite) in sprites.iter().enumerate() {
+            let pixels = bus.ppu.dump_sprite(&bus.vram, sprite);
+            height = pixels.len() / 8;
+
+            for (y, row) in pixels.chunks(8).enumerate() {
+                let start = y * 40 * 8 + i * 8;
+                atlas[start..start + 8].copy_from_slice(row);
+            }
+        }
+
+        let image = color_image([40 * 8, 16], &atlas, shades);
+        let texture = upload(ui, &mut self.sprites, "sprites", image);
+
+        ScrollArea::vertical().show(ui, |ui| {
+            Grid::new("oam")
+                .num_columns(6)
+                .striped(true)
+                .show(ui, |ui| {
+                    for header in ["#", "Sprite", "X", "Y", "Tile", "Attributes"] {
+                        ui.strong(header);
+                    }
+                    ui.end_row();
+
+                    for (i, sprite) in sprites.iter().enumerate() {
+                        let uv = Rect::from_min_max(
+                            pos2(i as f32 / 40.0, 0.0),
+                            pos2((i + 1) as f32 / 40.0, height as f32 / 16.0),
+                        );
+
+                        ui.monospace(format!("{i:02}"));
+                        ui.add(Image::new(texture, vec2(16.0, height as f32 * 2.0)).uv(uv));
+                        ui.monospace(format!("{:3}", sprite.x_pos));
+                        ui.monospace(format!("{:3}", sprite.y_pos));
+                        ui.monospace(format!("${:02X}", sprite.tile_index));
+
+                        let mut attributes = Vec::new();
+                        if cgb {
+                            attributes.push(format!("OBJ {}", sprite.get_cgb_obp_num()));
+                            attributes.push(format!("bank {}", sprite.vbk()));
+                        } else {
+                            attributes.push(format!("OBP{}", sprite.get_dmg_obp_num()));
+                        }
+                        if sprite.is_x_flipped() {
+                            attributes.push("X flip".to_string());
+                        }
+                        if sprite.is_y_flipped() {
+                            attributes.push("Y flip".to_string());
+                        }
+                        if !sprite.is_obj_prio() {
+                            attributes.push("behind BG".to_string());
+                        }
+                        ui.label(attributes.join(", "));
+                        ui.end_row();
+                    }
+                });
+        });
+    }
+}
+
+/// CRAM as swatches, one row per palette, hovering shows the RGB555 value.
+fn show_palettes(ui: &mut Ui, emulator: &Emulator) {
+    let ppu = &emulator.bus.ppu;
+    if !ppu.cgb && !ppu.is_dmg_compat() {
+        ui.label("Palettes in CRAM are only used in CGB mode.");
+    }
+
+    ui.horizontal_top(|ui| {
+        for (name, cram) in [("BG", ppu.bg_cram()), ("OBJ", ppu.obj_cram())] {
+            Grid::new(name).num_columns(5).show(ui, |ui| {
+                for (number, palette) in cram.chunks(8).enumerate() {
+                    ui.monospace(format!("{name} {number}"));
+
+                    for color in palette.chunks(2) {
+                        let rgb = u16::from_le_bytes([color[0], color[1]]);
+                        let (rect, response) =
+                            ui.allocate_exact_size(vec2(24.0, 16.0), Sense::hover());
+                        ui.painter().rect_filled(rect, 2.0, rgb555_to_color(rgb));
+
+                        response.on_hover_text(format!(
+                            "${:04X}\nR {} G {} B {}",
+                            rgb & 0x7FFF,
+                            rgb & 0x1F,
+                            (rgb >> 5) & 0x1F,
+                            (rgb >> 10) & 0x1F
+                        ));
+                    }
+                    ui.end_row();
+                }
+            });
+            ui.add_space(10.0);
+        }
+    });
+}
+
+fn color_image(size: [usize; 2], pixels: &[ScreenColor], shades: [Color32; 4]) -> ColorImage {
+    let pixels = pixels
+        .iter()
+        .map(|c| match *c {
+            ScreenColor::White(_) => shades[0],
+            ScreenColor::LightGray(_) => shades[1],
+            ScreenColor::Gray(_) => shades[2],
+            ScreenColor::Black(_) => shades[3],
+            ScreenColor::FullColor(c, _) => c,
+        })
+        .collect();
+
+    ColorImage { size, pixels }
+}
+
+/// Replaces the image of the texture in `slot`, which is created the first time.
+fn upload<'a>(
+    ui: &Ui,
+    slot: &'a mut Option<TextureHandle>,
+    name: &str,
+    image: ColorImage,
+) -> &'a TextureHandle {
+    match slot {
+        Some(texture) => {
+            texture.set(image, TextureOptions::NEAREST);
+            texture
+        }
+        None => slot.insert(ui.ctx().load_texture(name, image, TextureOptions::NEAREST)),
+    }
+}